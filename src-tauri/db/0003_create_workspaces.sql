@@ -0,0 +1,14 @@
+CREATE TABLE IF NOT EXISTS workspaces(
+  id INTEGER PRIMARY KEY,
+  name TEXT NOT NULL,
+  icon TEXT DEFAULT NULL,
+  sort INTEGER NOT NULL,
+  active BOOL NOT NULL DEFAULT FALSE
+);
+
+INSERT INTO workspaces (name, sort, active) VALUES ('Default', 1, TRUE);
+
+ALTER TABLE timelines
+  ADD COLUMN workspace_id INTEGER DEFAULT NULL REFERENCES workspaces(id) ON DELETE CASCADE;
+
+UPDATE timelines SET workspace_id = (SELECT id FROM workspaces WHERE active = TRUE);
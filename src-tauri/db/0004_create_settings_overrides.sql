@@ -0,0 +1,14 @@
+CREATE TABLE IF NOT EXISTS account_settings(
+  account_id INTEGER PRIMARY KEY,
+  confirm_reblog BOOL DEFAULT NULL,
+  default_visibility TEXT DEFAULT NULL,
+  default_language TEXT DEFAULT NULL,
+  FOREIGN KEY (account_id) REFERENCES accounts(id) ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS timeline_settings(
+  timeline_id INTEGER PRIMARY KEY,
+  font_size INTEGER DEFAULT NULL,
+  media_display TEXT DEFAULT NULL,
+  FOREIGN KEY (timeline_id) REFERENCES timelines(id) ON DELETE CASCADE
+);
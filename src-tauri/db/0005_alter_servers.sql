@@ -0,0 +1,2 @@
+ALTER TABLE servers
+  ADD COLUMN ca_certificate TEXT DEFAULT NULL;
@@ -0,0 +1,10 @@
+CREATE TABLE IF NOT EXISTS favicons(
+  server_id INTEGER PRIMARY KEY,
+  url TEXT NOT NULL,
+  content_type TEXT NOT NULL,
+  data BLOB NOT NULL,
+  etag TEXT DEFAULT NULL,
+  last_modified TEXT DEFAULT NULL,
+  fetched_at INTEGER NOT NULL,
+  FOREIGN KEY (server_id) REFERENCES servers(id) ON DELETE CASCADE
+);
@@ -0,0 +1,15 @@
+CREATE TABLE IF NOT EXISTS server_metadata(
+  server_id INTEGER PRIMARY KEY,
+  instance TEXT NOT NULL,
+  rules TEXT NOT NULL DEFAULT '[]',
+  emojis TEXT NOT NULL DEFAULT '[]',
+  max_characters INTEGER DEFAULT NULL,
+  max_media_attachments INTEGER DEFAULT NULL,
+  characters_reserved_per_url INTEGER DEFAULT NULL,
+  poll_max_options INTEGER DEFAULT NULL,
+  poll_max_characters_per_option INTEGER DEFAULT NULL,
+  poll_min_expiration INTEGER DEFAULT NULL,
+  poll_max_expiration INTEGER DEFAULT NULL,
+  fetched_at INTEGER NOT NULL,
+  FOREIGN KEY (server_id) REFERENCES servers(id) ON DELETE CASCADE
+);
@@ -0,0 +1,5 @@
+ALTER TABLE servers
+  ADD COLUMN software TEXT DEFAULT NULL;
+
+ALTER TABLE servers
+  ADD COLUMN software_version TEXT DEFAULT NULL;
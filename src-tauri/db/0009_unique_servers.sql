@@ -0,0 +1,20 @@
+-- Each account has its own server row, so only servers without an account have to be unique.
+-- The timelines of duplicated anonymous servers are moved to the oldest one.
+-- base_url of the existing rows is normalized by database::normalize_servers after migrations.
+CREATE TEMP TABLE duplicate_servers AS
+  SELECT s.id AS duplicate_id,
+    (SELECT k.id FROM servers k WHERE k.base_url = s.base_url AND k.account_id IS NULL ORDER BY k.id LIMIT 1) AS kept_id
+  FROM servers s
+  WHERE s.account_id IS NULL;
+
+DELETE FROM duplicate_servers WHERE duplicate_id = kept_id;
+
+UPDATE timelines
+  SET server_id = (SELECT kept_id FROM duplicate_servers WHERE duplicate_id = timelines.server_id)
+  WHERE server_id IN (SELECT duplicate_id FROM duplicate_servers);
+
+DELETE FROM servers WHERE id IN (SELECT duplicate_id FROM duplicate_servers);
+
+DROP TABLE duplicate_servers;
+
+CREATE UNIQUE INDEX IF NOT EXISTS servers_base_url ON servers(base_url) WHERE account_id IS NULL;
@@ -0,0 +1,8 @@
+ALTER TABLE timelines
+  ADD COLUMN remote_account_id TEXT DEFAULT NULL;
+
+ALTER TABLE timelines
+  ADD COLUMN remote_acct TEXT DEFAULT NULL;
+
+ALTER TABLE timelines
+  ADD COLUMN show_pinned BOOL NOT NULL DEFAULT TRUE;
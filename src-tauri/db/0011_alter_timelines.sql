@@ -0,0 +1,2 @@
+ALTER TABLE timelines
+  ADD COLUMN notification_filter TEXT DEFAULT NULL;
@@ -0,0 +1,267 @@
+use serde::Serialize;
+use sqlx::SqlitePool;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{database, error::Error};
+
+/// How many backups are kept in the backup directory.
+pub const MAX_BACKUPS: usize = 5;
+
+const BACKUP_PREFIX: &str = "fedistar-";
+const BACKUP_EXTENSION: &str = "db";
+
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum DatabaseStatus {
+    Ok,
+    Corrupted { message: String },
+    MigrationFailed { message: String },
+}
+
+pub struct Backups {
+    database_path: PathBuf,
+    backup_dir: PathBuf,
+}
+
+impl Backups {
+    pub fn new(database_path: PathBuf, backup_dir: PathBuf) -> Self {
+        Self {
+            database_path,
+            backup_dir,
+        }
+    }
+
+    /// Returns backup file names, the newest first.
+    pub fn list(&self) -> Vec<String> {
+        let Ok(entries) = fs::read_dir(&self.backup_dir) else {
+            return Vec::new();
+        };
+        let mut names: Vec<String> = entries
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| entry.file_name().into_string().ok())
+            .filter(|name| is_backup_name(name))
+            .collect();
+        names.sort_unstable_by(|a, b| b.cmp(a));
+        names
+    }
+
+    pub async fn create(&self, pool: &SqlitePool) -> Result<PathBuf, Error> {
+        fs::create_dir_all(&self.backup_dir)?;
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|e| Error::internal(e.to_string()))?
+            .as_secs();
+        let path = self.backup_dir.join(format!(
+            "{}{:012}.{}",
+            BACKUP_PREFIX, timestamp, BACKUP_EXTENSION
+        ));
+        if path.exists() {
+            return Ok(path);
+        }
+        database::backup_database(pool, &path).await?;
+        Ok(path)
+    }
+
+    pub fn prune(&self, keep: usize) {
+        for name in self.list().into_iter().skip(keep) {
+            let path = self.backup_dir.join(&name);
+            match fs::remove_file(&path) {
+                Ok(()) => tracing::info!("Removed old backup {}", name),
+                Err(e) => tracing::warn!("Failed to remove old backup {}: {}", name, e),
+            }
+        }
+    }
+
+    /// Restores the database file from the given backup, or the newest one when no name is given.
+    /// The backup is checked before the database is touched. When the database file itself is too broken to be written
+    /// through SQLite, the pool is closed and the file is replaced, so the caller has to restart in that case.
+    pub async fn restore(&self, pool: &SqlitePool, name: Option<&str>) -> Result<String, Error> {
+        let backups = self.list();
+        let name = match name {
+            Some(name) => backups
+                .into_iter()
+                .find(|b| b == name)
+                .ok_or_else(|| Error::not_found(format!("Backup {} does not exist", name)))?,
+            None => backups
+                .into_iter()
+                .next()
+                .ok_or_else(|| Error::not_found("There is no backup to restore"))?,
+        };
+        let backup_path = self.backup_dir.join(&name);
+        database::check_database_file(&backup_path).await?;
+
+        match database::restore_database(&self.database_path, &backup_path).await {
+            Ok(()) => {}
+            Err(e)
+                if database::check_database_file(&self.database_path)
+                    .await
+                    .is_ok() =>
+            {
+                return Err(e)
+            }
+            Err(e) => {
+                tracing::warn!(
+                    "Failed to restore {} online, replacing the file: {}",
+                    name,
+                    e
+                );
+                pool.close().await;
+                self.replace_database(&backup_path)?;
+            }
+        }
+        tracing::info!("The database is restored from {}", name);
+
+        Ok(name)
+    }
+
+    fn replace_database(&self, backup_path: &Path) -> Result<(), Error> {
+        fs::copy(backup_path, &self.database_path)?;
+        for suffix in ["-wal", "-shm"] {
+            let mut sidecar = self.database_path.clone().into_os_string();
+            sidecar.push(suffix);
+            let sidecar = PathBuf::from(sidecar);
+            if sidecar.exists() {
+                fs::remove_file(&sidecar)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn is_backup_name(name: &str) -> bool {
+    let path = Path::new(name);
+    name.starts_with(BACKUP_PREFIX)
+        && path.extension().and_then(|e| e.to_str()) == Some(BACKUP_EXTENSION)
+}
+
+/// Opens the database, checks its integrity, takes a backup and migrates it.
+/// Failures are reported as a status instead of an error, so that the app can start and offer a recovery.
+pub(crate) async fn open_database(
+    database_url: &str,
+    backups: &Backups,
+) -> Result<(SqlitePool, DatabaseStatus), Error> {
+    let database_exists = backups.database_path.exists();
+
+    let pool = match database::create_sqlite_pool(database_url).await {
+        Ok(pool) => pool,
+        Err(e) => {
+            tracing::error!("Failed to open the database: {}", e);
+            let pool = database::create_memory_pool().await?;
+            database::migrate_database(&pool).await?;
+            return Ok((
+                pool,
+                DatabaseStatus::Corrupted {
+                    message: e.to_string(),
+                },
+            ));
+        }
+    };
+
+    if database_exists {
+        if let Err(e) = database::integrity_check(&pool).await {
+            tracing::error!("The database integrity check failed: {}", e);
+            return Ok((
+                pool,
+                DatabaseStatus::Corrupted {
+                    message: e.to_string(),
+                },
+            ));
+        }
+
+        match backups.create(&pool).await {
+            Ok(path) => tracing::info!("The database is backed up to {:?}", path),
+            Err(e) => tracing::error!("Failed to back up the database: {}", e),
+        }
+        backups.prune(MAX_BACKUPS);
+    }
+
+    if let Err(e) = database::migrate_database(&pool).await {
+        tracing::error!("Failed to migrate the database: {}", e);
+        return Ok((
+            pool,
+            DatabaseStatus::MigrationFailed {
+                message: e.to_string(),
+            },
+        ));
+    }
+
+    Ok((pool, DatabaseStatus::Ok))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_backup_name() {
+        assert!(is_backup_name("fedistar-001760857500.db"));
+        assert!(!is_backup_name("fedistar.db"));
+        assert!(!is_backup_name("fedistar-001760857500.db-wal"));
+        assert!(!is_backup_name("settings.json"));
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("fedistar-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    async fn count_workspaces(pool: &SqlitePool) -> i64 {
+        sqlx::query_scalar("SELECT COUNT(*) FROM workspaces")
+            .fetch_one(pool)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_restore_while_the_pool_is_open() {
+        let dir = temp_dir("restore");
+        let database_path = dir.join("fedistar.db");
+        let backups = Backups::new(database_path.clone(), dir.join("backups"));
+        let pool = database::create_sqlite_pool(&format!("sqlite://{}", database_path.display()))
+            .await
+            .unwrap();
+        database::migrate_database(&pool).await.unwrap();
+        database::add_workspace(&pool, "Kept", None).await.unwrap();
+        backups.create(&pool).await.unwrap();
+        database::add_workspace(&pool, "Dropped", None)
+            .await
+            .unwrap();
+        let before = count_workspaces(&pool).await;
+
+        backups.restore(&pool, None).await.unwrap();
+
+        assert!(!pool.is_closed());
+        assert_eq!(count_workspaces(&pool).await, before - 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_restore_rejects_a_broken_backup() {
+        let dir = temp_dir("broken-backup");
+        let database_path = dir.join("fedistar.db");
+        let backups = Backups::new(database_path.clone(), dir.join("backups"));
+        let pool = database::create_sqlite_pool(&format!("sqlite://{}", database_path.display()))
+            .await
+            .unwrap();
+        database::migrate_database(&pool).await.unwrap();
+        let before = count_workspaces(&pool).await;
+        fs::create_dir_all(dir.join("backups")).unwrap();
+        fs::write(
+            dir.join("backups").join("fedistar-000000000001.db"),
+            b"not a database",
+        )
+        .unwrap();
+
+        assert!(backups.restore(&pool, None).await.is_err());
+
+        assert!(!pool.is_closed());
+        assert_eq!(count_workspaces(&pool).await, before);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,181 @@
+use serde::Serialize;
+
+use crate::entities;
+
+/// Features which the server supports, so the frontend can hide columns and actions which would fail.
+#[derive(Debug, Serialize, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Capabilities {
+    pub streaming: bool,
+    pub markers: bool,
+    pub quotes: bool,
+    pub reactions: bool,
+    pub edit: bool,
+    pub scheduled_posts: bool,
+    pub bookmarks: bool,
+    pub lists: bool,
+    pub translation: bool,
+}
+
+type Version = (u64, u64, u64);
+
+/// Parses the leading `major.minor.patch` of a version such as `4.3.0+glitch`.
+fn parse_version(version: &str) -> Option<Version> {
+    let end = version
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(version.len());
+    let mut parts = version[..end].split('.').map(|p| p.parse::<u64>().ok());
+    let major = parts.next()??;
+    let minor = parts.next().flatten().unwrap_or(0);
+    let patch = parts.next().flatten().unwrap_or(0);
+    Some((major, minor, patch))
+}
+
+pub(crate) fn resolve(server: &entities::Server) -> Capabilities {
+    let version = server.software_version.as_deref().and_then(parse_version);
+    // Unknown versions are treated as the latest, because old servers are rare.
+    let since = |v: Version| version.map_or(true, |current| current >= v);
+    let build = server
+        .software_version
+        .as_deref()
+        .unwrap_or("")
+        .to_lowercase();
+    let software = server.software.as_deref().unwrap_or(server.sns.as_str());
+
+    match software {
+        // Forks such as glitch-soc and fedibird report mastodon with their name in the version.
+        "mastodon" | "hometown" | "fedibird" => {
+            let fedibird = software == "fedibird" || build.contains("fedibird");
+            Capabilities {
+                streaming: true,
+                markers: since((3, 0, 0)),
+                quotes: fedibird || since((4, 5, 0)),
+                reactions: fedibird || build.contains("glitch"),
+                edit: since((3, 5, 0)),
+                scheduled_posts: since((2, 7, 0)),
+                bookmarks: since((3, 1, 0)),
+                lists: true,
+                translation: since((4, 0, 0)),
+            }
+        }
+        "pleroma" => Capabilities {
+            streaming: true,
+            markers: true,
+            quotes: since((2, 6, 0)),
+            reactions: true,
+            edit: since((2, 5, 0)),
+            scheduled_posts: true,
+            bookmarks: true,
+            lists: true,
+            translation: false,
+        },
+        "akkoma" => Capabilities {
+            streaming: true,
+            markers: true,
+            quotes: true,
+            reactions: true,
+            edit: true,
+            scheduled_posts: true,
+            bookmarks: true,
+            lists: true,
+            translation: true,
+        },
+        "gotosocial" => Capabilities {
+            streaming: true,
+            markers: true,
+            quotes: false,
+            reactions: false,
+            edit: since((0, 18, 0)),
+            scheduled_posts: false,
+            bookmarks: true,
+            lists: true,
+            translation: false,
+        },
+        "friendica" => Capabilities {
+            streaming: false,
+            markers: true,
+            quotes: false,
+            reactions: false,
+            edit: true,
+            scheduled_posts: true,
+            bookmarks: true,
+            lists: true,
+            translation: false,
+        },
+        "misskey" | "firefish" | "sharkey" | "iceshrimp" | "catodon" | "calckey" => Capabilities {
+            streaming: true,
+            markers: false,
+            quotes: true,
+            reactions: true,
+            edit: software != "misskey",
+            scheduled_posts: false,
+            bookmarks: true,
+            lists: true,
+            translation: software == "misskey" || software == "sharkey",
+        },
+        // Other software which megalodon talks to through the Mastodon API.
+        _ => Capabilities {
+            streaming: true,
+            markers: false,
+            quotes: false,
+            reactions: false,
+            edit: false,
+            scheduled_posts: false,
+            bookmarks: true,
+            lists: true,
+            translation: false,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn server(sns: &str, software: Option<&str>, version: Option<&str>) -> entities::Server {
+        let mut server = entities::Server::new(
+            1,
+            String::from("example.com"),
+            String::from("https://example.com"),
+            sns.to_string(),
+            None,
+        );
+        server.software = software.map(|s| s.to_string());
+        server.software_version = version.map(|v| v.to_string());
+        server
+    }
+
+    #[test]
+    fn test_parse_version() {
+        assert_eq!(parse_version("4.3.0+glitch"), Some((4, 3, 0)));
+        assert_eq!(parse_version("2024.11.0-beta.1"), Some((2024, 11, 0)));
+        assert_eq!(parse_version("0.18"), Some((0, 18, 0)));
+        assert_eq!(parse_version("unknown"), None);
+    }
+
+    #[test]
+    fn test_resolve_old_mastodon() {
+        let capabilities = resolve(&server("mastodon", Some("mastodon"), Some("3.0.1")));
+        assert!(capabilities.markers);
+        assert!(!capabilities.bookmarks);
+        assert!(!capabilities.edit);
+        assert!(!capabilities.translation);
+    }
+
+    #[test]
+    fn test_resolve_fedibird() {
+        let capabilities = resolve(&server(
+            "mastodon",
+            Some("mastodon"),
+            Some("3.4.1+fedibird"),
+        ));
+        assert!(capabilities.reactions);
+        assert!(capabilities.quotes);
+    }
+
+    #[test]
+    fn test_resolve_without_nodeinfo() {
+        let capabilities = resolve(&server("friendica", None, None));
+        assert!(!capabilities.streaming);
+        assert!(capabilities.scheduled_posts);
+    }
+}
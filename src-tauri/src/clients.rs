@@ -0,0 +1,211 @@
+use megalodon::Megalodon;
+use std::{
+    collections::HashMap,
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use crate::{entities, error::Error, gateway::Gateway, proxy, tls};
+
+pub const USER_AGENT: &str = concat!("fedistar/", env!("CARGO_PKG_VERSION"));
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+const POOL_IDLE_TIMEOUT: Duration = Duration::from_secs(90);
+
+pub type MegalodonClient = Arc<dyn Megalodon + Send + Sync>;
+
+/// Server id and account id, which is None for clients without authentication.
+pub(crate) type ClientKey = (i64, Option<i64>);
+
+/// HTTP clients which are built with the current proxy and certificates.
+#[derive(Clone)]
+pub struct HttpClient {
+    pub http: reqwest::Client,
+    /// Websockets are upgraded from HTTP/1.1 connections, and they are kept open beyond the request timeout.
+    pub websocket: reqwest::Client,
+}
+
+impl HttpClient {
+    /// Builds clients which trust the certificates in PEM for the origin, if any.
+    fn build(roots: Option<(&str, &str)>) -> Result<Self, Error> {
+        let builder = |http1_only| match roots {
+            Some((origin, pem)) => {
+                tls::scope_roots(proxy::client_builder()?, origin, pem, http1_only)
+            }
+            None => proxy::client_builder(),
+        };
+        let http = builder(false)?
+            .user_agent(USER_AGENT)
+            .connect_timeout(CONNECT_TIMEOUT)
+            .timeout(REQUEST_TIMEOUT)
+            .pool_idle_timeout(POOL_IDLE_TIMEOUT)
+            .build()?;
+        let websocket = builder(true)?
+            .user_agent(USER_AGENT)
+            .connect_timeout(CONNECT_TIMEOUT)
+            .http1_only()
+            .build()?;
+        Ok(Self { http, websocket })
+    }
+}
+
+/// The default HTTP clients, and the clients of servers with extra certificates keyed by their origin.
+/// Each server's certificates are trusted only by its own clients.
+#[derive(Clone)]
+pub struct HttpClients {
+    default: HttpClient,
+    servers: HashMap<String, HttpClient>,
+}
+
+impl HttpClients {
+    pub(crate) fn build() -> Result<Self, Error> {
+        let mut servers = HashMap::new();
+        for (origin, pem) in tls::roots() {
+            let client = HttpClient::build(Some((&origin, &pem)))?;
+            servers.insert(origin, client);
+        }
+        Ok(Self {
+            default: HttpClient::build(None)?,
+            servers,
+        })
+    }
+
+    /// Returns the clients for requests to the URL.
+    pub fn get(&self, url: &str) -> &HttpClient {
+        tls::origin(url)
+            .ok()
+            .and_then(|origin| self.servers.get(&origin))
+            .unwrap_or(&self.default)
+    }
+}
+
+/// Clients shared by commands and streamings.
+/// megalodon clients keep their HTTP connections, so they are created once for each server and account.
+/// They read the proxy and certificates when they are created, so `reset` must be called after changing them.
+pub struct Clients {
+    http: Arc<Mutex<HttpClients>>,
+    megalodon: Mutex<HashMap<ClientKey, MegalodonClient>>,
+    gateway: Arc<Gateway>,
+}
+
+impl Clients {
+    pub async fn new() -> Result<Self, Error> {
+        let http = Arc::new(Mutex::new(HttpClients::build()?));
+        let gateway = Gateway::start(http.clone()).await?;
+        Ok(Self {
+            http,
+            megalodon: Mutex::new(HashMap::new()),
+            gateway,
+        })
+    }
+
+    /// Returns the pooled HTTP client for requests which megalodon does not provide.
+    pub fn http(&self) -> reqwest::Client {
+        self.http
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .default
+            .http
+            .clone()
+    }
+
+    /// Returns the pooled HTTP client for requests to a server, which trusts its extra certificates.
+    pub fn http_for(&self, url: &str) -> reqwest::Client {
+        self.http
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(url)
+            .http
+            .clone()
+    }
+
+    pub fn megalodon(
+        &self,
+        server: &entities::Server,
+        account: Option<&entities::Account>,
+    ) -> Result<MegalodonClient, Error> {
+        let key = (server.id, account.map(|a| a.id));
+        let mut clients = self.megalodon.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(client) = clients.get(&key) {
+            return Ok(client.clone());
+        }
+
+        let sns = megalodon::SNS::from_str(server.sns.as_ref()).map_err(Error::unsupported_sns)?;
+        let client: MegalodonClient = Arc::from(self.generate(
+            sns,
+            &server.base_url,
+            account.map(|a| a.access_token.clone()),
+        )?);
+        clients.insert(key, client.clone());
+        Ok(client)
+    }
+
+    /// Builds an HTTP client which trusts the certificates for the server, before they are registered.
+    pub fn http_with_roots(&self, base_url: &str, pem: &str) -> Result<reqwest::Client, Error> {
+        let client = HttpClient::build(Some((&tls::origin(base_url)?, pem)))?;
+        Ok(client.http)
+    }
+
+    /// Creates a megalodon client which is not cached, such as for servers which are not registered yet.
+    /// Clients for servers behind the proxy or with extra certificates connect to the gateway,
+    /// which relays them with our HTTP clients.
+    pub fn generate(
+        &self,
+        sns: megalodon::SNS,
+        base_url: &str,
+        access_token: Option<String>,
+    ) -> Result<Box<dyn Megalodon + Send + Sync>, Error> {
+        let url = if is_relayed(base_url) {
+            self.gateway.url(base_url)
+        } else {
+            base_url.to_string()
+        };
+        let client = megalodon::generator(sns, url, access_token, Some(USER_AGENT.to_string()))?;
+        Ok(client)
+    }
+
+    /// Returns the URL on the server for URLs which megalodon built from the gateway.
+    pub fn upstream_url(&self, url: &str) -> String {
+        self.gateway.upstream_url(url)
+    }
+
+    /// Drops the clients of the server, which is called when the server or its account is changed.
+    pub fn remove_server(&self, server_id: i64) {
+        self.megalodon
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .retain(|(id, _), _| *id != server_id);
+    }
+
+    /// Builds the clients again with the current proxy and certificates.
+    pub fn reset(&self) -> Result<(), Error> {
+        let http = HttpClients::build()?;
+        *self.http.lock().unwrap_or_else(|e| e.into_inner()) = http;
+        self.megalodon
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clear();
+        self.gateway.retain(is_relayed);
+        Ok(())
+    }
+}
+
+/// Whether megalodon connects to the server through the gateway.
+fn is_relayed(base_url: &str) -> bool {
+    proxy::is_proxied(base_url) || tls::has_roots(base_url)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_user_agent_has_version() {
+        assert_eq!(
+            USER_AGENT,
+            format!("fedistar/{}", env!("CARGO_PKG_VERSION"))
+        );
+    }
+}
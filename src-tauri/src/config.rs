@@ -0,0 +1,1026 @@
+use base64::{engine::general_purpose, Engine};
+use ring::{
+    aead, pbkdf2,
+    rand::{SecureRandom, SystemRandom},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{from_str, to_string_pretty};
+use std::{collections::HashMap, fs, num::NonZeroU32, path::PathBuf};
+
+use crate::{
+    clients::Clients, database, entities, error::Error, favicon, nodeinfo, server_url, settings,
+    tls,
+};
+
+/// The format version of exported files. Bump this when the layout changes in an incompatible way.
+/// Version 2 refers to servers by their index, so that accounts on the same instance are kept apart.
+pub const CONFIG_VERSION: u32 = 2;
+
+const PBKDF2_ITERATIONS: NonZeroU32 = match NonZeroU32::new(100_000) {
+    Some(n) => n,
+    None => panic!("PBKDF2 iterations must not be zero"),
+};
+const SALT_LEN: usize = 16;
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct ExportedConfig {
+    pub version: u32,
+    pub servers: Vec<ExportedServer>,
+    pub workspaces: Vec<ExportedWorkspace>,
+    pub timelines: Vec<ExportedTimeline>,
+    pub settings: Option<settings::Settings>,
+    pub credentials: Option<EncryptedCredentials>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct ExportedServer {
+    pub domain: String,
+    pub base_url: String,
+    pub sns: String,
+    #[serde(default)]
+    pub ca_certificate: Option<String>,
+    /// Id of the signed-in account on the server, which finds the same account on import even without credentials.
+    #[serde(default)]
+    pub account_id: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct ExportedWorkspace {
+    pub name: String,
+    pub icon: Option<String>,
+    pub active: bool,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct ExportedTimeline {
+    /// Index in `ExportedConfig::servers`.
+    pub server: usize,
+    /// Index in `ExportedConfig::workspaces`.
+    pub workspace: Option<usize>,
+    pub kind: entities::timeline::Kind,
+    pub name: String,
+    pub list_id: Option<String>,
+    pub column_width: entities::timeline::ColumnWidth,
+    pub show_boosts: bool,
+    pub show_replies: bool,
+    #[serde(default)]
+    pub remote_account_id: Option<String>,
+    #[serde(default)]
+    pub remote_acct: Option<String>,
+    #[serde(default = "default_show_pinned")]
+    pub show_pinned: bool,
+    #[serde(default)]
+    pub notification_filter: Option<entities::timeline::NotificationFilter>,
+}
+
+fn default_show_pinned() -> bool {
+    true
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct EncryptedCredentials {
+    pub salt: String,
+    pub nonce: String,
+    pub ciphertext: String,
+}
+
+/// Secrets which are only exported encrypted with the password.
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+struct ExportedCredentials {
+    accounts: Vec<ExportedAccount>,
+    proxy_password: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+struct ExportedAccount {
+    /// Index in `ExportedConfig::servers`.
+    server: usize,
+    username: String,
+    account_id: String,
+    avatar: Option<String>,
+    client_id: Option<String>,
+    client_secret: String,
+    access_token: String,
+    refresh_token: Option<String>,
+    usual: bool,
+}
+
+#[derive(Deserialize)]
+struct ConfigVersion {
+    version: u32,
+}
+
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct ImportReport {
+    pub dry_run: bool,
+    pub new_servers: Vec<String>,
+    pub conflicts: Vec<ImportConflict>,
+    pub workspaces: usize,
+    pub timelines: usize,
+    pub accounts: usize,
+    pub settings: bool,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ImportConflict {
+    pub base_url: String,
+    pub reason: ConflictReason,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictReason {
+    /// The server is already registered, so it is reused instead of added.
+    ServerExists,
+    /// The server is already registered but detected as a different SNS.
+    SnsMismatch,
+    /// The server already has an account, so the imported credentials are skipped.
+    AccountExists,
+}
+
+/// The local server which an exported server is imported to.
+#[derive(Debug, Clone)]
+enum Target {
+    /// The server of the same account, or the anonymous server of the instance, in the database.
+    Existing(entities::Server),
+    /// The anonymous server which is added for the base_url.
+    Anonymous(String),
+    /// A server which is added for the imported account at this index of the credentials.
+    Account(usize),
+}
+
+/// What an import writes, which is decided by reading the database without writing.
+struct ImportPlan {
+    report: ImportReport,
+    config: ExportedConfig,
+    credentials: ExportedCredentials,
+    /// The target of each server in `config.servers`.
+    targets: Vec<Target>,
+    /// Servers which are registered, keyed by base_url.
+    registered: HashMap<String, entities::Server>,
+    existing_workspaces: Vec<entities::Workspace>,
+    existing_timelines: Vec<entities::Timeline>,
+}
+
+pub(crate) async fn export(
+    pool: &sqlx::SqlitePool,
+    settings_path: &PathBuf,
+    password: Option<&str>,
+) -> Result<ExportedConfig, Error> {
+    let servers = database::list_servers(pool).await?;
+    let workspaces = database::list_workspaces(pool).await?;
+    let timelines = database::list_all_timelines(pool).await?;
+    let mut settings = settings::read_settings(settings_path)?;
+    // The proxy password is a secret, so it is only exported in the encrypted credentials.
+    let proxy_password = settings.proxy.as_mut().and_then(|p| p.password.take());
+
+    let exported_timelines = timelines
+        .into_iter()
+        .filter_map(|t| {
+            let server = servers.iter().position(|(s, _)| s.id == t.server_id)?;
+            let workspace = t
+                .workspace_id
+                .and_then(|id| workspaces.iter().position(|w| w.id == id));
+            Some(ExportedTimeline {
+                server,
+                workspace,
+                kind: t.kind,
+                name: t.name,
+                list_id: t.list_id,
+                column_width: t.column_width,
+                show_boosts: t.show_boosts,
+                show_replies: t.show_replies,
+                remote_account_id: t.remote_account_id,
+                remote_acct: t.remote_acct,
+                show_pinned: t.show_pinned,
+                notification_filter: t.notification_filter,
+            })
+        })
+        .collect();
+
+    let credentials = match password {
+        Some(password) => {
+            let accounts: Vec<ExportedAccount> = servers
+                .iter()
+                .enumerate()
+                .filter_map(|(index, (_, a))| {
+                    a.as_ref().map(|a| ExportedAccount {
+                        server: index,
+                        username: a.username.clone(),
+                        account_id: a.account_id.clone(),
+                        avatar: a.avatar.clone(),
+                        client_id: a.client_id.clone(),
+                        client_secret: a.client_secret.clone(),
+                        access_token: a.access_token.clone(),
+                        refresh_token: a.refresh_token.clone(),
+                        usual: a.usual,
+                    })
+                })
+                .collect();
+            let plain = serde_json::to_string(&ExportedCredentials {
+                accounts,
+                proxy_password,
+            })?;
+            Some(encrypt(password, plain.as_bytes())?)
+        }
+        None => None,
+    };
+
+    Ok(ExportedConfig {
+        version: CONFIG_VERSION,
+        servers: servers
+            .into_iter()
+            .map(|(s, a)| ExportedServer {
+                domain: s.domain,
+                base_url: s.base_url,
+                sns: s.sns,
+                ca_certificate: s.ca_certificate,
+                account_id: a.map(|a| a.account_id),
+            })
+            .collect(),
+        workspaces: workspaces
+            .into_iter()
+            .map(|w| ExportedWorkspace {
+                name: w.name,
+                icon: w.icon,
+                active: w.active,
+            })
+            .collect(),
+        timelines: exported_timelines,
+        settings: Some(settings),
+        credentials,
+    })
+}
+
+pub(crate) fn write(filepath: &PathBuf, config: &ExportedConfig) -> Result<(), Error> {
+    let str = to_string_pretty(config)?;
+    Ok(fs::write(filepath, str)?)
+}
+
+pub(crate) fn read(filepath: &PathBuf) -> Result<ExportedConfig, Error> {
+    let text = fs::read_to_string(filepath)?;
+    // The version is checked first, because files of other versions do not parse as this layout.
+    let version = from_str::<ConfigVersion>(&text)?.version;
+    if version > CONFIG_VERSION {
+        return Err(Error::validation(format!(
+            "The configuration version {} is newer than this application supports",
+            version
+        )));
+    }
+    if version < CONFIG_VERSION {
+        return Err(Error::validation(format!(
+            "The configuration version {} is no longer supported, so export it again",
+            version
+        )));
+    }
+    let config = from_str::<ExportedConfig>(&text)?;
+    Ok(config)
+}
+
+/// Imports the configuration into the current database and settings.
+/// When `dry_run` is true, nothing is written and only the report is returned.
+/// Rows are written in one transaction, and the accounts which are created by the import are returned with their servers.
+pub(crate) async fn import(
+    pool: &sqlx::SqlitePool,
+    clients: &Clients,
+    settings_path: &PathBuf,
+    config: ExportedConfig,
+    password: Option<&str>,
+    dry_run: bool,
+) -> Result<(ImportReport, Vec<(entities::Account, entities::Server)>), Error> {
+    let mut plan = plan(pool, config, password).await?;
+    if dry_run {
+        plan.report.dry_run = true;
+        return Ok((plan.report, Vec::new()));
+    }
+
+    // Servers are detected before the transaction, so that it does not wait for the network.
+    let mut templates = server_templates(&plan);
+    for server in templates.values_mut() {
+        if plan.registered.contains_key(&server.base_url) {
+            continue;
+        }
+        // The certificate is trusted only after the import is written, so a temporary client is used until then.
+        let client = match &server.ca_certificate {
+            Some(pem) => clients.http_with_roots(&server.base_url, pem)?,
+            None => clients.http_for(&server.base_url),
+        };
+        if let Err(err) = nodeinfo::detect_software(&client, server).await {
+            tracing::warn!(
+                "Failed to detect the software of {}: {}",
+                server.base_url,
+                err
+            );
+        }
+    }
+
+    let proxy_password = plan.credentials.proxy_password.clone();
+    let settings = plan.config.settings.take();
+    let (report, created_accounts, created_servers) = apply(pool, plan, templates).await?;
+
+    let mut trusted = false;
+    for created in created_servers.iter() {
+        if let Some(pem) = &created.ca_certificate {
+            tls::set_roots(&created.base_url, Some(pem))?;
+            trusted = true;
+        }
+    }
+    if trusted {
+        clients.reset()?;
+    }
+
+    for created in created_servers.iter() {
+        if let Err(err) =
+            favicon::refresh(pool, &clients.http_for(&created.base_url), created).await
+        {
+            tracing::warn!(
+                "Failed to download the favicon for {}: {}",
+                created.base_url,
+                err
+            );
+        }
+    }
+
+    if let Some(mut settings) = settings {
+        if let Some(proxy) = settings.proxy.as_mut() {
+            proxy.password = proxy_password;
+        }
+        settings::save_settings(settings_path, &settings)?;
+    }
+
+    Ok((report, created_accounts))
+}
+
+/// Decides where each exported server goes and reports conflicts with the database, without writing anything.
+async fn plan(
+    pool: &sqlx::SqlitePool,
+    mut config: ExportedConfig,
+    password: Option<&str>,
+) -> Result<ImportPlan, Error> {
+    let mut report = ImportReport::default();
+
+    let credentials: ExportedCredentials = match (&config.credentials, password) {
+        (Some(credentials), Some(password)) => {
+            let plain = decrypt(password, credentials)?;
+            serde_json::from_slice(&plain)?
+        }
+        _ => ExportedCredentials::default(),
+    };
+
+    // Files exported by older versions may have base URLs which are not normalized.
+    for server in config.servers.iter_mut() {
+        server.base_url = normalize_base_url(&server.base_url);
+    }
+
+    let mut registered: HashMap<String, entities::Server> = HashMap::new();
+    for exported in config.servers.iter() {
+        if registered.contains_key(&exported.base_url)
+            || report.new_servers.contains(&exported.base_url)
+        {
+            continue;
+        }
+        match database::find_server_by_base_url(pool, &exported.base_url).await? {
+            Some(server) => {
+                let reason = if server.sns == exported.sns {
+                    ConflictReason::ServerExists
+                } else {
+                    ConflictReason::SnsMismatch
+                };
+                report.conflicts.push(ImportConflict {
+                    base_url: exported.base_url.clone(),
+                    reason,
+                });
+                registered.insert(exported.base_url.clone(), server);
+            }
+            None => report.new_servers.push(exported.base_url.clone()),
+        }
+    }
+
+    let existing_accounts = database::list_account(pool).await?;
+    let mut targets: Vec<Target> = Vec::with_capacity(config.servers.len());
+    for (index, exported) in config.servers.iter().enumerate() {
+        let account = credentials.accounts.iter().position(|a| a.server == index);
+        let account_id = account
+            .map(|i| &credentials.accounts[i].account_id)
+            .or(exported.account_id.as_ref());
+        let existing = account_id.and_then(|id| {
+            existing_accounts
+                .iter()
+                .find(|(a, s)| s.base_url == exported.base_url && &a.account_id == id)
+                .map(|(_, s)| s.clone())
+        });
+        let target = match (existing, account) {
+            (Some(server), account) => {
+                if account.is_some() {
+                    report.conflicts.push(ImportConflict {
+                        base_url: exported.base_url.clone(),
+                        reason: ConflictReason::AccountExists,
+                    });
+                }
+                Target::Existing(server)
+            }
+            (None, Some(account)) => {
+                report.accounts += 1;
+                Target::Account(account)
+            }
+            (None, None) => {
+                match database::find_anonymous_server(pool, &exported.base_url).await? {
+                    Some(server) => Target::Existing(server),
+                    None => Target::Anonymous(exported.base_url.clone()),
+                }
+            }
+        };
+        targets.push(target);
+    }
+
+    report.workspaces = config.workspaces.len();
+    report.timelines = config
+        .timelines
+        .iter()
+        .filter(|t| {
+            targets
+                .get(t.server)
+                .is_some_and(|target| can_import(target, &t.kind))
+        })
+        .count();
+    report.settings = config.settings.is_some();
+
+    Ok(ImportPlan {
+        report,
+        config,
+        credentials,
+        targets,
+        registered,
+        existing_workspaces: database::list_workspaces(pool).await?,
+        existing_timelines: database::list_all_timelines(pool).await?,
+    })
+}
+
+/// Timelines which can only be read with an account are skipped when the account is not imported.
+fn can_import(target: &Target, kind: &entities::timeline::Kind) -> bool {
+    let signed_in = match target {
+        Target::Existing(server) => server.account_id.is_some(),
+        Target::Anonymous(_) => false,
+        Target::Account(_) => true,
+    };
+    signed_in || !kind.requires_account()
+}
+
+/// Returns the servers to add keyed by base_url, copied from the registered ones when they exist.
+fn server_templates(plan: &ImportPlan) -> HashMap<String, entities::Server> {
+    let mut templates: HashMap<String, entities::Server> = HashMap::new();
+    for (exported, target) in plan.config.servers.iter().zip(plan.targets.iter()) {
+        if matches!(target, Target::Existing(_)) || templates.contains_key(&exported.base_url) {
+            continue;
+        }
+        let server = match plan.registered.get(&exported.base_url) {
+            Some(existing) => {
+                let mut server = existing.clone();
+                server.id = 0;
+                server.account_id = None;
+                server.favicon = None;
+                server
+            }
+            None => {
+                let mut server = entities::Server::new(
+                    0,
+                    exported.domain.clone(),
+                    exported.base_url.clone(),
+                    exported.sns.clone(),
+                    None,
+                );
+                server.ca_certificate = exported.ca_certificate.clone();
+                server
+            }
+        };
+        templates.insert(exported.base_url.clone(), server);
+    }
+    templates
+}
+
+/// Writes the plan in one transaction, and returns the report with the created accounts and servers.
+async fn apply(
+    pool: &sqlx::SqlitePool,
+    mut plan: ImportPlan,
+    templates: HashMap<String, entities::Server>,
+) -> Result<
+    (
+        ImportReport,
+        Vec<(entities::Account, entities::Server)>,
+        Vec<entities::Server>,
+    ),
+    Error,
+> {
+    let mut tx = pool.begin().await?;
+    let mut created_servers: Vec<entities::Server> = Vec::new();
+    let mut created_accounts: Vec<(entities::Account, entities::Server)> = Vec::new();
+    let mut servers: Vec<entities::Server> = Vec::with_capacity(plan.targets.len());
+    for (exported, target) in plan.config.servers.iter().zip(plan.targets.iter()) {
+        let template = || {
+            templates.get(&exported.base_url).cloned().ok_or_else(|| {
+                Error::internal(format!("No server to add for {}", exported.base_url))
+            })
+        };
+        let server = match target {
+            Target::Existing(server) => server.clone(),
+            Target::Anonymous(base_url) => {
+                // Several accounts which are not imported share the anonymous server of the instance.
+                match created_servers
+                    .iter()
+                    .find(|s| &s.base_url == base_url && s.account_id.is_none())
+                {
+                    Some(server) => server.clone(),
+                    None => {
+                        let created = database::insert_server(&mut tx, template()?).await?;
+                        created_servers.push(created.clone());
+                        created
+                    }
+                }
+            }
+            Target::Account(index) => {
+                let a = &plan.credentials.accounts[*index];
+                // Each account has its own server row, so another account on the same instance gets a new one.
+                let mut server = database::insert_server(&mut tx, template()?).await?;
+                let account = entities::Account::new(
+                    0,
+                    a.username.clone(),
+                    a.account_id.clone(),
+                    a.avatar.clone(),
+                    a.client_id.clone(),
+                    a.client_secret.clone(),
+                    a.access_token.clone(),
+                    a.refresh_token.clone(),
+                    a.usual,
+                );
+                let created = database::insert_account(&mut tx, &server, &account).await?;
+                server.account_id = Some(created.id);
+                created_servers.push(server.clone());
+                created_accounts.push((created, server.clone()));
+                server
+            }
+        };
+        servers.push(server);
+    }
+
+    let mut workspace_ids: Vec<i64> = Vec::with_capacity(plan.config.workspaces.len());
+    for exported in plan.config.workspaces.iter() {
+        let id = match plan
+            .existing_workspaces
+            .iter()
+            .find(|w| w.name == exported.name)
+        {
+            Some(w) => w.id,
+            None => {
+                database::insert_workspace(&mut tx, &exported.name, exported.icon.as_deref())
+                    .await?
+                    .id
+            }
+        };
+        workspace_ids.push(id);
+    }
+
+    plan.report.timelines = 0;
+    for exported in std::mem::take(&mut plan.config.timelines).into_iter() {
+        let (Some(server), Some(target)) = (
+            servers.get(exported.server),
+            plan.targets.get(exported.server),
+        ) else {
+            continue;
+        };
+        if !can_import(target, &exported.kind) {
+            continue;
+        }
+        let server_id = server.id;
+        let workspace_id = exported
+            .workspace
+            .and_then(|index| workspace_ids.get(index).copied());
+        let duplicated = plan.existing_timelines.iter().any(|t| {
+            t.server_id == server_id
+                && t.kind == exported.kind
+                && t.name == exported.name
+                && (workspace_id.is_none() || t.workspace_id == workspace_id)
+        });
+        if duplicated {
+            continue;
+        }
+
+        let mut timeline = entities::Timeline::new(
+            0,
+            server_id,
+            exported.kind,
+            exported.name,
+            0,
+            exported.list_id,
+            exported.column_width,
+        );
+        timeline.show_boosts = exported.show_boosts;
+        timeline.show_replies = exported.show_replies;
+        timeline.show_pinned = exported.show_pinned;
+        timeline.notification_filter = exported.notification_filter;
+        timeline.remote_account_id = exported.remote_account_id;
+        timeline.remote_acct = exported.remote_acct;
+        timeline.workspace_id = workspace_id;
+        let created = database::insert_timeline(&mut tx, &timeline).await?;
+        // Timelines of several exported servers may go to the same server, so they are checked against each other too.
+        plan.existing_timelines.push(created);
+        plan.report.timelines += 1;
+    }
+    tx.commit().await?;
+
+    Ok((plan.report, created_accounts, created_servers))
+}
+
+fn derive_key(password: &str, salt: &[u8]) -> Result<aead::LessSafeKey, Error> {
+    let mut key = [0u8; 32];
+    pbkdf2::derive(
+        pbkdf2::PBKDF2_HMAC_SHA256,
+        PBKDF2_ITERATIONS,
+        salt,
+        password.as_bytes(),
+        &mut key,
+    );
+    let unbound = aead::UnboundKey::new(&aead::AES_256_GCM, &key)
+        .map_err(|_| Error::internal("Failed to create encryption key"))?;
+    Ok(aead::LessSafeKey::new(unbound))
+}
+
+fn encrypt(password: &str, plain: &[u8]) -> Result<EncryptedCredentials, Error> {
+    let rng = SystemRandom::new();
+    let mut salt = [0u8; SALT_LEN];
+    rng.fill(&mut salt)
+        .map_err(|_| Error::internal("Failed to generate salt"))?;
+    let mut nonce = [0u8; aead::NONCE_LEN];
+    rng.fill(&mut nonce)
+        .map_err(|_| Error::internal("Failed to generate nonce"))?;
+
+    let key = derive_key(password, &salt)?;
+    let mut in_out = plain.to_vec();
+    key.seal_in_place_append_tag(
+        aead::Nonce::assume_unique_for_key(nonce),
+        aead::Aad::empty(),
+        &mut in_out,
+    )
+    .map_err(|_| Error::internal("Failed to encrypt credentials"))?;
+
+    Ok(EncryptedCredentials {
+        salt: general_purpose::STANDARD.encode(salt),
+        nonce: general_purpose::STANDARD.encode(nonce),
+        ciphertext: general_purpose::STANDARD.encode(in_out),
+    })
+}
+
+fn decrypt(password: &str, credentials: &EncryptedCredentials) -> Result<Vec<u8>, Error> {
+    let salt = general_purpose::STANDARD
+        .decode(&credentials.salt)
+        .map_err(|e| Error::validation(e.to_string()))?;
+    let nonce: [u8; aead::NONCE_LEN] = general_purpose::STANDARD
+        .decode(&credentials.nonce)
+        .map_err(|e| Error::validation(e.to_string()))?
+        .try_into()
+        .map_err(|_| Error::validation("Invalid nonce"))?;
+    let mut in_out = general_purpose::STANDARD
+        .decode(&credentials.ciphertext)
+        .map_err(|e| Error::validation(e.to_string()))?;
+
+    let key = derive_key(password, &salt)?;
+    let plain = key
+        .open_in_place(
+            aead::Nonce::assume_unique_for_key(nonce),
+            aead::Aad::empty(),
+            &mut in_out,
+        )
+        .map_err(|_| {
+            Error::validation("Failed to decrypt credentials, the password may be wrong")
+        })?;
+
+    Ok(plain.to_vec())
+}
+
+fn normalize_base_url(base_url: &str) -> String {
+    server_url::parse(base_url)
+        .map(|s| s.base_url)
+        .unwrap_or_else(|_| base_url.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encrypt_and_decrypt_credentials() {
+        let encrypted = encrypt("secret", b"tokens").unwrap();
+        let decrypted = decrypt("secret", &encrypted).unwrap();
+        assert_eq!(decrypted, b"tokens".to_vec());
+    }
+
+    #[test]
+    fn test_decrypt_credentials_with_wrong_password() {
+        let encrypted = encrypt("secret", b"tokens").unwrap();
+        assert!(decrypt("wrong", &encrypted).is_err());
+    }
+
+    async fn create_pool() -> sqlx::SqlitePool {
+        let pool = database::create_memory_pool().await.unwrap();
+        database::migrate_database(&pool).await.unwrap();
+        pool
+    }
+
+    async fn add_signed_in_server(pool: &sqlx::SqlitePool, base_url: &str, account_id: &str) {
+        let server = database::add_server(
+            pool,
+            entities::Server::new(
+                0,
+                "example.social".to_string(),
+                base_url.to_string(),
+                "mastodon".to_string(),
+                None,
+            ),
+        )
+        .await
+        .unwrap();
+        database::add_account(pool, &server, &account(account_id))
+            .await
+            .unwrap();
+    }
+
+    fn account(account_id: &str) -> entities::Account {
+        entities::Account::new(
+            0,
+            format!("user{}", account_id),
+            account_id.to_string(),
+            None,
+            None,
+            "secret".to_string(),
+            format!("token{}", account_id),
+            None,
+            false,
+        )
+    }
+
+    fn exported_server(base_url: &str, sns: &str, account_id: Option<&str>) -> ExportedServer {
+        ExportedServer {
+            domain: base_url.trim_start_matches("https://").to_string(),
+            base_url: base_url.to_string(),
+            sns: sns.to_string(),
+            ca_certificate: None,
+            account_id: account_id.map(|id| id.to_string()),
+        }
+    }
+
+    fn exported_timeline(server: usize, kind: entities::timeline::Kind) -> ExportedTimeline {
+        ExportedTimeline {
+            server,
+            workspace: Some(0),
+            name: kind.to_string(),
+            kind,
+            list_id: None,
+            column_width: entities::timeline::ColumnWidth::SM,
+            show_boosts: true,
+            show_replies: true,
+            remote_account_id: None,
+            remote_acct: None,
+            show_pinned: true,
+            notification_filter: None,
+        }
+    }
+
+    fn exported_account(server: usize, account_id: &str) -> ExportedAccount {
+        let a = account(account_id);
+        ExportedAccount {
+            server,
+            username: a.username,
+            account_id: a.account_id,
+            avatar: None,
+            client_id: None,
+            client_secret: a.client_secret,
+            access_token: a.access_token,
+            refresh_token: None,
+            usual: false,
+        }
+    }
+
+    fn exported_config(
+        servers: Vec<ExportedServer>,
+        timelines: Vec<ExportedTimeline>,
+        accounts: Vec<ExportedAccount>,
+    ) -> ExportedConfig {
+        let credentials = ExportedCredentials {
+            accounts,
+            proxy_password: None,
+        };
+        ExportedConfig {
+            version: CONFIG_VERSION,
+            servers,
+            workspaces: vec![ExportedWorkspace {
+                name: "Default".to_string(),
+                icon: None,
+                active: true,
+            }],
+            timelines,
+            settings: None,
+            credentials: Some(
+                encrypt(
+                    "secret",
+                    serde_json::to_string(&credentials).unwrap().as_bytes(),
+                )
+                .unwrap(),
+            ),
+        }
+    }
+
+    /// Imports the plan without the network, which only detects the software and downloads favicons.
+    async fn import_offline(
+        pool: &sqlx::SqlitePool,
+        config: ExportedConfig,
+    ) -> (ImportReport, Vec<(entities::Account, entities::Server)>) {
+        let plan = plan(pool, config, Some("secret")).await.unwrap();
+        let templates = server_templates(&plan);
+        let (report, accounts, _) = apply(pool, plan, templates).await.unwrap();
+        (report, accounts)
+    }
+
+    /// Returns the account ids of the servers which the timelines of the kind are on.
+    async fn timeline_accounts(
+        pool: &sqlx::SqlitePool,
+        kind: entities::timeline::Kind,
+    ) -> Vec<Option<String>> {
+        let accounts = database::list_account(pool).await.unwrap();
+        let mut ids: Vec<Option<String>> = database::list_all_timelines(pool)
+            .await
+            .unwrap()
+            .into_iter()
+            .filter(|t| t.kind == kind)
+            .map(|t| {
+                accounts
+                    .iter()
+                    .find(|(_, s)| s.id == t.server_id)
+                    .map(|(a, _)| a.account_id.clone())
+            })
+            .collect();
+        ids.sort();
+        ids
+    }
+
+    #[tokio::test]
+    async fn test_dry_run_reports_conflicts_without_writing() {
+        let pool = create_pool().await;
+        add_signed_in_server(&pool, "https://example.social", "1").await;
+        let config = exported_config(
+            vec![
+                exported_server("https://example.social", "mastodon", Some("1")),
+                exported_server("https://misskey.example", "misskey", None),
+                exported_server("https://new.example", "mastodon", None),
+            ],
+            vec![
+                exported_timeline(0, entities::timeline::Kind::Home),
+                exported_timeline(1, entities::timeline::Kind::Local),
+                exported_timeline(2, entities::timeline::Kind::Local),
+                // Home can not be read on the anonymous server which is added.
+                exported_timeline(2, entities::timeline::Kind::Home),
+            ],
+            vec![exported_account(0, "1")],
+        );
+        database::add_server(
+            &pool,
+            entities::Server::new(
+                0,
+                "misskey.example".to_string(),
+                "https://misskey.example".to_string(),
+                "mastodon".to_string(),
+                None,
+            ),
+        )
+        .await
+        .unwrap();
+
+        let report = plan(&pool, config, Some("secret")).await.unwrap().report;
+
+        assert_eq!(report.new_servers, vec!["https://new.example".to_string()]);
+        let reasons: Vec<(&str, &ConflictReason)> = report
+            .conflicts
+            .iter()
+            .map(|c| (c.base_url.as_str(), &c.reason))
+            .collect();
+        assert_eq!(
+            reasons,
+            vec![
+                ("https://example.social", &ConflictReason::ServerExists),
+                ("https://misskey.example", &ConflictReason::SnsMismatch),
+                ("https://example.social", &ConflictReason::AccountExists),
+            ]
+        );
+        assert_eq!(report.accounts, 0);
+        assert_eq!(report.timelines, 3);
+        assert_eq!(database::list_servers(&pool).await.unwrap().len(), 2);
+        assert_eq!(database::list_account(&pool).await.unwrap().len(), 1);
+        assert!(database::list_all_timelines(&pool)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_import_keeps_accounts_on_the_same_instance_apart() {
+        let pool = create_pool().await;
+        let config = exported_config(
+            vec![
+                exported_server("https://example.social", "mastodon", Some("1")),
+                exported_server("https://example.social", "mastodon", Some("2")),
+            ],
+            vec![
+                exported_timeline(0, entities::timeline::Kind::Home),
+                exported_timeline(1, entities::timeline::Kind::Home),
+                exported_timeline(1, entities::timeline::Kind::Notifications),
+            ],
+            vec![exported_account(0, "1"), exported_account(1, "2")],
+        );
+
+        let (report, created) = import_offline(&pool, config).await;
+
+        assert_eq!(report.accounts, 2);
+        assert_eq!(report.timelines, 3);
+        assert_eq!(created.len(), 2);
+        assert_ne!(created[0].1.id, created[1].1.id);
+        assert_eq!(database::list_servers(&pool).await.unwrap().len(), 2);
+        assert_eq!(
+            timeline_accounts(&pool, entities::timeline::Kind::Home).await,
+            vec![Some("1".to_string()), Some("2".to_string())]
+        );
+        assert_eq!(
+            timeline_accounts(&pool, entities::timeline::Kind::Notifications).await,
+            vec![Some("2".to_string())]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_import_without_credentials_finds_existing_accounts() {
+        let pool = create_pool().await;
+        add_signed_in_server(&pool, "https://example.social", "1").await;
+        add_signed_in_server(&pool, "https://example.social", "2").await;
+        let mut config = exported_config(
+            vec![
+                exported_server("https://example.social", "mastodon", Some("1")),
+                exported_server("https://example.social", "mastodon", Some("2")),
+                exported_server("https://example.social", "mastodon", Some("3")),
+            ],
+            vec![
+                exported_timeline(0, entities::timeline::Kind::Home),
+                exported_timeline(1, entities::timeline::Kind::Home),
+                exported_timeline(2, entities::timeline::Kind::Home),
+                exported_timeline(2, entities::timeline::Kind::Local),
+            ],
+            Vec::new(),
+        );
+        config.credentials = None;
+
+        let (report, created) = import_offline(&pool, config).await;
+
+        assert!(created.is_empty());
+        assert_eq!(report.timelines, 3);
+        assert_eq!(
+            timeline_accounts(&pool, entities::timeline::Kind::Home).await,
+            vec![Some("1".to_string()), Some("2".to_string())]
+        );
+        // The account 3 is not imported, so its Local timeline goes to a new anonymous server.
+        assert_eq!(
+            timeline_accounts(&pool, entities::timeline::Kind::Local).await,
+            vec![None]
+        );
+        assert_eq!(database::list_servers(&pool).await.unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_export_keeps_the_proxy_password_out_of_the_settings() {
+        let pool = create_pool().await;
+        let path = std::env::temp_dir().join(format!(
+            "fedistar-export-settings-{}.json",
+            std::process::id()
+        ));
+        let mut stored = settings::Settings::default();
+        stored.proxy = Some(settings::Proxy {
+            protocol: settings::ProxyProtocol::Http,
+            host: "proxy.example".to_string(),
+            port: 8080,
+            username: Some("alice".to_string()),
+            password: Some("hunter2".to_string()),
+            no_proxy: Vec::new(),
+        });
+        settings::save_settings(&path, &stored).unwrap();
+
+        let plain = export(&pool, &path, None).await.unwrap();
+        let encrypted = export(&pool, &path, Some("secret")).await.unwrap();
+        let _ = fs::remove_file(&path);
+
+        assert!(plain.credentials.is_none());
+        for config in [&plain, &encrypted] {
+            let proxy = config.settings.as_ref().unwrap().proxy.as_ref().unwrap();
+            assert_eq!(proxy.username.as_deref(), Some("alice"));
+            assert_eq!(proxy.password, None);
+        }
+        let credentials: ExportedCredentials = serde_json::from_slice(
+            &decrypt("secret", encrypted.credentials.as_ref().unwrap()).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(credentials.proxy_password.as_deref(), Some("hunter2"));
+    }
+}
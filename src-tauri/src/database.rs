@@ -1,14 +1,15 @@
-use std::str::FromStr;
+use std::{ffi::CStr, path::Path, str::FromStr};
 
+use libsqlite3_sys as ffi;
 use sqlx::{
     query_as,
     sqlite::{SqliteConnectOptions, SqlitePoolOptions, SqliteRow},
-    Row, SqlitePool,
+    ConnectOptions, Connection, Row, SqliteConnection, SqliteExecutor, SqlitePool,
 };
 
-use crate::entities;
+use crate::{entities, error::Error, server_url};
 
-type DBResult<T> = Result<T, Box<dyn std::error::Error>>;
+type DBResult<T> = Result<T, Error>;
 
 pub(crate) async fn create_sqlite_pool(database_url: &str) -> DBResult<SqlitePool> {
     let connect_options = SqliteConnectOptions::from_str(database_url)?
@@ -23,11 +24,170 @@ pub(crate) async fn create_sqlite_pool(database_url: &str) -> DBResult<SqlitePoo
     Ok(sqlite_pool)
 }
 
+// Used only when the database file can not be opened, so that commands keep working until it is restored.
+pub(crate) async fn create_memory_pool() -> DBResult<SqlitePool> {
+    let connect_options = SqliteConnectOptions::from_str("sqlite::memory:")?;
+
+    let sqlite_pool = SqlitePoolOptions::new()
+        .max_connections(1)
+        .idle_timeout(None)
+        .max_lifetime(None)
+        .connect_with(connect_options)
+        .await?;
+
+    Ok(sqlite_pool)
+}
+
 pub(crate) async fn migrate_database(pool: &SqlitePool) -> DBResult<()> {
     sqlx::migrate!("./db").run(pool).await?;
+    normalize_servers(pool).await?;
+    Ok(())
+}
+
+/// Stores base_url and domain of the servers in the form which `server_url::parse` returns.
+/// Anonymous servers which turn out to be the same one are merged, moving their timelines.
+/// Servers with an account are never merged, because each account has its own server.
+async fn normalize_servers(pool: &SqlitePool) -> DBResult<()> {
+    let servers = query_as::<_, entities::Server>("SELECT * FROM servers ORDER BY id")
+        .fetch_all(pool)
+        .await?;
+
+    let mut tx = pool.begin().await?;
+    for server in servers {
+        let normalized = match server_url::parse(&server.base_url) {
+            Ok(normalized) => normalized,
+            Err(err) => {
+                tracing::warn!("Failed to normalize {}: {}", server.base_url, err);
+                continue;
+            }
+        };
+        if normalized.base_url == server.base_url && normalized.domain == server.domain {
+            continue;
+        }
+
+        if server.account_id.is_none() {
+            let kept = sqlx::query_scalar::<_, i64>(
+                "SELECT id FROM servers WHERE base_url = ? AND account_id IS NULL AND id != ?",
+            )
+            .bind(&normalized.base_url)
+            .bind(server.id)
+            .fetch_optional(&mut *tx)
+            .await?;
+            if let Some(kept) = kept {
+                sqlx::query("UPDATE timelines SET server_id = ? WHERE server_id = ?")
+                    .bind(kept)
+                    .bind(server.id)
+                    .execute(&mut *tx)
+                    .await?;
+                sqlx::query("DELETE FROM servers WHERE id = ?")
+                    .bind(server.id)
+                    .execute(&mut *tx)
+                    .await?;
+                continue;
+            }
+        }
+
+        sqlx::query("UPDATE servers SET base_url = ?, domain = ? WHERE id = ?")
+            .bind(normalized.base_url)
+            .bind(normalized.domain)
+            .bind(server.id)
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await?;
+
+    Ok(())
+}
+
+pub(crate) async fn integrity_check<'c>(executor: impl SqliteExecutor<'c>) -> DBResult<()> {
+    let results = sqlx::query_scalar::<_, String>("PRAGMA integrity_check")
+        .fetch_all(executor)
+        .await?;
+
+    if results.len() == 1 && results[0] == "ok" {
+        Ok(())
+    } else {
+        Err(Error::db(results.join("\n")))
+    }
+}
+
+/// Writes a consistent copy of the database to the file while it is in use.
+pub(crate) async fn backup_database(pool: &SqlitePool, path: &Path) -> DBResult<()> {
+    let mut source = pool.acquire().await?;
+    let mut destination = SqliteConnectOptions::new()
+        .filename(path)
+        .create_if_missing(true)
+        .connect()
+        .await?;
+    copy_database(&mut destination, &mut source).await?;
+    destination.close().await?;
+    Ok(())
+}
+
+/// Opens the database file read-only and checks its integrity.
+pub(crate) async fn check_database_file(path: &Path) -> DBResult<()> {
+    let mut conn = SqliteConnectOptions::new()
+        .filename(path)
+        .read_only(true)
+        .connect()
+        .await?;
+    integrity_check(&mut conn).await?;
+    conn.close().await?;
     Ok(())
 }
 
+/// Replaces the contents of the database file with the backup file.
+/// The database is left unchanged when this fails.
+pub(crate) async fn restore_database(database_path: &Path, backup_path: &Path) -> DBResult<()> {
+    let mut source = SqliteConnectOptions::new()
+        .filename(backup_path)
+        .read_only(true)
+        .connect()
+        .await?;
+    let mut destination = SqliteConnectOptions::new()
+        .filename(database_path)
+        .create_if_missing(true)
+        .connect()
+        .await?;
+    copy_database(&mut destination, &mut source).await?;
+    destination.close().await?;
+    source.close().await?;
+    Ok(())
+}
+
+// Copies all pages with the online backup API, which writes the destination in one transaction
+// so that other connections to it see either the old or the new contents.
+async fn copy_database(
+    destination: &mut SqliteConnection,
+    source: &mut SqliteConnection,
+) -> DBResult<()> {
+    let mut destination = destination.lock_handle().await?;
+    let mut source = source.lock_handle().await?;
+    let destination = destination.as_raw_handle().as_ptr();
+    let source = source.as_raw_handle().as_ptr();
+
+    // SAFETY: Both handles are open and locked for this function, and the backup object is finished before they are released.
+    unsafe {
+        let backup =
+            ffi::sqlite3_backup_init(destination, c"main".as_ptr(), source, c"main".as_ptr());
+        if backup.is_null() {
+            return Err(Error::db(sqlite_error(destination)));
+        }
+        let step = ffi::sqlite3_backup_step(backup, -1);
+        let finish = ffi::sqlite3_backup_finish(backup);
+        if step != ffi::SQLITE_DONE || finish != ffi::SQLITE_OK {
+            return Err(Error::db(sqlite_error(destination)));
+        }
+    }
+    Ok(())
+}
+
+unsafe fn sqlite_error(handle: *mut ffi::sqlite3) -> String {
+    unsafe { CStr::from_ptr(ffi::sqlite3_errmsg(handle)) }
+        .to_string_lossy()
+        .into_owned()
+}
+
 pub(crate) async fn list_servers(
     pool: &SqlitePool,
 ) -> DBResult<Vec<(entities::Server, Option<entities::Account>)>> {
@@ -35,7 +195,8 @@ pub(crate) async fn list_servers(
         r#"
 SELECT servers.id, servers.domain, servers.base_url, servers.sns, servers.favicon, servers.account_id,
        accounts.id, accounts.username, accounts.account_id, accounts.avatar, accounts.client_id, accounts.client_secret,
-       accounts.access_token, accounts.refresh_token, accounts.usual
+       accounts.access_token, accounts.refresh_token, accounts.usual, servers.ca_certificate,
+       servers.software, servers.software_version
 FROM servers LEFT JOIN accounts ON servers.account_id = accounts.id"#,
     ).map(|row: SqliteRow| {
         let server = entities::Server {
@@ -45,6 +206,9 @@ FROM servers LEFT JOIN accounts ON servers.account_id = accounts.id"#,
                 sns: row.get(3),
                 favicon: row.get(4),
                 account_id: row.get(5),
+                ca_certificate: row.get(15),
+                software: row.get(16),
+                software_version: row.get(17),
         };
         if row.get(6) {
             (server, Some(entities::Account {
@@ -77,23 +241,65 @@ pub(crate) async fn get_server(pool: &SqlitePool, id: i64) -> DBResult<entities:
     Ok(server)
 }
 
+/// Finds a server of the base_url, preferring one with an account.
+pub(crate) async fn find_server_by_base_url(
+    pool: &SqlitePool,
+    base_url: &str,
+) -> DBResult<Option<entities::Server>> {
+    let server = query_as::<_, entities::Server>(
+        "SELECT * FROM servers WHERE base_url = ? ORDER BY account_id IS NULL, id LIMIT 1",
+    )
+    .bind(base_url)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(server)
+}
+
+/// Finds the server which is not signed in, since servers with an account may share the same base_url.
+pub(crate) async fn find_anonymous_server(
+    pool: &SqlitePool,
+    base_url: &str,
+) -> DBResult<Option<entities::Server>> {
+    let server = query_as::<_, entities::Server>(
+        "SELECT * FROM servers WHERE base_url = ? AND account_id IS NULL",
+    )
+    .bind(base_url)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(server)
+}
+
 pub(crate) async fn add_server(
     pool: &SqlitePool,
     server: entities::Server,
 ) -> DBResult<entities::Server> {
     let mut tx = pool.begin().await?;
+    let created = insert_server(&mut tx, server).await?;
+    tx.commit().await?;
+    Ok(created)
+}
+
+/// Inserts the server in the caller's transaction.
+pub(crate) async fn insert_server(
+    conn: &mut SqliteConnection,
+    server: entities::Server,
+) -> DBResult<entities::Server> {
     let mut created = server.clone();
 
     let res =
-        sqlx::query("INSERT INTO servers (domain, base_url, sns, favicon) VALUES (?, ?, ?, ?)")
+        sqlx::query("INSERT INTO servers (domain, base_url, sns, favicon, ca_certificate, software, software_version) VALUES (?, ?, ?, ?, ?, ?, ?)")
             .bind(server.domain)
             .bind(server.base_url)
             .bind(server.sns)
             .bind(server.favicon)
-            .execute(&mut *tx)
+            .bind(server.ca_certificate)
+            .bind(server.software)
+            .bind(server.software_version)
+            .execute(&mut *conn)
             .await?;
 
-    tx.commit().await?;
     let id = res.last_insert_rowid();
     created.id = id;
     Ok(created)
@@ -114,11 +320,14 @@ pub(crate) async fn remove_server(pool: &SqlitePool, id: i64) -> DBResult<()> {
 pub(crate) async fn update_server(pool: &SqlitePool, server: entities::Server) -> DBResult<()> {
     let mut tx = pool.begin().await?;
 
-    sqlx::query("UPDATE servers SET domain = ?, base_url = ?, sns = ?, favicon = ? WHERE id = ?")
+    sqlx::query("UPDATE servers SET domain = ?, base_url = ?, sns = ?, favicon = ?, ca_certificate = ?, software = ?, software_version = ? WHERE id = ?")
         .bind(server.domain)
         .bind(server.base_url)
         .bind(server.sns)
         .bind(server.favicon)
+        .bind(server.ca_certificate)
+        .bind(server.software)
+        .bind(server.software_version)
         .bind(server.id)
         .execute(&mut *tx)
         .await?;
@@ -128,12 +337,148 @@ pub(crate) async fn update_server(pool: &SqlitePool, server: entities::Server) -
     Ok(())
 }
 
+pub(crate) async fn get_favicon(
+    pool: &SqlitePool,
+    server_id: i64,
+) -> DBResult<Option<entities::Favicon>> {
+    let favicon = query_as::<_, entities::Favicon>("SELECT * FROM favicons WHERE server_id = ?")
+        .bind(server_id)
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(favicon)
+}
+
+pub(crate) async fn save_favicon(pool: &SqlitePool, favicon: &entities::Favicon) -> DBResult<()> {
+    let mut tx = pool.begin().await?;
+
+    sqlx::query(
+        r#"
+INSERT INTO favicons (server_id, url, content_type, data, etag, last_modified, fetched_at) VALUES (?, ?, ?, ?, ?, ?, ?)
+ON CONFLICT(server_id) DO UPDATE SET url = excluded.url, content_type = excluded.content_type, data = excluded.data,
+  etag = excluded.etag, last_modified = excluded.last_modified, fetched_at = excluded.fetched_at"#,
+    )
+    .bind(favicon.server_id)
+    .bind(&favicon.url)
+    .bind(&favicon.content_type)
+    .bind(&favicon.data)
+    .bind(&favicon.etag)
+    .bind(&favicon.last_modified)
+    .bind(favicon.fetched_at)
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+pub(crate) async fn touch_favicon(
+    pool: &SqlitePool,
+    server_id: i64,
+    fetched_at: i64,
+) -> DBResult<()> {
+    let mut tx = pool.begin().await?;
+
+    sqlx::query("UPDATE favicons SET fetched_at = ? WHERE server_id = ?")
+        .bind(fetched_at)
+        .bind(server_id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+pub(crate) async fn get_server_metadata(
+    pool: &SqlitePool,
+    server_id: i64,
+) -> DBResult<Option<entities::ServerMetadata>> {
+    let row = sqlx::query(
+        r#"
+SELECT server_id, instance, rules, emojis, max_characters, max_media_attachments, characters_reserved_per_url,
+       poll_max_options, poll_max_characters_per_option, poll_min_expiration, poll_max_expiration, fetched_at
+FROM server_metadata WHERE server_id = ?"#,
+    )
+    .bind(server_id)
+    .fetch_optional(pool)
+    .await?;
+    let Some(row) = row else {
+        return Ok(None);
+    };
+
+    let metadata = entities::ServerMetadata {
+        server_id: row.get(0),
+        instance: serde_json::from_str(row.get(1))?,
+        rules: serde_json::from_str(row.get(2))?,
+        emojis: serde_json::from_str(row.get(3))?,
+        limits: entities::server_metadata::Limits {
+            max_characters: row.get(4),
+            max_media_attachments: row.get(5),
+            characters_reserved_per_url: row.get(6),
+            poll_max_options: row.get(7),
+            poll_max_characters_per_option: row.get(8),
+            poll_min_expiration: row.get(9),
+            poll_max_expiration: row.get(10),
+        },
+        fetched_at: row.get(11),
+    };
+    Ok(Some(metadata))
+}
+
+pub(crate) async fn save_server_metadata(
+    pool: &SqlitePool,
+    metadata: &entities::ServerMetadata,
+) -> DBResult<()> {
+    let mut tx = pool.begin().await?;
+
+    sqlx::query(
+        r#"
+INSERT INTO server_metadata (server_id, instance, rules, emojis, max_characters, max_media_attachments,
+  characters_reserved_per_url, poll_max_options, poll_max_characters_per_option, poll_min_expiration,
+  poll_max_expiration, fetched_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+ON CONFLICT(server_id) DO UPDATE SET instance = excluded.instance, rules = excluded.rules, emojis = excluded.emojis,
+  max_characters = excluded.max_characters, max_media_attachments = excluded.max_media_attachments,
+  characters_reserved_per_url = excluded.characters_reserved_per_url, poll_max_options = excluded.poll_max_options,
+  poll_max_characters_per_option = excluded.poll_max_characters_per_option,
+  poll_min_expiration = excluded.poll_min_expiration, poll_max_expiration = excluded.poll_max_expiration,
+  fetched_at = excluded.fetched_at"#,
+    )
+    .bind(metadata.server_id)
+    .bind(serde_json::to_string(&metadata.instance)?)
+    .bind(serde_json::to_string(&metadata.rules)?)
+    .bind(serde_json::to_string(&metadata.emojis)?)
+    .bind(metadata.limits.max_characters)
+    .bind(metadata.limits.max_media_attachments)
+    .bind(metadata.limits.characters_reserved_per_url)
+    .bind(metadata.limits.poll_max_options)
+    .bind(metadata.limits.poll_max_characters_per_option)
+    .bind(metadata.limits.poll_min_expiration)
+    .bind(metadata.limits.poll_max_expiration)
+    .bind(metadata.fetched_at)
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
 pub(crate) async fn add_account(
     pool: &SqlitePool,
     server: &entities::Server,
     account: &entities::Account,
 ) -> DBResult<entities::Account> {
     let mut tx = pool.begin().await?;
+    let created = insert_account(&mut tx, server, account).await?;
+    tx.commit().await?;
+    Ok(created)
+}
+
+/// Inserts the account and signs the server in with it in the caller's transaction.
+pub(crate) async fn insert_account(
+    conn: &mut SqliteConnection,
+    server: &entities::Server,
+    account: &entities::Account,
+) -> DBResult<entities::Account> {
     let mut created = account.clone();
 
     let res = sqlx::query("INSERT INTO accounts (username, account_id, avatar, client_id, client_secret, access_token, refresh_token, usual) VALUES (?, ?, ?, ?, ?, ?, ?, ?)")
@@ -145,7 +490,7 @@ pub(crate) async fn add_account(
         .bind(account.access_token.clone())
         .bind(account.refresh_token.clone())
         .bind(account.usual.clone())
-        .execute(&mut *tx)
+        .execute(&mut *conn)
         .await?;
     let id = res.last_insert_rowid();
     created.id = id;
@@ -153,10 +498,9 @@ pub(crate) async fn add_account(
     sqlx::query("UPDATE servers SET account_id = ? WHERE id = ?")
         .bind(id)
         .bind(server.id.clone())
-        .execute(&mut *tx)
+        .execute(&mut *conn)
         .await?;
 
-    tx.commit().await?;
     Ok(created)
 }
 
@@ -165,9 +509,12 @@ pub(crate) async fn list_timelines(
 ) -> DBResult<Vec<(entities::Timeline, entities::Server)>> {
     let timelines = sqlx::query(
         r#"
-SELECT timelines.id, timelines.server_id, timelines.kind, timelines.name, timelines.sort, timelines.list_id, timelines.column_width, timelines.show_boosts, timelines.show_replies,
-       servers.id, servers.domain, servers.base_url, servers.sns, servers.favicon, servers.account_id
-FROM timelines INNER JOIN servers ON servers.id = timelines.server_id ORDER BY timelines.sort"#,
+SELECT timelines.id, timelines.server_id, timelines.kind, timelines.name, timelines.sort, timelines.list_id, timelines.column_width, timelines.show_boosts, timelines.show_replies, timelines.workspace_id,
+       timelines.remote_account_id, timelines.remote_acct, timelines.show_pinned, timelines.notification_filter,
+       servers.id, servers.domain, servers.base_url, servers.sns, servers.favicon, servers.account_id, servers.ca_certificate,
+       servers.software, servers.software_version
+FROM timelines INNER JOIN servers ON servers.id = timelines.server_id
+WHERE timelines.workspace_id = (SELECT id FROM workspaces WHERE active = TRUE) ORDER BY timelines.sort"#,
     )
     .map(|row: SqliteRow| {
         (
@@ -181,14 +528,22 @@ FROM timelines INNER JOIN servers ON servers.id = timelines.server_id ORDER BY t
                 column_width: row.get(6),
                 show_boosts: row.get(7),
                 show_replies: row.get(8),
+                workspace_id: row.get(9),
+                remote_account_id: row.get(10),
+                remote_acct: row.get(11),
+                show_pinned: row.get(12),
+                notification_filter: row.get(13),
             },
             entities::Server {
-                id: row.get(9),
-                domain: row.get(10),
-                base_url: row.get(11),
-                sns: row.get(12),
-                favicon: row.get(13),
-                account_id: row.get(14),
+                id: row.get(14),
+                domain: row.get(15),
+                base_url: row.get(16),
+                sns: row.get(17),
+                favicon: row.get(18),
+                account_id: row.get(19),
+                ca_certificate: row.get(20),
+                software: row.get(21),
+                software_version: row.get(22),
             },
         )
     })
@@ -198,6 +553,14 @@ FROM timelines INNER JOIN servers ON servers.id = timelines.server_id ORDER BY t
     Ok(timelines)
 }
 
+pub(crate) async fn list_all_timelines(pool: &SqlitePool) -> DBResult<Vec<entities::Timeline>> {
+    let timelines = query_as::<_, entities::Timeline>("SELECT * FROM timelines ORDER BY sort")
+        .fetch_all(pool)
+        .await?;
+
+    Ok(timelines)
+}
+
 pub(crate) async fn get_timeline(
     pool: &SqlitePool,
     server: &entities::Server,
@@ -206,9 +569,13 @@ pub(crate) async fn get_timeline(
 ) -> DBResult<entities::Timeline> {
     let timeline = sqlx::query(
         r#"
-SELECT timelines.id, timelines.server_id, timelines.kind, timelines.name, timelines.sort, timelines.list_id, timelines.column_width, timelines.show_boosts, timelines.show_replies
-FROM timelines INNER JOIN servers ON servers.id = timelines.server_id WHERE timelines.name = ? AND servers.id = ? AND timelines.kind = ?"#
-    ).bind(name).bind(server.id).bind(kind)
+SELECT timelines.id, timelines.server_id, timelines.kind, timelines.name, timelines.sort, timelines.list_id, timelines.column_width, timelines.show_boosts, timelines.show_replies, timelines.workspace_id,
+       timelines.remote_account_id, timelines.remote_acct, timelines.show_pinned, timelines.notification_filter
+FROM timelines INNER JOIN servers ON servers.id = timelines.server_id
+WHERE (timelines.name = ? OR (timelines.kind = 'account' AND timelines.remote_acct = ?)) AND servers.id = ? AND timelines.kind = ?
+  AND timelines.workspace_id = (SELECT id FROM workspaces WHERE active = TRUE)
+ORDER BY timelines.sort LIMIT 1"#
+    ).bind(name).bind(name.trim_start_matches('@')).bind(server.id).bind(kind)
     .map(|row: SqliteRow| {
         entities::Timeline {
                 id: row.get(0),
@@ -220,6 +587,11 @@ FROM timelines INNER JOIN servers ON servers.id = timelines.server_id WHERE time
                 column_width: row.get(6),
                 show_boosts: row.get(7),
                 show_replies: row.get(8),
+                workspace_id: row.get(9),
+                remote_account_id: row.get(10),
+                remote_acct: row.get(11),
+                show_pinned: row.get(12),
+                notification_filter: row.get(13),
         }
     })
 
@@ -228,46 +600,60 @@ FROM timelines INNER JOIN servers ON servers.id = timelines.server_id WHERE time
     Ok(timeline)
 }
 
+pub(crate) async fn find_timeline(pool: &SqlitePool, id: i64) -> DBResult<entities::Timeline> {
+    let timeline = query_as::<_, entities::Timeline>("SELECT * FROM timelines WHERE id = ?")
+        .bind(id)
+        .fetch_one(pool)
+        .await?;
+
+    Ok(timeline)
+}
+
 pub(crate) async fn add_timeline(
     pool: &SqlitePool,
-    server: &entities::Server,
-    kind: &entities::timeline::Kind,
-    name: &str,
-    list_id: Option<&str>,
-    column_width: &entities::timeline::ColumnWidth,
+    timeline: &entities::Timeline,
 ) -> DBResult<entities::Timeline> {
     let mut tx = pool.begin().await?;
+    let created = insert_timeline(&mut tx, timeline).await?;
+    tx.commit().await?;
 
-    let exists = query_as::<_, entities::Timeline>("SELECT * FROM timelines ORDER BY sort DESC")
-        .fetch_all(&mut *tx)
+    Ok(created)
+}
+
+/// Inserts the timeline at the end in the caller's transaction.
+pub(crate) async fn insert_timeline(
+    conn: &mut SqliteConnection,
+    timeline: &entities::Timeline,
+) -> DBResult<entities::Timeline> {
+    let max_sort = sqlx::query_scalar::<_, i64>("SELECT COALESCE(MAX(sort), 0) FROM timelines")
+        .fetch_one(&mut *conn)
         .await?;
 
-    let mut sort = 1;
-    if exists.len() > 0 {
-        sort = exists[0].sort + 1;
-    }
     let res = sqlx::query(
-        "INSERT INTO timelines (server_id, kind, name, sort, list_id) VALUES (?, ?, ?, ?, ?)",
+        "INSERT INTO timelines (server_id, kind, name, sort, list_id, column_width, show_boosts, show_replies, workspace_id, remote_account_id, remote_acct, show_pinned, notification_filter) VALUES (?, ?, ?, ?, ?, ?, ?, ?, COALESCE(?, (SELECT id FROM workspaces WHERE active = TRUE)), ?, ?, ?, ?)",
     )
-    .bind(server.id)
-    .bind(kind)
-    .bind(name)
-    .bind::<i64>(sort)
-    .bind(list_id)
-    .execute(&mut *tx)
+    .bind(timeline.server_id)
+    .bind(&timeline.kind)
+    .bind(&timeline.name)
+    .bind(max_sort + 1)
+    .bind(&timeline.list_id)
+    .bind(&timeline.column_width)
+    .bind(timeline.show_boosts)
+    .bind(timeline.show_replies)
+    .bind(timeline.workspace_id)
+    .bind(&timeline.remote_account_id)
+    .bind(&timeline.remote_acct)
+    .bind(timeline.show_pinned)
+    .bind(timeline.notification_filter)
+    .execute(&mut *conn)
     .await?;
     let id = res.last_insert_rowid();
-    tx.commit().await?;
 
-    let created = entities::Timeline::new(
-        id,
-        server.id,
-        kind.clone(),
-        name.to_string(),
-        1,
-        list_id.map(|i| i.to_string()),
-        column_width.clone(),
-    );
+    let created = query_as::<_, entities::Timeline>("SELECT * FROM timelines WHERE id = ?")
+        .bind(id)
+        .fetch_one(&mut *conn)
+        .await?;
+
     Ok(created)
 }
 
@@ -286,9 +672,11 @@ pub(crate) async fn remove_timeline(pool: &SqlitePool, id: i64) -> DBResult<()>
 pub(crate) async fn switch_left_timeline(pool: &SqlitePool, id: i64) -> DBResult<()> {
     let mut tx = pool.begin().await?;
 
-    let exists = query_as::<_, entities::Timeline>("SELECT * FROM timelines ORDER BY sort")
-        .fetch_all(&mut *tx)
-        .await?;
+    let exists = query_as::<_, entities::Timeline>(
+        "SELECT * FROM timelines WHERE workspace_id = (SELECT id FROM workspaces WHERE active = TRUE) ORDER BY sort",
+    )
+    .fetch_all(&mut *tx)
+    .await?;
 
     let find = exists.iter().position(|e| e.id == id);
     if let Some(index) = find {
@@ -322,9 +710,11 @@ pub(crate) async fn switch_left_timeline(pool: &SqlitePool, id: i64) -> DBResult
 pub(crate) async fn switch_right_timeline(pool: &SqlitePool, id: i64) -> DBResult<()> {
     let mut tx = pool.begin().await?;
 
-    let exists = query_as::<_, entities::Timeline>("SELECT * FROM timelines ORDER BY sort")
-        .fetch_all(&mut *tx)
-        .await?;
+    let exists = query_as::<_, entities::Timeline>(
+        "SELECT * FROM timelines WHERE workspace_id = (SELECT id FROM workspaces WHERE active = TRUE) ORDER BY sort",
+    )
+    .fetch_all(&mut *tx)
+    .await?;
 
     let find = exists.iter().position(|e| e.id == id);
     if let Some(index) = find {
@@ -355,6 +745,82 @@ pub(crate) async fn switch_right_timeline(pool: &SqlitePool, id: i64) -> DBResul
     Ok(())
 }
 
+pub(crate) async fn move_timeline(pool: &SqlitePool, id: i64, new_index: usize) -> DBResult<()> {
+    let mut tx = pool.begin().await?;
+
+    let mut ids = sqlx::query_scalar::<_, i64>(
+        "SELECT id FROM timelines WHERE workspace_id = (SELECT id FROM workspaces WHERE active = TRUE) ORDER BY sort",
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let Some(index) = ids.iter().position(|i| *i == id) else {
+        return Err(Error::not_found(format!("Timeline {} does not exist", id)));
+    };
+    let target = ids.remove(index);
+    let new_index = std::cmp::min(new_index, ids.len());
+    ids.insert(new_index, target);
+
+    rewrite_timeline_sort(&mut tx, &ids).await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+pub(crate) async fn reorder_timelines(pool: &SqlitePool, ids: &[i64]) -> DBResult<()> {
+    let mut tx = pool.begin().await?;
+
+    let mut exists = sqlx::query_scalar::<_, i64>(
+        "SELECT id FROM timelines WHERE workspace_id = (SELECT id FROM workspaces WHERE active = TRUE)",
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+    exists.sort_unstable();
+    let mut requested = ids.to_vec();
+    requested.sort_unstable();
+    if exists != requested {
+        return Err(Error::validation(
+            "Timeline ids must contain every timeline exactly once",
+        ));
+    }
+
+    rewrite_timeline_sort(&mut tx, ids).await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+// sort is unique across all workspaces, so the given timelines only swap the sort values they already hold.
+// Every row is moved out of the way first to keep the unique constraint satisfied while rewriting.
+async fn rewrite_timeline_sort(conn: &mut SqliteConnection, ids: &[i64]) -> DBResult<()> {
+    let mut sorts: Vec<i64> = Vec::with_capacity(ids.len());
+    for id in ids.iter() {
+        let sort = sqlx::query_scalar::<_, i64>("SELECT sort FROM timelines WHERE id = ?")
+            .bind(id)
+            .fetch_one(&mut *conn)
+            .await?;
+        sorts.push(sort);
+    }
+    sorts.sort_unstable();
+
+    for (index, id) in ids.iter().enumerate() {
+        sqlx::query("UPDATE timelines SET sort = ? WHERE id = ?")
+            .bind(-(index as i64) - 1)
+            .bind(id)
+            .execute(&mut *conn)
+            .await?;
+    }
+    for (id, sort) in ids.iter().zip(sorts) {
+        sqlx::query("UPDATE timelines SET sort = ? WHERE id = ?")
+            .bind(sort)
+            .bind(id)
+            .execute(&mut *conn)
+            .await?;
+    }
+
+    Ok(())
+}
+
 pub(crate) async fn update_column_width(
     pool: &SqlitePool,
     id: i64,
@@ -406,6 +872,54 @@ pub(crate) async fn update_show_replies(
     Ok(())
 }
 
+pub(crate) async fn update_show_pinned(
+    pool: &SqlitePool,
+    id: i64,
+    show_pinned: bool,
+) -> DBResult<()> {
+    let mut tx = pool.begin().await?;
+
+    sqlx::query("UPDATE timelines SET show_pinned = ? WHERE id = ?")
+        .bind(show_pinned)
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+pub(crate) async fn update_notification_filter(
+    pool: &SqlitePool,
+    id: i64,
+    notification_filter: Option<entities::timeline::NotificationFilter>,
+) -> DBResult<()> {
+    let mut tx = pool.begin().await?;
+
+    sqlx::query("UPDATE timelines SET notification_filter = ? WHERE id = ?")
+        .bind(notification_filter)
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+pub(crate) async fn list_notification_filters(
+    pool: &SqlitePool,
+    server_id: i64,
+) -> DBResult<Vec<(i64, Option<entities::timeline::NotificationFilter>)>> {
+    let filters = sqlx::query_as::<_, (i64, Option<entities::timeline::NotificationFilter>)>(
+        "SELECT id, notification_filter FROM timelines WHERE server_id = ? AND kind = 'notifications'",
+    )
+    .bind(server_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(filters)
+}
+
 pub(crate) async fn get_account(
     pool: &SqlitePool,
     id: i64,
@@ -414,7 +928,8 @@ pub(crate) async fn get_account(
         r#"
 SELECT accounts.id, accounts.username, accounts.account_id, accounts.avatar, accounts.client_id, accounts.client_secret,
        accounts.access_token, accounts.refresh_token, accounts.usual, servers.id, servers.domain, servers.base_url, servers.sns,
-       servers.favicon, servers.account_id
+       servers.favicon, servers.account_id, servers.ca_certificate,
+       servers.software, servers.software_version
 FROM accounts INNER JOIN servers ON accounts.id = servers.account_id WHERE accounts.id = ?"#
         )
         .bind(id)
@@ -438,6 +953,9 @@ FROM accounts INNER JOIN servers ON accounts.id = servers.account_id WHERE accou
                     sns: row.get(12),
                     favicon: row.get(13),
                     account_id: row.get(14),
+                    ca_certificate: row.get(15),
+                    software: row.get(16),
+                    software_version: row.get(17),
                 },
             )
         }).fetch_one(pool)
@@ -453,7 +971,8 @@ pub(crate) async fn list_account(
         r#"
 SELECT accounts.id, accounts.username, accounts.account_id, accounts.avatar, accounts.client_id, accounts.client_secret,
        accounts.access_token, accounts.refresh_token, accounts.usual, servers.id, servers.domain, servers.base_url, servers.sns,
-       servers.favicon, servers.account_id
+       servers.favicon, servers.account_id, servers.ca_certificate,
+       servers.software, servers.software_version
 FROM accounts INNER JOIN servers ON accounts.id = servers.account_id"#
         ).map(|row: SqliteRow| {
             (
@@ -475,6 +994,9 @@ FROM accounts INNER JOIN servers ON accounts.id = servers.account_id"#
                     sns: row.get(12),
                     favicon: row.get(13),
                     account_id: row.get(14),
+                    ca_certificate: row.get(15),
+                    software: row.get(16),
+                    software_version: row.get(17),
                 },
             )
         }).fetch_all(pool)
@@ -506,6 +1028,206 @@ pub(crate) async fn set_usual_account(pool: &SqlitePool, id: i64) -> DBResult<en
     Ok(account)
 }
 
+pub(crate) async fn get_account_settings(
+    pool: &SqlitePool,
+    account_id: i64,
+) -> DBResult<Option<entities::AccountSettings>> {
+    let settings = query_as::<_, entities::AccountSettings>(
+        "SELECT * FROM account_settings WHERE account_id = ?",
+    )
+    .bind(account_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(settings)
+}
+
+pub(crate) async fn update_account_settings(
+    pool: &SqlitePool,
+    settings: &entities::AccountSettings,
+) -> DBResult<()> {
+    let mut tx = pool.begin().await?;
+
+    sqlx::query(
+        r#"
+INSERT INTO account_settings (account_id, confirm_reblog, default_visibility, default_language) VALUES (?, ?, ?, ?)
+ON CONFLICT(account_id) DO UPDATE SET confirm_reblog = excluded.confirm_reblog, default_visibility = excluded.default_visibility, default_language = excluded.default_language"#,
+    )
+    .bind(settings.account_id)
+    .bind(settings.confirm_reblog)
+    .bind(&settings.default_visibility)
+    .bind(&settings.default_language)
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+pub(crate) async fn get_timeline_settings(
+    pool: &SqlitePool,
+    timeline_id: i64,
+) -> DBResult<Option<entities::TimelineSettings>> {
+    let settings = query_as::<_, entities::TimelineSettings>(
+        "SELECT * FROM timeline_settings WHERE timeline_id = ?",
+    )
+    .bind(timeline_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(settings)
+}
+
+pub(crate) async fn update_timeline_settings(
+    pool: &SqlitePool,
+    settings: &entities::TimelineSettings,
+) -> DBResult<()> {
+    let mut tx = pool.begin().await?;
+
+    sqlx::query(
+        r#"
+INSERT INTO timeline_settings (timeline_id, font_size, media_display) VALUES (?, ?, ?)
+ON CONFLICT(timeline_id) DO UPDATE SET font_size = excluded.font_size, media_display = excluded.media_display"#,
+    )
+    .bind(settings.timeline_id)
+    .bind(settings.font_size)
+    .bind(&settings.media_display)
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+pub(crate) async fn list_workspaces(pool: &SqlitePool) -> DBResult<Vec<entities::Workspace>> {
+    let workspaces = query_as::<_, entities::Workspace>("SELECT * FROM workspaces ORDER BY sort")
+        .fetch_all(pool)
+        .await?;
+
+    Ok(workspaces)
+}
+
+pub(crate) async fn add_workspace(
+    pool: &SqlitePool,
+    name: &str,
+    icon: Option<&str>,
+) -> DBResult<entities::Workspace> {
+    let mut tx = pool.begin().await?;
+    let created = insert_workspace(&mut tx, name, icon).await?;
+    tx.commit().await?;
+
+    Ok(created)
+}
+
+/// Inserts the inactive workspace at the end in the caller's transaction.
+pub(crate) async fn insert_workspace(
+    conn: &mut SqliteConnection,
+    name: &str,
+    icon: Option<&str>,
+) -> DBResult<entities::Workspace> {
+    let max_sort = sqlx::query_scalar::<_, i64>("SELECT COALESCE(MAX(sort), 0) FROM workspaces")
+        .fetch_one(&mut *conn)
+        .await?;
+
+    let res = sqlx::query("INSERT INTO workspaces (name, icon, sort, active) VALUES (?, ?, ?, ?)")
+        .bind(name)
+        .bind(icon)
+        .bind(max_sort + 1)
+        .bind(false)
+        .execute(&mut *conn)
+        .await?;
+    let id = res.last_insert_rowid();
+
+    let created = query_as::<_, entities::Workspace>("SELECT * FROM workspaces WHERE id = ?")
+        .bind(id)
+        .fetch_one(&mut *conn)
+        .await?;
+
+    Ok(created)
+}
+
+pub(crate) async fn update_workspace(
+    pool: &SqlitePool,
+    id: i64,
+    name: &str,
+    icon: Option<&str>,
+) -> DBResult<()> {
+    let mut tx = pool.begin().await?;
+
+    sqlx::query("UPDATE workspaces SET name = ?, icon = ? WHERE id = ?")
+        .bind(name)
+        .bind(icon)
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+
+    Ok(())
+}
+
+pub(crate) async fn remove_workspace(pool: &SqlitePool, id: i64) -> DBResult<()> {
+    let mut tx = pool.begin().await?;
+
+    let exists = query_as::<_, entities::Workspace>("SELECT * FROM workspaces ORDER BY sort")
+        .fetch_all(&mut *tx)
+        .await?;
+    let Some(target) = exists.iter().find(|w| w.id == id) else {
+        return Err(Error::not_found(format!("Workspace {} does not exist", id)));
+    };
+    if exists.len() <= 1 {
+        return Err(Error::validation("The last workspace can not be removed"));
+    }
+
+    sqlx::query("DELETE FROM workspaces WHERE id = ?")
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+
+    if target.active {
+        if let Some(next) = exists.iter().find(|w| w.id != id) {
+            sqlx::query("UPDATE workspaces SET active = ? WHERE id = ?")
+                .bind(true)
+                .bind(next.id)
+                .execute(&mut *tx)
+                .await?;
+        }
+    }
+    tx.commit().await?;
+
+    Ok(())
+}
+
+pub(crate) async fn set_active_workspace(
+    pool: &SqlitePool,
+    id: i64,
+) -> DBResult<entities::Workspace> {
+    let mut tx = pool.begin().await?;
+
+    let workspace = query_as::<_, entities::Workspace>("SELECT * FROM workspaces WHERE id = ?")
+        .bind(id)
+        .fetch_one(&mut *tx)
+        .await?;
+
+    sqlx::query("UPDATE workspaces SET active = ? WHERE id = ?")
+        .bind(true)
+        .bind(workspace.id)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query("UPDATE workspaces SET active = ? WHERE id != ?")
+        .bind(false)
+        .bind(workspace.id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+
+    let workspace = query_as::<_, entities::Workspace>("SELECT * FROM workspaces WHERE id = ?")
+        .bind(id)
+        .fetch_one(pool)
+        .await?;
+
+    Ok(workspace)
+}
+
 pub(crate) async fn get_instruction(pool: &SqlitePool) -> DBResult<entities::Instruction> {
     let instruction = query_as::<_, entities::Instruction>("SELECT * FROM instructions")
         .fetch_one(pool)
@@ -562,3 +1284,369 @@ pub(crate) async fn update_instruction(
     tx.commit().await?;
     Ok(instruction)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::migrate::Migrate;
+
+    const UNIQUE_SERVERS_VERSION: i64 = 9;
+
+    // Applies the migrations before unique servers, so that the rows which they allowed can be inserted.
+    async fn create_pool_before_unique_servers() -> SqlitePool {
+        let pool = create_memory_pool().await.unwrap();
+        let migrator = sqlx::migrate!("./db");
+        let mut conn = pool.acquire().await.unwrap();
+        conn.ensure_migrations_table().await.unwrap();
+        for migration in migrator
+            .iter()
+            .filter(|m| m.version < UNIQUE_SERVERS_VERSION)
+        {
+            conn.apply(migration).await.unwrap();
+        }
+        pool
+    }
+
+    async fn seed_server(pool: &SqlitePool, base_url: &str, account_id: Option<i64>) -> i64 {
+        if let Some(account_id) = account_id {
+            sqlx::query("INSERT INTO accounts (id, username, account_id, client_secret, access_token) VALUES (?, 'alice', ?, 'secret', 'token')")
+                .bind(account_id)
+                .bind(account_id.to_string())
+                .execute(pool)
+                .await
+                .unwrap();
+        }
+        sqlx::query("INSERT INTO servers (domain, base_url, sns, account_id) VALUES ('example.social', ?, 'mastodon', ?)")
+            .bind(base_url)
+            .bind(account_id)
+            .execute(pool)
+            .await
+            .unwrap()
+            .last_insert_rowid()
+    }
+
+    async fn seed_timeline(pool: &SqlitePool, server_id: i64, sort: i64) {
+        sqlx::query(
+            "INSERT INTO timelines (server_id, kind, name, sort) VALUES (?, 'local', 'Local', ?)",
+        )
+        .bind(server_id)
+        .bind(sort)
+        .execute(pool)
+        .await
+        .unwrap();
+    }
+
+    async fn create_migrated_pool() -> SqlitePool {
+        let pool = create_memory_pool().await.unwrap();
+        migrate_database(&pool).await.unwrap();
+        pool
+    }
+
+    async fn add_local_timeline(pool: &SqlitePool, server_id: i64) -> entities::Timeline {
+        add_timeline(
+            pool,
+            &entities::Timeline::new(
+                0,
+                server_id,
+                entities::timeline::Kind::Local,
+                "Local".to_string(),
+                0,
+                None,
+                entities::timeline::ColumnWidth::SM,
+            ),
+        )
+        .await
+        .unwrap()
+    }
+
+    async fn active_timeline_ids(pool: &SqlitePool) -> Vec<i64> {
+        list_timelines(pool)
+            .await
+            .unwrap()
+            .iter()
+            .map(|(t, _)| t.id)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_migration_keeps_accounts_on_the_same_instance() {
+        let pool = create_pool_before_unique_servers().await;
+        let first = seed_server(&pool, "https://example.social", Some(1)).await;
+        let second = seed_server(&pool, "https://example.social", Some(2)).await;
+        seed_timeline(&pool, first, 1).await;
+        seed_timeline(&pool, second, 2).await;
+
+        migrate_database(&pool).await.unwrap();
+
+        let servers = list_servers(&pool).await.unwrap();
+        assert_eq!(servers.len(), 2);
+        assert!(servers.iter().all(|(_, account)| account.is_some()));
+        let accounts = list_account(&pool).await.unwrap();
+        assert_eq!(accounts.len(), 2);
+        let timelines = list_all_timelines(&pool).await.unwrap();
+        assert_eq!(timelines.iter().filter(|t| t.server_id == first).count(), 1);
+        assert_eq!(
+            timelines.iter().filter(|t| t.server_id == second).count(),
+            1
+        );
+
+        // Another account can still sign in to the same instance.
+        seed_server(&pool, "https://example.social", Some(3)).await;
+    }
+
+    #[tokio::test]
+    async fn test_migration_merges_anonymous_servers() {
+        let pool = create_pool_before_unique_servers().await;
+        let kept = seed_server(&pool, "https://example.social", None).await;
+        let duplicate = seed_server(&pool, "https://example.social", None).await;
+        let not_normalized = seed_server(&pool, "https://Example.Social/", None).await;
+        let signed_in = seed_server(&pool, "https://EXAMPLE.social", Some(1)).await;
+        seed_timeline(&pool, duplicate, 1).await;
+        seed_timeline(&pool, not_normalized, 2).await;
+        seed_timeline(&pool, signed_in, 3).await;
+
+        migrate_database(&pool).await.unwrap();
+
+        let servers = list_servers(&pool).await.unwrap();
+        let mut ids: Vec<i64> = servers.iter().map(|(s, _)| s.id).collect();
+        ids.sort();
+        assert_eq!(ids, vec![kept, signed_in]);
+        assert!(servers
+            .iter()
+            .all(|(s, _)| s.base_url == "https://example.social"));
+        let timelines = list_all_timelines(&pool).await.unwrap();
+        assert_eq!(timelines.iter().filter(|t| t.server_id == kept).count(), 2);
+        assert_eq!(
+            timelines
+                .iter()
+                .filter(|t| t.server_id == signed_in)
+                .count(),
+            1
+        );
+
+        let res = add_server(
+            &pool,
+            entities::Server::new(
+                0,
+                "example.social".to_string(),
+                "https://example.social".to_string(),
+                "mastodon".to_string(),
+                None,
+            ),
+        )
+        .await;
+        assert_eq!(
+            res.unwrap_err().code,
+            crate::error::ErrorCode::AlreadyExists
+        );
+    }
+
+    #[tokio::test]
+    async fn test_normalize_servers_converts_idn_to_punycode() {
+        let pool = create_pool_before_unique_servers().await;
+        seed_server(&pool, "https://例え.jp/", None).await;
+
+        migrate_database(&pool).await.unwrap();
+
+        let servers = list_servers(&pool).await.unwrap();
+        assert_eq!(servers[0].0.base_url, "https://xn--r8jz45g.jp");
+        assert_eq!(servers[0].0.domain, "xn--r8jz45g.jp");
+    }
+
+    #[tokio::test]
+    async fn test_get_timeline_matches_remote_acct_only_for_accounts() {
+        let pool = create_memory_pool().await.unwrap();
+        migrate_database(&pool).await.unwrap();
+        let server_id = seed_server(&pool, "https://example.social", None).await;
+        sqlx::query("INSERT INTO timelines (server_id, kind, name, sort, remote_acct, workspace_id) VALUES (?, 'account', 'Alice', 1, 'alice@example.social', 1), (?, 'tag', 'rust', 2, 'alice@example.social', 1)")
+            .bind(server_id)
+            .bind(server_id)
+            .execute(&pool)
+            .await
+            .unwrap();
+        let server = get_server(&pool, server_id).await.unwrap();
+
+        let timeline = get_timeline(
+            &pool,
+            &server,
+            &entities::timeline::Kind::Account,
+            "@alice@example.social",
+        )
+        .await
+        .unwrap();
+        assert_eq!(timeline.name, "Alice");
+        assert!(get_timeline(
+            &pool,
+            &server,
+            &entities::timeline::Kind::Tag,
+            "alice@example.social"
+        )
+        .await
+        .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_add_timeline_stores_column_width_and_sort() {
+        let pool = create_migrated_pool().await;
+        let server_id = seed_server(&pool, "https://example.social", None).await;
+
+        let first = add_local_timeline(&pool, server_id).await;
+        let second = add_local_timeline(&pool, server_id).await;
+
+        assert_eq!(first.column_width, entities::timeline::ColumnWidth::SM);
+        assert_eq!(first.sort, 1);
+        assert_eq!(second.sort, 2);
+        let stored = find_timeline(&pool, second.id).await.unwrap();
+        assert_eq!(stored.column_width, entities::timeline::ColumnWidth::SM);
+        assert_eq!(stored.sort, second.sort);
+    }
+
+    #[tokio::test]
+    async fn test_move_timeline() {
+        let pool = create_migrated_pool().await;
+        let server_id = seed_server(&pool, "https://example.social", None).await;
+        let mut ids = Vec::new();
+        for _ in 0..3 {
+            ids.push(add_local_timeline(&pool, server_id).await.id);
+        }
+
+        move_timeline(&pool, ids[2], 0).await.unwrap();
+        assert_eq!(
+            active_timeline_ids(&pool).await,
+            vec![ids[2], ids[0], ids[1]]
+        );
+
+        move_timeline(&pool, ids[2], 2).await.unwrap();
+        assert_eq!(active_timeline_ids(&pool).await, ids);
+
+        // An index past the end moves the timeline to the last.
+        move_timeline(&pool, ids[0], 10).await.unwrap();
+        assert_eq!(
+            active_timeline_ids(&pool).await,
+            vec![ids[1], ids[2], ids[0]]
+        );
+
+        let res = move_timeline(&pool, 100, 0).await;
+        assert_eq!(res.unwrap_err().code, crate::error::ErrorCode::NotFound);
+    }
+
+    #[tokio::test]
+    async fn test_reorder_timelines() {
+        let pool = create_migrated_pool().await;
+        let server_id = seed_server(&pool, "https://example.social", None).await;
+        let mut ids = Vec::new();
+        for _ in 0..3 {
+            ids.push(add_local_timeline(&pool, server_id).await.id);
+        }
+        let other = add_workspace(&pool, "Work", None).await.unwrap();
+        set_active_workspace(&pool, other.id).await.unwrap();
+        let other_id = add_local_timeline(&pool, server_id).await.id;
+        let default = list_workspaces(&pool).await.unwrap()[0].id;
+        set_active_workspace(&pool, default).await.unwrap();
+
+        reorder_timelines(&pool, &[ids[1], ids[2], ids[0]])
+            .await
+            .unwrap();
+        assert_eq!(
+            active_timeline_ids(&pool).await,
+            vec![ids[1], ids[2], ids[0]]
+        );
+
+        for invalid in [
+            vec![ids[1], ids[2]],
+            vec![ids[1], ids[2], ids[0], other_id],
+            vec![ids[1], ids[2], other_id],
+            vec![ids[1], ids[1], ids[0]],
+        ] {
+            let res = reorder_timelines(&pool, &invalid).await;
+            assert_eq!(res.unwrap_err().code, crate::error::ErrorCode::Validation);
+        }
+        assert_eq!(
+            active_timeline_ids(&pool).await,
+            vec![ids[1], ids[2], ids[0]]
+        );
+        assert_eq!(find_timeline(&pool, other_id).await.unwrap().sort, 4);
+    }
+
+    #[tokio::test]
+    async fn test_add_update_and_switch_workspaces() {
+        let pool = create_migrated_pool().await;
+        let server_id = seed_server(&pool, "https://example.social", None).await;
+        let default = list_workspaces(&pool).await.unwrap()[0].clone();
+        assert!(default.active);
+        let in_default = add_local_timeline(&pool, server_id).await;
+
+        let work = add_workspace(&pool, "Work", Some("briefcase"))
+            .await
+            .unwrap();
+        assert!(!work.active);
+        assert_eq!(work.sort, default.sort + 1);
+        update_workspace(&pool, work.id, "Office", None)
+            .await
+            .unwrap();
+        let renamed = list_workspaces(&pool).await.unwrap()[1].clone();
+        assert_eq!(renamed.name, "Office");
+        assert_eq!(renamed.icon, None);
+
+        let switched = set_active_workspace(&pool, work.id).await.unwrap();
+        assert!(switched.active);
+        let workspaces = list_workspaces(&pool).await.unwrap();
+        assert_eq!(workspaces.iter().filter(|w| w.active).count(), 1);
+        assert!(active_timeline_ids(&pool).await.is_empty());
+
+        let in_work = add_local_timeline(&pool, server_id).await;
+        assert_eq!(in_work.workspace_id, Some(work.id));
+        assert_eq!(active_timeline_ids(&pool).await, vec![in_work.id]);
+
+        set_active_workspace(&pool, default.id).await.unwrap();
+        assert_eq!(active_timeline_ids(&pool).await, vec![in_default.id]);
+    }
+
+    #[tokio::test]
+    async fn test_remove_workspace_removes_its_timelines() {
+        let pool = create_migrated_pool().await;
+        let server_id = seed_server(&pool, "https://example.social", None).await;
+        let default = list_workspaces(&pool).await.unwrap()[0].clone();
+        let in_default = add_local_timeline(&pool, server_id).await;
+        let work = add_workspace(&pool, "Work", None).await.unwrap();
+        set_active_workspace(&pool, work.id).await.unwrap();
+        add_local_timeline(&pool, server_id).await;
+
+        remove_workspace(&pool, work.id).await.unwrap();
+
+        let workspaces = list_workspaces(&pool).await.unwrap();
+        assert_eq!(workspaces.len(), 1);
+        assert!(workspaces[0].active);
+        let timelines = list_all_timelines(&pool).await.unwrap();
+        assert_eq!(timelines.len(), 1);
+        assert_eq!(timelines[0].id, in_default.id);
+
+        let res = remove_workspace(&pool, default.id).await;
+        assert_eq!(res.unwrap_err().code, crate::error::ErrorCode::Validation);
+        let res = remove_workspace(&pool, work.id).await;
+        assert_eq!(res.unwrap_err().code, crate::error::ErrorCode::NotFound);
+    }
+
+    #[tokio::test]
+    async fn test_get_timeline_finds_only_the_active_workspace() {
+        let pool = create_migrated_pool().await;
+        let server_id = seed_server(&pool, "https://example.social", None).await;
+        let server = get_server(&pool, server_id).await.unwrap();
+        let default = list_workspaces(&pool).await.unwrap()[0].id;
+        let in_default = add_local_timeline(&pool, server_id).await;
+        let work = add_workspace(&pool, "Work", None).await.unwrap();
+        set_active_workspace(&pool, work.id).await.unwrap();
+        let in_work = add_local_timeline(&pool, server_id).await;
+
+        let found = get_timeline(&pool, &server, &entities::timeline::Kind::Local, "Local")
+            .await
+            .unwrap();
+        assert_eq!(found.id, in_work.id);
+
+        set_active_workspace(&pool, default).await.unwrap();
+        let found = get_timeline(&pool, &server, &entities::timeline::Kind::Local, "Local")
+            .await
+            .unwrap();
+        assert_eq!(found.id, in_default.id);
+    }
+}
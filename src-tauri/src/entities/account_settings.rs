@@ -0,0 +1,13 @@
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+use crate::settings::Visibility;
+
+/// Overrides of the global settings for an account. None means to use the global value.
+#[derive(Debug, Serialize, Deserialize, FromRow, Clone, Default)]
+pub struct AccountSettings {
+    pub account_id: i64,
+    pub confirm_reblog: Option<bool>,
+    pub default_visibility: Option<Visibility>,
+    pub default_language: Option<String>,
+}
@@ -0,0 +1,15 @@
+use sqlx::FromRow;
+
+/// Favicon of a server which is downloaded, so the webview does not request it from the server.
+#[derive(Debug, FromRow, Clone, PartialEq, Eq)]
+pub struct Favicon {
+    pub server_id: i64,
+    /// URL which the icon is downloaded from.
+    pub url: String,
+    pub content_type: String,
+    pub data: Vec<u8>,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    /// Unix time when the icon was downloaded or revalidated.
+    pub fetched_at: i64,
+}
@@ -1,9 +1,19 @@
 pub mod account;
+pub mod account_settings;
+pub mod favicon;
 pub mod instruction;
 pub mod server;
+pub mod server_metadata;
 pub mod timeline;
+pub mod timeline_settings;
+pub mod workspace;
 
 pub use account::Account;
+pub use account_settings::AccountSettings;
+pub use favicon::Favicon;
 pub use instruction::Instruction;
 pub use server::Server;
+pub use server_metadata::ServerMetadata;
 pub use timeline::Timeline;
+pub use timeline_settings::TimelineSettings;
+pub use workspace::Workspace;
@@ -9,6 +9,11 @@ pub struct Server {
     pub sns: String,
     pub favicon: Option<String>,
     pub account_id: Option<i64>,
+    /// Extra root certificates in PEM for self-hosted servers with an internal CA.
+    pub ca_certificate: Option<String>,
+    /// Software name and version from nodeinfo, which are more precise than `sns`.
+    pub software: Option<String>,
+    pub software_version: Option<String>,
 }
 
 impl Server {
@@ -26,6 +31,9 @@ impl Server {
             favicon,
             sns,
             account_id: None,
+            ca_certificate: None,
+            software: None,
+            software_version: None,
         }
     }
 }
@@ -0,0 +1,50 @@
+use megalodon::entities::{instance::InstanceRule, Emoji, Instance};
+use serde::Serialize;
+
+/// Instance information which is cached, so compose and reports do not wait for the server.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct ServerMetadata {
+    pub server_id: i64,
+    pub instance: Instance,
+    pub rules: Vec<InstanceRule>,
+    pub emojis: Vec<Emoji>,
+    pub limits: Limits,
+    /// Unix time when the metadata was fetched.
+    pub fetched_at: i64,
+}
+
+#[derive(Debug, Serialize, Clone, Default, PartialEq, Eq)]
+pub struct Limits {
+    pub max_characters: Option<u32>,
+    pub max_media_attachments: Option<u32>,
+    pub characters_reserved_per_url: Option<u32>,
+    pub poll_max_options: Option<u32>,
+    pub poll_max_characters_per_option: Option<u32>,
+    pub poll_min_expiration: Option<u32>,
+    pub poll_max_expiration: Option<u32>,
+}
+
+impl ServerMetadata {
+    pub fn new(server_id: i64, instance: Instance, emojis: Vec<Emoji>, fetched_at: i64) -> Self {
+        let statuses = &instance.configuration.statuses;
+        let polls = instance.configuration.polls.as_ref();
+        let limits = Limits {
+            // Some servers report 0 when they do not set the limit.
+            max_characters: Some(statuses.max_characters).filter(|c| *c > 0),
+            max_media_attachments: statuses.max_media_attachments,
+            characters_reserved_per_url: statuses.characters_reserved_per_url,
+            poll_max_options: polls.map(|p| p.max_options),
+            poll_max_characters_per_option: polls.map(|p| p.max_characters_per_option),
+            poll_min_expiration: polls.map(|p| p.min_expiration),
+            poll_max_expiration: polls.map(|p| p.max_expiration),
+        };
+        Self {
+            server_id,
+            rules: instance.rules.clone().unwrap_or_default(),
+            instance,
+            emojis,
+            limits,
+            fetched_at,
+        }
+    }
+}
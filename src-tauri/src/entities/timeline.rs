@@ -1,5 +1,6 @@
 use std::{fmt, str::FromStr};
 
+use megalodon::entities::notification::NotificationType;
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
 
@@ -14,6 +15,14 @@ pub struct Timeline {
     pub column_width: ColumnWidth,
     pub show_boosts: bool,
     pub show_replies: bool,
+    pub workspace_id: Option<i64>,
+    /// Id of the account on the server, which is only set for account timelines.
+    pub remote_account_id: Option<String>,
+    /// `user@domain` of the account, which is used to find the account on other servers.
+    pub remote_acct: Option<String>,
+    pub show_pinned: bool,
+    /// Types of notifications which a notifications timeline shows, or everything when it is `None`.
+    pub notification_filter: Option<NotificationFilter>,
 }
 
 impl Timeline {
@@ -36,6 +45,11 @@ impl Timeline {
             column_width,
             show_boosts: true,
             show_replies: true,
+            workspace_id: None,
+            remote_account_id: None,
+            remote_acct: None,
+            show_pinned: true,
+            notification_filter: None,
         }
     }
 }
@@ -53,6 +67,22 @@ pub enum Kind {
     Bookmarks,
     Direct,
     Tag,
+    Account,
+}
+
+impl Kind {
+    /// Whether the timeline can only be read with an account, so it can not be on anonymous servers.
+    pub fn requires_account(&self) -> bool {
+        matches!(
+            self,
+            Kind::Home
+                | Kind::Notifications
+                | Kind::Favourites
+                | Kind::List
+                | Kind::Bookmarks
+                | Kind::Direct
+        )
+    }
 }
 
 impl fmt::Display for Kind {
@@ -67,6 +97,7 @@ impl fmt::Display for Kind {
             Kind::Bookmarks => write!(f, "bookmarks"),
             Kind::Direct => write!(f, "direct"),
             Kind::Tag => write!(f, "tag"),
+            Kind::Account => write!(f, "account"),
         }
     }
 }
@@ -85,11 +116,93 @@ impl FromStr for Kind {
             "bookmarks" => Ok(Kind::Bookmarks),
             "direct" => Ok(Kind::Direct),
             "tag" => Ok(Kind::Tag),
+            "account" => Ok(Kind::Account),
             _ => Err(String::from("Unknown timeline kind")),
         }
     }
 }
 
+#[derive(Debug, Serialize, Deserialize, sqlx::Type, Clone, Copy, PartialEq, Eq)]
+#[sqlx(rename_all = "lowercase")]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationFilter {
+    Mentions,
+    /// Follows and follow requests.
+    Follows,
+    /// Reblogs, favourites and emoji reactions.
+    Reactions,
+    /// Votes on and expiration of polls.
+    Polls,
+    /// Sign ups and reports for moderators.
+    Admin,
+}
+
+impl NotificationFilter {
+    pub fn types(&self) -> &'static [NotificationType] {
+        match self {
+            NotificationFilter::Mentions => &[NotificationType::Mention],
+            NotificationFilter::Follows => {
+                &[NotificationType::Follow, NotificationType::FollowRequest]
+            }
+            NotificationFilter::Reactions => &[
+                NotificationType::Reblog,
+                NotificationType::Favourite,
+                NotificationType::Reaction,
+            ],
+            NotificationFilter::Polls => {
+                &[NotificationType::PollVote, NotificationType::PollExpired]
+            }
+            NotificationFilter::Admin => {
+                &[NotificationType::AdminSignup, NotificationType::AdminReport]
+            }
+        }
+    }
+
+    pub fn matches(&self, kind: &NotificationType) -> bool {
+        self.types().contains(kind)
+    }
+
+    /// Returns the names of the types in megalodon for JavaScript, which the frontend requests notifications with.
+    /// It distinguishes emoji reactions of Pleroma from reactions of Misskey, which are one type here.
+    pub fn type_names(&self) -> Vec<String> {
+        let mut names = Vec::new();
+        for kind in self.types() {
+            if *kind == NotificationType::Reaction {
+                names.push(String::from("emoji_reaction"));
+            }
+            names.push(kind.to_string());
+        }
+        names
+    }
+}
+
+impl fmt::Display for NotificationFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NotificationFilter::Mentions => write!(f, "mentions"),
+            NotificationFilter::Follows => write!(f, "follows"),
+            NotificationFilter::Reactions => write!(f, "reactions"),
+            NotificationFilter::Polls => write!(f, "polls"),
+            NotificationFilter::Admin => write!(f, "admin"),
+        }
+    }
+}
+
+impl FromStr for NotificationFilter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "mentions" => Ok(NotificationFilter::Mentions),
+            "follows" => Ok(NotificationFilter::Follows),
+            "reactions" => Ok(NotificationFilter::Reactions),
+            "polls" => Ok(NotificationFilter::Polls),
+            "admin" => Ok(NotificationFilter::Admin),
+            _ => Err(String::from("Unknown notification filter")),
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, sqlx::Type, Clone, PartialEq, Eq)]
 #[sqlx(rename_all = "lowercase")]
 #[serde(rename_all = "lowercase")]
@@ -124,3 +237,59 @@ impl FromStr for ColumnWidth {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_kind_requires_account() {
+        assert!(Kind::Home.requires_account());
+        assert!(Kind::Direct.requires_account());
+        assert!(!Kind::Local.requires_account());
+        assert!(!Kind::Tag.requires_account());
+        assert!(!Kind::Account.requires_account());
+    }
+
+    #[test]
+    fn test_notification_filter_matches() {
+        assert!(NotificationFilter::Mentions.matches(&NotificationType::Mention));
+        assert!(!NotificationFilter::Mentions.matches(&NotificationType::Status));
+        assert!(NotificationFilter::Follows.matches(&NotificationType::FollowRequest));
+        assert!(NotificationFilter::Reactions.matches(&NotificationType::Reaction));
+        assert!(!NotificationFilter::Reactions.matches(&NotificationType::Mention));
+        assert!(NotificationFilter::Polls.matches(&NotificationType::PollExpired));
+        assert!(NotificationFilter::Admin.matches(&NotificationType::AdminReport));
+        assert!(!NotificationFilter::Admin.matches(&NotificationType::Follow));
+    }
+
+    #[test]
+    fn test_notification_filter_from_str() {
+        for filter in [
+            NotificationFilter::Mentions,
+            NotificationFilter::Follows,
+            NotificationFilter::Reactions,
+            NotificationFilter::Polls,
+            NotificationFilter::Admin,
+        ] {
+            assert_eq!(
+                NotificationFilter::from_str(&filter.to_string()),
+                Ok(filter)
+            );
+        }
+        assert!(NotificationFilter::from_str("boosts").is_err());
+        assert!(NotificationFilter::from_str("Mentions").is_err());
+    }
+
+    #[test]
+    fn test_notification_filter_type_names() {
+        assert_eq!(
+            NotificationFilter::Reactions.type_names(),
+            vec!["reblog", "favourite", "emoji_reaction", "reaction"]
+        );
+        assert_eq!(
+            NotificationFilter::Admin.type_names(),
+            vec!["admin.sign_up", "admin.report"]
+        );
+    }
+}
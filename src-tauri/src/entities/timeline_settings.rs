@@ -0,0 +1,12 @@
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+use crate::settings::MediaDisplay;
+
+/// Overrides of the global settings for a timeline. None means to use the account or global value.
+#[derive(Debug, Serialize, Deserialize, FromRow, Clone, Default)]
+pub struct TimelineSettings {
+    pub timeline_id: i64,
+    pub font_size: Option<u32>,
+    pub media_display: Option<MediaDisplay>,
+}
@@ -0,0 +1,11 @@
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+#[derive(Debug, Serialize, Deserialize, FromRow, Clone)]
+pub struct Workspace {
+    pub id: i64,
+    pub name: String,
+    pub icon: Option<String>,
+    pub sort: i64,
+    pub active: bool,
+}
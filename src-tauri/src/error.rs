@@ -0,0 +1,189 @@
+use rust_i18n::t;
+use serde::Serialize;
+use std::fmt;
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    Network,
+    RateLimited,
+    Auth,
+    NotFound,
+    AlreadyExists,
+    UnsupportedSns,
+    Db,
+    Validation,
+    Internal,
+}
+
+/// The error returned from commands.
+/// `code` is stable so the frontend can branch on it, `message` is localized for display,
+/// and `detail` keeps the original error for logs and bug reports.
+#[derive(Debug, Serialize, Clone)]
+pub struct Error {
+    pub code: ErrorCode,
+    pub message: String,
+    pub detail: String,
+}
+
+impl Error {
+    pub fn new(code: ErrorCode, detail: impl Into<String>) -> Self {
+        let message = match code {
+            ErrorCode::Network => t!("errors.network"),
+            ErrorCode::RateLimited => t!("errors.rate_limited"),
+            ErrorCode::Auth => t!("errors.auth"),
+            ErrorCode::NotFound => t!("errors.not_found"),
+            ErrorCode::AlreadyExists => t!("errors.already_exists"),
+            ErrorCode::UnsupportedSns => t!("errors.unsupported_sns"),
+            ErrorCode::Db => t!("errors.db"),
+            ErrorCode::Validation => t!("errors.validation"),
+            ErrorCode::Internal => t!("errors.internal"),
+        };
+        Self {
+            code,
+            message: message.to_string(),
+            detail: detail.into(),
+        }
+    }
+
+    pub fn network(detail: impl Into<String>) -> Self {
+        Self::new(ErrorCode::Network, detail)
+    }
+
+    pub fn rate_limited(detail: impl Into<String>) -> Self {
+        Self::new(ErrorCode::RateLimited, detail)
+    }
+
+    pub fn auth(detail: impl Into<String>) -> Self {
+        Self::new(ErrorCode::Auth, detail)
+    }
+
+    pub fn not_found(detail: impl Into<String>) -> Self {
+        Self::new(ErrorCode::NotFound, detail)
+    }
+
+    pub fn already_exists(detail: impl Into<String>) -> Self {
+        Self::new(ErrorCode::AlreadyExists, detail)
+    }
+
+    pub fn unsupported_sns(detail: impl Into<String>) -> Self {
+        Self::new(ErrorCode::UnsupportedSns, detail)
+    }
+
+    pub fn db(detail: impl Into<String>) -> Self {
+        Self::new(ErrorCode::Db, detail)
+    }
+
+    pub fn validation(detail: impl Into<String>) -> Self {
+        Self::new(ErrorCode::Validation, detail)
+    }
+
+    pub fn internal(detail: impl Into<String>) -> Self {
+        Self::new(ErrorCode::Internal, detail)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.message, self.detail)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<sqlx::Error> for Error {
+    fn from(err: sqlx::Error) -> Self {
+        match err {
+            sqlx::Error::RowNotFound => Self::not_found(err.to_string()),
+            sqlx::Error::Database(ref db) if db.is_unique_violation() => {
+                Self::already_exists(err.to_string())
+            }
+            _ => Self::db(err.to_string()),
+        }
+    }
+}
+
+impl From<sqlx::migrate::MigrateError> for Error {
+    fn from(err: sqlx::migrate::MigrateError) -> Self {
+        Self::db(err.to_string())
+    }
+}
+
+impl From<megalodon::error::Error> for Error {
+    fn from(err: megalodon::error::Error) -> Self {
+        use megalodon::error::{Error as MegalodonError, Kind};
+
+        match &err {
+            MegalodonError::ParseError(_) => Self::validation(err.to_string()),
+            MegalodonError::OwnError(own) => match own.kind {
+                Kind::HTTPStatusError => match own.status {
+                    Some(401) | Some(403) => Self::auth(err.to_string()),
+                    Some(404) => Self::not_found(err.to_string()),
+                    Some(429) => Self::rate_limited(err.to_string()),
+                    _ => Self::network(err.to_string()),
+                },
+                Kind::NoImplementedError | Kind::NodeinfoError | Kind::UnknownSNSError => {
+                    Self::unsupported_sns(err.to_string())
+                }
+                Kind::UnsatisfiedError => Self::validation(err.to_string()),
+                _ => Self::network(err.to_string()),
+            },
+            _ => Self::network(err.to_string()),
+        }
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(err: reqwest::Error) -> Self {
+        Self::network(err.to_string())
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Self {
+        Self::validation(err.to_string())
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        Self::internal(err.to_string())
+    }
+}
+
+impl From<tauri::Error> for Error {
+    fn from(err: tauri::Error) -> Self {
+        Self::internal(err.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_row_not_found_is_not_found() {
+        let err = Error::from(sqlx::Error::RowNotFound);
+        assert_eq!(err.code, ErrorCode::NotFound);
+    }
+
+    #[test]
+    fn test_too_many_requests_is_rate_limited() {
+        let err = Error::from(megalodon::error::Error::new_own(
+            "Too Many Requests".to_string(),
+            megalodon::error::Kind::HTTPStatusError,
+            None,
+            Some(429),
+            None,
+        ));
+        assert_eq!(err.code, ErrorCode::RateLimited);
+    }
+
+    #[test]
+    fn test_serialize_code_as_snake_case() {
+        let err = Error::unsupported_sns("misskey");
+        let json = serde_json::to_value(&err).unwrap();
+        assert_eq!(json["code"], "unsupported_sns");
+        assert_eq!(json["detail"], "misskey");
+    }
+}
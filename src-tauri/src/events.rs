@@ -0,0 +1,24 @@
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tauri::{AppHandle, Emitter};
+
+static EMIT_FAILURES: AtomicU64 = AtomicU64::new(0);
+
+/// Sends an event to the frontend.
+/// Emitting fails when the window is already closed, which must not crash the backend,
+/// so failures are only logged and counted.
+pub fn emit<S: Serialize + Clone>(app_handle: &AppHandle, event: &str, payload: S) {
+    if let Err(err) = app_handle.emit(event, payload) {
+        let failures = EMIT_FAILURES.fetch_add(1, Ordering::Relaxed) + 1;
+        tracing::error!(
+            "Failed to send {} event ({} failures in total): {}",
+            event,
+            failures,
+            err
+        );
+    }
+}
+
+pub fn emit_failures() -> u64 {
+    EMIT_FAILURES.load(Ordering::Relaxed)
+}
@@ -1,136 +1,644 @@
-use reqwest::{self, header::CONTENT_TYPE};
+use chrono::Utc;
+use reqwest::{
+    self,
+    header::{CONTENT_TYPE, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED},
+    StatusCode,
+};
 use scraper::{Html, Selector};
+use serde::Deserialize;
+use tauri::{
+    http::{header, Request, Response},
+    AppHandle, Manager, Runtime, UriSchemeContext, UriSchemeResponder,
+};
 
-pub async fn get_favicon_url(url: &str) -> Option<String> {
-    let Ok(client) = reqwest::Client::builder().user_agent("fedistar").build() else {
-        return None;
-    };
-    match client.get(url).send().await {
-        Err(err) => {
-            tracing::error!("{:#?}", err);
-            None
+use url::Url;
+
+use crate::{database, entities, error::Error, nodeinfo};
+
+/// URI scheme which serves the downloaded favicons as `favicon://localhost/<server id>`.
+pub const PROTOCOL: &str = "favicon";
+/// Downloaded favicons are used without asking the server for a week.
+const REVALIDATE_INTERVAL: i64 = 7 * 24 * 60 * 60;
+const MAX_ICON_SIZE: usize = 1024 * 1024;
+/// The server icon is 32px in the sidebar, which needs 64px on HiDPI displays.
+const PREFERRED_SIZE: u32 = 64;
+
+enum Fetched {
+    NotModified,
+    Icon(entities::Favicon),
+}
+
+/// Downloads the favicon of the server unless the cached one is new enough.
+pub(crate) async fn refresh(
+    pool: &sqlx::SqlitePool,
+    client: &reqwest::Client,
+    server: &entities::Server,
+) -> Result<(), Error> {
+    let cached = database::get_favicon(pool, server.id).await?;
+    let now = Utc::now().timestamp();
+    if cached.as_ref().is_some_and(|c| !needs_revalidation(c, now)) {
+        return Ok(());
+    }
+
+    // The best icon may be missing or broken, so the next one is tried.
+    let mut last_error = None;
+    for url in discover(client, &server.base_url).await {
+        match fetch_icon(client, server.id, &url, cached.as_ref(), now).await {
+            Ok(Fetched::NotModified) => database::touch_favicon(pool, server.id, now).await?,
+            Ok(Fetched::Icon(favicon)) => database::save_favicon(pool, &favicon).await?,
+            Err(err) => {
+                tracing::debug!("Failed to download {}: {}", url, err);
+                last_error = Some(err);
+                continue;
+            }
+        }
+        tracing::info!("The favicon for {} is {}", server.base_url, url);
+
+        if server.favicon.as_deref() != Some(url.as_str()) {
+            let mut server = server.clone();
+            server.favicon = Some(url);
+            database::update_server(pool, server).await?;
+        }
+        return Ok(());
+    }
+    Err(last_error.unwrap_or_else(|| {
+        Error::not_found(format!("No favicon is found for {}", server.base_url))
+    }))
+}
+
+fn needs_revalidation(favicon: &entities::Favicon, now: i64) -> bool {
+    now - favicon.fetched_at >= REVALIDATE_INTERVAL
+}
+
+async fn fetch_icon(
+    client: &reqwest::Client,
+    server_id: i64,
+    url: &str,
+    cached: Option<&entities::Favicon>,
+    now: i64,
+) -> Result<Fetched, Error> {
+    let mut req = client.get(url);
+    // Validators are only meaningful for the same URL, since the icon may be moved to another file.
+    if let Some(cached) = cached.filter(|c| c.url == url) {
+        if let Some(etag) = &cached.etag {
+            req = req.header(IF_NONE_MATCH, etag);
         }
-        Ok(res) => match parse_location(res).await {
+        if let Some(last_modified) = &cached.last_modified {
+            req = req.header(IF_MODIFIED_SINCE, last_modified);
+        }
+    }
+    let res = req.send().await?;
+    if res.status() == StatusCode::NOT_MODIFIED && cached.is_some_and(|c| c.url == url) {
+        return Ok(Fetched::NotModified);
+    }
+    let res = res.error_for_status()?;
+
+    let header = |name| {
+        res.headers()
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.to_string())
+    };
+    let content_type = image_type(header(CONTENT_TYPE).as_deref(), url)
+        .ok_or_else(|| Error::validation(format!("{} is not an image", url)))?;
+    let etag = header(ETAG);
+    let last_modified = header(LAST_MODIFIED);
+    let data = res.bytes().await?;
+    if data.is_empty() || data.len() > MAX_ICON_SIZE {
+        return Err(Error::validation(format!(
+            "The size of {} is {} bytes",
+            url,
+            data.len()
+        )));
+    }
+
+    Ok(Fetched::Icon(entities::Favicon {
+        server_id,
+        url: url.to_string(),
+        content_type,
+        data: data.to_vec(),
+        etag,
+        last_modified,
+        fetched_at: now,
+    }))
+}
+
+// Some servers return favicon.ico as application/octet-stream, so guess the type from the extension.
+fn image_type(content_type: Option<&str>, url: &str) -> Option<String> {
+    let content_type = content_type
+        .and_then(|c| c.split(';').next())
+        .map(|c| c.trim().to_lowercase());
+    if let Some(content_type) = content_type.filter(|c| c.starts_with("image/")) {
+        return Some(content_type);
+    }
+    let path = Url::parse(url).ok()?.path().to_lowercase();
+    let extension = path.rsplit_once('.')?.1.to_string();
+    let guessed = match extension.as_str() {
+        "ico" => "image/x-icon",
+        "png" => "image/png",
+        "svg" => "image/svg+xml",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        _ => return None,
+    };
+    Some(guessed.to_string())
+}
+
+/// Serves the downloaded favicons to the webview.
+pub(crate) fn handle_protocol<R: Runtime>(
+    ctx: UriSchemeContext<'_, R>,
+    request: Request<Vec<u8>>,
+    responder: UriSchemeResponder,
+) {
+    let app_handle = ctx.app_handle().clone();
+    tauri::async_runtime::spawn(async move {
+        let response = match load(&app_handle, request.uri().path()).await {
+            Ok(Some(favicon)) => Response::builder()
+                .header(header::CONTENT_TYPE, favicon.content_type)
+                .header(header::CACHE_CONTROL, "no-cache")
+                .body(favicon.data),
+            Ok(None) => Response::builder().status(404).body(Vec::new()),
             Err(err) => {
-                tracing::error!("{:#?}", err);
-                None
+                tracing::error!("Failed to load the favicon {}: {}", request.uri(), err);
+                Response::builder().status(400).body(Vec::new())
             }
-            Ok(list) => {
-                if list.len() > 0 {
-                    let path = list[0].clone();
-                    let base = url::Url::parse(url).ok();
-                    base.map(|b| b.join(&path).ok())
-                        .flatten()
-                        .map(|u| u.to_string())
-                } else {
-                    tracing::error!("Favicon not found");
-                    None
+        };
+        match response {
+            Ok(response) => responder.respond(response),
+            Err(err) => tracing::error!("Failed to build the favicon response: {}", err),
+        }
+    });
+}
+
+async fn load<R: Runtime>(
+    app_handle: &AppHandle<R>,
+    path: &str,
+) -> Result<Option<entities::Favicon>, Error> {
+    let server_id: i64 = path
+        .trim_matches('/')
+        .parse()
+        .map_err(|_| Error::validation(format!("Invalid favicon path {}", path)))?;
+    let Some(pool) = app_handle.try_state::<sqlx::SqlitePool>() else {
+        return Ok(None);
+    };
+    let favicon = database::get_favicon(&pool, server_id).await?;
+    Ok(favicon)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Candidate {
+    url: String,
+    /// The largest width in `sizes`, which is `u32::MAX` for `any`, or None when it is not declared.
+    size: Option<u32>,
+}
+
+impl Candidate {
+    fn new(base: &Url, href: &str, sizes: Option<&str>) -> Option<Self> {
+        let url = base.join(href.trim()).ok()?;
+        if url.scheme() != "https" && url.scheme() != "http" {
+            return None;
+        }
+        Some(Self {
+            url: url.to_string(),
+            size: sizes.and_then(parse_sizes),
+        })
+    }
+
+    // Smaller is better: scalable icons, the smallest icon which is not blurred in the sidebar,
+    // smaller icons from the largest one, and then icons without sizes.
+    fn rank(&self) -> u64 {
+        match self.size {
+            Some(u32::MAX) => 0,
+            Some(size) if size >= PREFERRED_SIZE => 1 + u64::from(size - PREFERRED_SIZE),
+            Some(size) => 1 << 32 | u64::from(PREFERRED_SIZE - size),
+            None => 1 << 33,
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Page {
+    icons: Vec<Candidate>,
+    manifest: Option<Url>,
+}
+
+#[derive(Deserialize)]
+struct Manifest {
+    #[serde(default)]
+    icons: Vec<ManifestIcon>,
+}
+
+#[derive(Deserialize)]
+struct ManifestIcon {
+    src: String,
+    sizes: Option<String>,
+    purpose: Option<String>,
+}
+
+/// Returns URLs of the icons of the server, the best one for the sidebar first.
+/// The page, its web app manifest and nodeinfo are searched, and `/favicon.ico` is the last resort.
+pub(crate) async fn discover(client: &reqwest::Client, base_url: &str) -> Vec<String> {
+    let Ok(base) = Url::parse(base_url) else {
+        return Vec::new();
+    };
+    let mut candidates = Vec::new();
+
+    match fetch_page(client, &base).await {
+        Ok(page) => {
+            candidates.extend(page.icons);
+            if let Some(manifest) = page.manifest {
+                match fetch_manifest(client, &manifest).await {
+                    Ok(icons) => candidates.extend(icons),
+                    Err(err) => tracing::warn!("Failed to read {}: {}", manifest, err),
                 }
             }
-        },
+        }
+        Err(err) => tracing::warn!("Failed to read {}: {}", base, err),
     }
-}
+    match fetch_nodeinfo(client, &base).await {
+        Ok(icons) => candidates.extend(icons),
+        Err(err) => tracing::debug!("No icon is found in nodeinfo of {}: {}", base, err),
+    }
+    candidates.extend(Candidate::new(&base, "/favicon.ico", None));
 
-async fn parse_location(response: reqwest::Response) -> Result<Vec<String>, reqwest::Error> {
-    let content_type = response.headers().get(CONTENT_TYPE);
-    if let Some(content_type) = content_type {
-        if content_type.to_str().unwrap_or("").starts_with("text/html") {
-            let content = response.text().await?;
-            let list = parse_content(&content);
-            return Ok(list);
+    candidates.sort_by_key(Candidate::rank);
+    let mut urls: Vec<String> = Vec::with_capacity(candidates.len());
+    for candidate in candidates {
+        if !urls.contains(&candidate.url) {
+            urls.push(candidate.url);
         }
     }
-    Ok(Vec::new())
+    urls
 }
 
-fn parse_content(content: &str) -> Vec<String> {
-    let mut list: Vec<String> = Vec::new();
+async fn fetch_page(client: &reqwest::Client, base: &Url) -> Result<Page, Error> {
+    let res = client.get(base.clone()).send().await?.error_for_status()?;
+    let is_html = res
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|c| c.to_str().ok())
+        .is_some_and(|c| c.starts_with("text/html"));
+    if !is_html {
+        return Ok(Page::default());
+    }
+    // Relative URLs are resolved against the URL after redirects, as browsers do.
+    let url = res.url().clone();
+    let content = res.text().await?;
+    Ok(parse_content(&url, &content))
+}
+
+fn parse_content(base: &Url, content: &str) -> Page {
+    let mut page = Page::default();
     let fragment = Html::parse_fragment(content);
-    let selector = Selector::parse("link").expect("Failed to parse link");
+    let selector = Selector::parse("link[rel][href]").expect("Failed to parse link");
 
     for element in fragment.select(&selector) {
-        if let Some(rel) = element.value().attr("rel") {
-            if rel == "icon" || rel == "apple-touch-icon" {
-                if let Some(href) = element.value().attr("href") {
-                    list.push(href.to_string());
-                }
-            }
+        let (Some(rel), Some(href)) = (element.value().attr("rel"), element.value().attr("href"))
+        else {
+            continue;
+        };
+        let rel = rel.to_ascii_lowercase();
+        let mut tokens = rel.split_ascii_whitespace();
+        // `shortcut icon` has the icon token, and iOS icons are usually the largest ones.
+        if tokens
+            .clone()
+            .any(|t| t == "icon" || t == "apple-touch-icon" || t == "apple-touch-icon-precomposed")
+        {
+            page.icons
+                .extend(Candidate::new(base, href, element.value().attr("sizes")));
+        } else if tokens.any(|t| t == "manifest") && page.manifest.is_none() {
+            page.manifest = base.join(href.trim()).ok();
         }
     }
-    list
+    page
+}
+
+async fn fetch_manifest(client: &reqwest::Client, url: &Url) -> Result<Vec<Candidate>, Error> {
+    let manifest: Manifest = client
+        .get(url.clone())
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    Ok(manifest
+        .icons
+        .iter()
+        // Monochrome icons are masks which look broken as they are.
+        .filter(|icon| {
+            icon.purpose.as_deref().map_or(true, |p| {
+                p.split_ascii_whitespace().any(|p| p != "monochrome")
+            })
+        })
+        .filter_map(|icon| Candidate::new(url, &icon.src, icon.sizes.as_deref()))
+        .collect())
+}
+
+// Some servers put their icon in the metadata of nodeinfo, whose keys depend on the software.
+async fn fetch_nodeinfo(client: &reqwest::Client, base: &Url) -> Result<Vec<Candidate>, Error> {
+    let (url, nodeinfo) = nodeinfo::fetch(client, base).await?;
+    let metadata = &nodeinfo.metadata;
+    Ok(["iconUrl", "icon", "thumbnail"]
+        .iter()
+        .filter_map(|key| {
+            let value = &metadata[*key];
+            value.as_str().or_else(|| value["url"].as_str())
+        })
+        .filter_map(|href| Candidate::new(&url, href, None))
+        .collect())
+}
+
+fn parse_sizes(sizes: &str) -> Option<u32> {
+    sizes
+        .split_ascii_whitespace()
+        .filter_map(|size| {
+            if size.eq_ignore_ascii_case("any") {
+                return Some(u32::MAX);
+            }
+            let (width, _) = size.split_once(['x', 'X'])?;
+            width.parse().ok()
+        })
+        .max()
 }
 
 #[cfg(test)]
 mod tests {
 
     use super::*;
+    use std::collections::HashMap;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
 
-    #[tokio::test]
-    async fn test_get_favicon_url_for_mastodon() {
-        let result = get_favicon_url("https://fedibird.com").await;
-        assert!(result.is_some());
+    const MASTODON: &str = include_str!("../fixtures/favicon/mastodon.html");
+    const PLEROMA: &str = include_str!("../fixtures/favicon/pleroma.html");
+    const MISSKEY: &str = include_str!("../fixtures/favicon/misskey.html");
+    const MANIFEST: &str = include_str!("../fixtures/favicon/manifest.json");
+    const ICON: &[u8] = b"\x89PNG\r\n\x1a\nfixture";
+    const ETAG_VALUE: &str = "\"fixture\"";
+
+    type Routes = HashMap<String, (&'static str, Vec<u8>)>;
+
+    // A local HTTP server which serves the routes, and answers 304 when the ETag matches.
+    async fn start_server(routes: impl FnOnce(&str) -> Routes) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        let routes = std::sync::Arc::new(routes(&base_url));
+
+        tokio::spawn(async move {
+            loop {
+                let Ok((mut stream, _)) = listener.accept().await else {
+                    return;
+                };
+                let routes = routes.clone();
+                tokio::spawn(async move {
+                    let mut buf = vec![0; 4096];
+                    let Ok(n) = stream.read(&mut buf).await else {
+                        return;
+                    };
+                    let request = String::from_utf8_lossy(&buf[..n]).to_lowercase();
+                    let path = request.split_whitespace().nth(1).unwrap_or("/").to_string();
+                    let (head, body) = match routes.get(&path) {
+                        Some(_) if request.contains(&format!("if-none-match: {}", ETAG_VALUE)) => {
+                            ("304 Not Modified".to_string(), Vec::new())
+                        }
+                        Some((content_type, body)) => (
+                            format!(
+                                "200 OK\r\nContent-Type: {}\r\nETag: {}",
+                                content_type, ETAG_VALUE
+                            ),
+                            body.clone(),
+                        ),
+                        None => ("404 Not Found".to_string(), Vec::new()),
+                    };
+                    let header = format!(
+                        "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                        head,
+                        body.len()
+                    );
+                    let _ = stream.write_all(header.as_bytes()).await;
+                    let _ = stream.write_all(&body).await;
+                    let _ = stream.shutdown().await;
+                });
+            }
+        });
+        base_url
+    }
+
+    fn route(
+        path: &str,
+        content_type: &'static str,
+        body: &[u8],
+    ) -> (String, (&'static str, Vec<u8>)) {
+        (path.to_string(), (content_type, body.to_vec()))
+    }
+
+    fn base() -> Url {
+        Url::parse("https://example.com").unwrap()
+    }
+
+    fn candidate(url: &str, size: Option<u32>) -> Candidate {
+        Candidate {
+            url: url.to_string(),
+            size,
+        }
+    }
+
+    #[test]
+    fn test_needs_revalidation() {
+        let favicon = entities::Favicon {
+            server_id: 1,
+            url: String::from("https://example.com/favicon.ico"),
+            content_type: String::from("image/x-icon"),
+            data: vec![0],
+            etag: None,
+            last_modified: None,
+            fetched_at: 1_000_000,
+        };
+        assert!(!needs_revalidation(
+            &favicon,
+            1_000_000 + REVALIDATE_INTERVAL - 1
+        ));
+        assert!(needs_revalidation(
+            &favicon,
+            1_000_000 + REVALIDATE_INTERVAL
+        ));
+    }
+
+    #[test]
+    fn test_image_type() {
         assert_eq!(
-            result,
-            Some(String::from("https://fedibird.com/favicon.ico"))
+            image_type(
+                Some("image/PNG; charset=binary"),
+                "https://example.com/icon"
+            ),
+            Some(String::from("image/png"))
+        );
+        assert_eq!(
+            image_type(
+                Some("application/octet-stream"),
+                "https://example.com/favicon.ico?v=2"
+            ),
+            Some(String::from("image/x-icon"))
         );
+        assert_eq!(image_type(Some("text/html"), "https://example.com/"), None);
     }
 
-    #[tokio::test]
-    async fn test_get_favicon_url_for_pleroma() {
-        let result = get_favicon_url("https://pleroma.io").await;
-        assert!(result.is_some());
-        assert_eq!(result, Some(String::from("https://pleroma.io/favicon.png")));
+    #[test]
+    fn test_parse_sizes() {
+        assert_eq!(parse_sizes("16x16 32X32"), Some(32));
+        assert_eq!(parse_sizes("any"), Some(u32::MAX));
+        assert_eq!(parse_sizes("invalid"), None);
+    }
+
+    #[test]
+    fn test_rank_prefers_the_smallest_sharp_icon() {
+        let mut candidates = vec![
+            candidate("none", None),
+            candidate("16", Some(16)),
+            candidate("512", Some(512)),
+            candidate("180", Some(180)),
+            candidate("32", Some(32)),
+            candidate("svg", Some(u32::MAX)),
+        ];
+        candidates.sort_by_key(Candidate::rank);
+        let urls: Vec<&str> = candidates.iter().map(|c| c.url.as_str()).collect();
+        assert_eq!(urls, vec!["svg", "180", "512", "32", "16", "none"]);
     }
 
     #[test]
     fn test_parse_content_for_mastodon() {
-        let html = r#"
-<!DOCTYPE html>
-<html lang='en'>
-<head>
-<meta charset='utf-8'>
-<meta content='width=device-width, initial-scale=1, viewport-fit=cover' name='viewport'>
-<link href='/favicon.ico' rel='icon' type='image/x-icon'>
-<link href='/apple-touch-icon.png' rel='apple-touch-icon' sizes='180x180'>
-<link color='#2B90D9' href='/mask-icon.svg' rel='mask-icon'>
-<meta content='/browserconfig.xml' name='msapplication-config'>
-<title>Sample</title>
-</head>
-<body></body>
-</html>
-"#;
-
-        let result = parse_content(html);
+        let page = parse_content(&base(), MASTODON);
         assert_eq!(
-            result,
+            page.icons,
             vec![
-                String::from("/favicon.ico"),
-                String::from("/apple-touch-icon.png")
+                candidate("https://example.com/favicon.ico", None),
+                candidate("https://example.com/apple-touch-icon.png", Some(180)),
             ]
         );
+        assert_eq!(page.manifest, None);
     }
 
     #[test]
     fn test_parse_content_for_pleroma() {
-        let html = r#"
-"<!DOCTYPE html>
-<html lang=en>
-<head>
-<meta charset=utf-8>
-<meta name=viewport content=\"width=device-width,initial-scale=1,user-scalable=no\">
-<title>Pleroma.io</title>
-<link rel=icon type=image/png href=/favicon.png>
-<link href=/static/css/app.7d2d223f75c3a14b0991.css rel=stylesheet>
-</head>
-<body class=hidden>
-<noscript>To use Pleroma, please enable JavaScript.</noscript>
-<div id=app></div>
-<script type=text/javascript src=/static/js/vendors~app.cea10ab53f3aa19fc30e.js></script>
-<script type=text/javascript src=/static/js/app.6c972d84b60f601b01f8.js></script>
-</body>
-</html>"
-"#;
-        let result = parse_content(html);
-        assert_eq!(result, vec![String::from("/favicon.png"),]);
+        let page = parse_content(&base(), PLEROMA);
+        assert_eq!(
+            page.icons,
+            vec![candidate("https://example.com/favicon.png", None)]
+        );
+    }
+
+    #[test]
+    fn test_parse_content_with_shortcut_icon_and_manifest() {
+        let page = parse_content(&base(), MISSKEY);
+        assert_eq!(
+            page.icons,
+            vec![
+                candidate("https://example.com/favicon.ico", None),
+                candidate("https://example.com/static-assets/icon-16.png", Some(16)),
+                candidate("https://example.com/static-assets/icon-32.png", Some(32)),
+            ]
+        );
+        assert_eq!(
+            page.manifest,
+            Some(Url::parse("https://example.com/manifest.json").unwrap())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_discover_for_mastodon() {
+        let base_url = start_server(|_| {
+            Routes::from([route("/", "text/html; charset=utf-8", MASTODON.as_bytes())])
+        })
+        .await;
+        let result = discover(&reqwest::Client::new(), &base_url).await;
+        assert_eq!(
+            result,
+            vec![
+                format!("{}/apple-touch-icon.png", base_url),
+                format!("{}/favicon.ico", base_url),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_discover_from_manifest() {
+        let base_url = start_server(|_| {
+            Routes::from([
+                route("/", "text/html", MISSKEY.as_bytes()),
+                route(
+                    "/manifest.json",
+                    "application/manifest+json",
+                    MANIFEST.as_bytes(),
+                ),
+            ])
+        })
+        .await;
+        let result = discover(&reqwest::Client::new(), &base_url).await;
+        assert_eq!(
+            result,
+            vec![
+                format!("{}/static-assets/icons/192.png", base_url),
+                format!("{}/static-assets/icons/512.png", base_url),
+                format!("{}/static-assets/icon-32.png", base_url),
+                format!("{}/static-assets/icon-16.png", base_url),
+                format!("{}/favicon.ico", base_url),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_discover_from_nodeinfo() {
+        let base_url = start_server(|base_url| {
+            let links = format!(
+                r#"{{"links":[{{"rel":"http://nodeinfo.diaspora.software/ns/schema/2.0","href":"{}/nodeinfo/2.0"}}]}}"#,
+                base_url
+            );
+            let nodeinfo = r#"{"version":"2.0","software":{"name":"mastodon","version":"4.3.0"},"metadata":{"thumbnail":{"url":"/files/thumbnail.png"}}}"#;
+            Routes::from([
+                route("/.well-known/nodeinfo", "application/json", links.as_bytes()),
+                route("/nodeinfo/2.0", "application/json", nodeinfo.as_bytes()),
+            ])
+        })
+        .await;
+        let result = discover(&reqwest::Client::new(), &base_url).await;
+        assert_eq!(
+            result,
+            vec![
+                format!("{}/files/thumbnail.png", base_url),
+                format!("{}/favicon.ico", base_url),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fetch_icon_revalidates_with_etag() {
+        let base_url =
+            start_server(|_| Routes::from([route("/favicon.png", "image/png", ICON)])).await;
+        let url = format!("{}/favicon.png", base_url);
+        let client = reqwest::Client::new();
+
+        let Fetched::Icon(favicon) = fetch_icon(&client, 1, &url, None, 0).await.unwrap() else {
+            panic!("The icon is not downloaded");
+        };
+        assert_eq!(favicon.data, ICON);
+        assert_eq!(favicon.content_type, "image/png");
+        assert_eq!(favicon.etag.as_deref(), Some(ETAG_VALUE));
+
+        let fetched = fetch_icon(&client, 1, &url, Some(&favicon), 1)
+            .await
+            .unwrap();
+        assert!(matches!(fetched, Fetched::NotModified));
+    }
+
+    #[tokio::test]
+    async fn test_fetch_icon_rejects_missing_icon() {
+        let base_url = start_server(|_| Routes::new()).await;
+        let url = format!("{}/favicon.ico", base_url);
+        assert!(fetch_icon(&reqwest::Client::new(), 1, &url, None, 0)
+            .await
+            .is_err());
     }
 }
@@ -0,0 +1,435 @@
+use base64::{engine::general_purpose, Engine};
+use http_body_util::{BodyExt, Full};
+use hyper::{
+    body::{Bytes, Incoming},
+    header::{self, HeaderMap, HeaderValue},
+    server::conn::http1,
+    service::service_fn,
+    Request, Response, StatusCode, Uri,
+};
+use hyper_util::rt::TokioIo;
+use ring::rand::{SecureRandom, SystemRandom};
+use std::{
+    convert::Infallible,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
+use subtle::ConstantTimeEq;
+use tokio::net::TcpListener;
+
+use crate::{clients::HttpClients, error::Error};
+
+/// The path segment under a route which is relayed to the streaming server of the route.
+const STREAMING_SEGMENT: &str = "~streaming";
+
+/// Headers which belong to a single connection, so they are not relayed.
+const HOP_BY_HOP_HEADERS: &[&str] = &[
+    "connection",
+    "content-length",
+    "host",
+    "keep-alive",
+    "proxy-authorization",
+    "proxy-connection",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+];
+
+struct Route {
+    /// Ids are never reused, so URLs of removed routes are not relayed to another server.
+    id: usize,
+    base_url: String,
+    /// The streaming server which the server announced in `/api/v1/instance`, with an HTTP scheme.
+    streaming_url: Option<String>,
+}
+
+/// A local HTTP server which relays requests of megalodon clients with our HTTP clients.
+/// megalodon builds its own HTTP clients and websockets, which can not be given the proxy or the certificates
+/// of the server, so its clients are given a base URL on this server instead of the server's one.
+/// Paths start with a random secret, so other local processes can not use it as an open relay.
+pub struct Gateway {
+    addr: SocketAddr,
+    secret: String,
+    clients: Arc<Mutex<HttpClients>>,
+    routes: Mutex<Vec<Route>>,
+    next_id: AtomicUsize,
+}
+
+impl Gateway {
+    pub async fn start(clients: Arc<Mutex<HttpClients>>) -> Result<Arc<Self>, Error> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let mut secret = [0u8; 16];
+        SystemRandom::new()
+            .fill(&mut secret)
+            .map_err(|_| Error::internal("Failed to generate a secret for the gateway"))?;
+        let gateway = Arc::new(Self {
+            addr: listener.local_addr()?,
+            secret: general_purpose::URL_SAFE_NO_PAD.encode(secret),
+            clients,
+            routes: Mutex::new(Vec::new()),
+            next_id: AtomicUsize::new(0),
+        });
+        tracing::info!("The gateway is listening on {}", gateway.addr);
+
+        let server = gateway.clone();
+        tauri::async_runtime::spawn(async move {
+            loop {
+                let stream = match listener.accept().await {
+                    Ok((stream, _)) => stream,
+                    Err(err) => {
+                        tracing::warn!("Failed to accept a connection to the gateway: {}", err);
+                        continue;
+                    }
+                };
+                let gateway = server.clone();
+                tauri::async_runtime::spawn(async move {
+                    let service = service_fn(move |req| gateway.clone().handle(req));
+                    if let Err(err) = http1::Builder::new()
+                        .serve_connection(TokioIo::new(stream), service)
+                        .with_upgrades()
+                        .await
+                    {
+                        tracing::debug!("A connection to the gateway is closed: {}", err);
+                    }
+                });
+            }
+        });
+        Ok(gateway)
+    }
+
+    /// Returns the base URL on the gateway which is relayed to `base_url`.
+    pub fn url(&self, base_url: &str) -> String {
+        let mut routes = self.routes.lock().unwrap_or_else(|e| e.into_inner());
+        let id = match routes.iter().find(|r| r.base_url == base_url) {
+            Some(route) => route.id,
+            None => {
+                let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+                routes.push(Route {
+                    id,
+                    base_url: base_url.to_string(),
+                    streaming_url: None,
+                });
+                id
+            }
+        };
+        self.route_url(id)
+    }
+
+    /// Removes the routes of servers for which `keep` returns false.
+    /// Clients which were given their URLs must be generated again.
+    pub fn retain(&self, keep: impl Fn(&str) -> bool) {
+        self.routes
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .retain(|r| keep(&r.base_url));
+    }
+
+    /// Replaces the gateway in URLs which megalodon builds from the base URL,
+    /// such as the authorization URL which is opened in the browser.
+    pub fn upstream_url(&self, url: &str) -> String {
+        let Some(rest) = url.strip_prefix(&format!("http://{}/{}/", self.addr, self.secret)) else {
+            return url.to_string();
+        };
+        let (id, path) = rest.split_at(rest.find(['/', '?']).unwrap_or(rest.len()));
+        let routes = self.routes.lock().unwrap_or_else(|e| e.into_inner());
+        match id
+            .parse::<usize>()
+            .ok()
+            .and_then(|id| routes.iter().find(|r| r.id == id))
+        {
+            Some(route) => format!("{}{}", route.base_url.trim_end_matches('/'), path),
+            None => url.to_string(),
+        }
+    }
+
+    fn route_url(&self, id: usize) -> String {
+        format!("http://{}/{}/{}", self.addr, self.secret, id)
+    }
+
+    /// Returns the route id and the upstream URL of the request.
+    fn target(&self, uri: &Uri) -> Option<(usize, String)> {
+        let mut segments = uri.path().strip_prefix('/')?.splitn(3, '/');
+        // The secret is compared in constant time, so that it can not be guessed from response times.
+        let secret = segments.next()?.as_bytes();
+        if !bool::from(secret.ct_eq(self.secret.as_bytes())) {
+            return None;
+        }
+        let id = segments.next()?.parse::<usize>().ok()?;
+        let path = segments
+            .next()
+            .map(|p| format!("/{}", p))
+            .unwrap_or_default();
+        let routes = self.routes.lock().unwrap_or_else(|e| e.into_inner());
+        let route = routes.iter().find(|r| r.id == id)?;
+        let (base_url, path) = match path
+            .strip_prefix('/')
+            .and_then(|p| p.strip_prefix(STREAMING_SEGMENT))
+        {
+            Some(path) => (route.streaming_url.as_deref()?, path),
+            None => (route.base_url.as_str(), path.as_str()),
+        };
+        let mut target = format!("{}{}", base_url.trim_end_matches('/'), path);
+        if let Some(query) = uri.query() {
+            target.push('?');
+            target.push_str(query);
+        }
+        Some((id, target))
+    }
+
+    async fn handle(
+        self: Arc<Self>,
+        req: Request<Incoming>,
+    ) -> Result<Response<Full<Bytes>>, Infallible> {
+        let Some((id, target)) = self.target(req.uri()) else {
+            return Ok(status_response(StatusCode::NOT_FOUND));
+        };
+        let clients = self
+            .clients
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(&target)
+            .clone();
+        let res = if is_websocket(req.headers()) {
+            relay_websocket(&clients.websocket, req, &target).await
+        } else {
+            self.relay(&clients.http, req, id, &target).await
+        };
+        Ok(res.unwrap_or_else(|err| {
+            tracing::warn!("Failed to relay a request to {}: {}", target, err);
+            status_response(StatusCode::BAD_GATEWAY)
+        }))
+    }
+
+    async fn relay(
+        &self,
+        client: &reqwest::Client,
+        req: Request<Incoming>,
+        id: usize,
+        target: &str,
+    ) -> Result<Response<Full<Bytes>>, Error> {
+        let method = req.method().clone();
+        let is_instance = req.uri().path().ends_with("/api/v1/instance");
+        let mut request = client
+            .request(method, target)
+            .headers(relayed_headers(req.headers()));
+        let body = req
+            .into_body()
+            .collect()
+            .await
+            .map_err(|e| Error::internal(e.to_string()))?
+            .to_bytes();
+        if !body.is_empty() {
+            request = request.body(body);
+        }
+        let res = request.send().await?;
+
+        let status = res.status();
+        let headers = relayed_headers(res.headers());
+        let mut body = res.bytes().await?;
+        if is_instance && status.is_success() {
+            body = self.rewrite_streaming_url(id, body);
+        }
+        let mut response = Response::new(Full::new(body));
+        *response.status_mut() = status;
+        *response.headers_mut() = headers;
+        Ok(response)
+    }
+
+    /// megalodon opens websockets to the streaming server in the instance,
+    /// so it is recorded for the route and replaced with the gateway.
+    fn rewrite_streaming_url(&self, id: usize, body: Bytes) -> Bytes {
+        let Ok(mut instance) = serde_json::from_slice::<serde_json::Value>(&body) else {
+            return body;
+        };
+        let Some(streaming_api) = instance.pointer_mut("/urls/streaming_api") else {
+            return body;
+        };
+        let Some(upstream) = streaming_api.as_str().and_then(http_url) else {
+            return body;
+        };
+        if let Some(route) = self
+            .routes
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter_mut()
+            .find(|r| r.id == id)
+        {
+            route.streaming_url = Some(upstream);
+        }
+        *streaming_api = serde_json::Value::String(format!(
+            "ws{}/{}",
+            self.route_url(id).trim_start_matches("http"),
+            STREAMING_SEGMENT
+        ));
+        serde_json::to_vec(&instance)
+            .map(Bytes::from)
+            .unwrap_or(body)
+    }
+}
+
+/// Sends the upgrade request with our client, and copies frames between both connections after switching protocols.
+async fn relay_websocket(
+    client: &reqwest::Client,
+    mut req: Request<Incoming>,
+    target: &str,
+) -> Result<Response<Full<Bytes>>, Error> {
+    let mut headers = relayed_headers(req.headers());
+    headers.insert(header::CONNECTION, HeaderValue::from_static("upgrade"));
+    headers.insert(header::UPGRADE, HeaderValue::from_static("websocket"));
+    let res = client.get(target).headers(headers).send().await?;
+
+    let status = res.status();
+    let mut headers = relayed_headers(res.headers());
+    if status != StatusCode::SWITCHING_PROTOCOLS {
+        let mut response = Response::new(Full::new(res.bytes().await?));
+        *response.status_mut() = status;
+        *response.headers_mut() = headers;
+        return Ok(response);
+    }
+    headers.insert(header::CONNECTION, HeaderValue::from_static("upgrade"));
+    headers.insert(header::UPGRADE, HeaderValue::from_static("websocket"));
+    let mut upstream = res.upgrade().await?;
+    let downstream = hyper::upgrade::on(&mut req);
+    tauri::async_runtime::spawn(async move {
+        match downstream.await {
+            Ok(downstream) => {
+                let mut downstream = TokioIo::new(downstream);
+                if let Err(err) =
+                    tokio::io::copy_bidirectional(&mut downstream, &mut upstream).await
+                {
+                    tracing::debug!("A relayed websocket is closed: {}", err);
+                }
+            }
+            Err(err) => tracing::warn!("Failed to upgrade a connection to the gateway: {}", err),
+        }
+    });
+
+    let mut response = Response::new(Full::new(Bytes::new()));
+    *response.status_mut() = status;
+    *response.headers_mut() = headers;
+    Ok(response)
+}
+
+fn is_websocket(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::UPGRADE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.eq_ignore_ascii_case("websocket"))
+}
+
+fn relayed_headers(headers: &HeaderMap) -> HeaderMap {
+    let mut relayed = headers.clone();
+    for name in HOP_BY_HOP_HEADERS {
+        relayed.remove(*name);
+    }
+    relayed
+}
+
+/// Converts a websocket URL to the HTTP URL which reqwest upgrades.
+fn http_url(url: &str) -> Option<String> {
+    if let Some(rest) = url.strip_prefix("wss://") {
+        Some(format!("https://{}", rest))
+    } else if let Some(rest) = url.strip_prefix("ws://") {
+        Some(format!("http://{}", rest))
+    } else if url.starts_with("https://") || url.starts_with("http://") {
+        Some(url.to_string())
+    } else {
+        None
+    }
+}
+
+fn status_response(status: StatusCode) -> Response<Full<Bytes>> {
+    let mut response = Response::new(Full::new(Bytes::new()));
+    *response.status_mut() = status;
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_http_url() {
+        assert_eq!(
+            http_url("wss://streaming.example.com"),
+            Some("https://streaming.example.com".to_string())
+        );
+        assert_eq!(
+            http_url("ws://localhost:4000"),
+            Some("http://localhost:4000".to_string())
+        );
+        assert_eq!(http_url("ftp://example.com"), None);
+    }
+
+    fn gateway() -> Gateway {
+        Gateway {
+            addr: "127.0.0.1:8080".parse().unwrap(),
+            secret: "secret".to_string(),
+            clients: Arc::new(Mutex::new(HttpClients::build().unwrap())),
+            routes: Mutex::new(Vec::new()),
+            next_id: AtomicUsize::new(0),
+        }
+    }
+
+    #[test]
+    fn test_target_requires_the_exact_secret() {
+        let gateway = gateway();
+        let url = gateway.url("https://example.social");
+        let uri = |url: String| url.parse::<Uri>().unwrap();
+
+        assert_eq!(
+            gateway.target(&uri(format!("{}/api/v1/instance?a=1", url))),
+            Some((0, "https://example.social/api/v1/instance?a=1".to_string()))
+        );
+        assert_eq!(
+            gateway.target(&uri(url.clone())),
+            Some((0, "https://example.social".to_string()))
+        );
+        assert_eq!(
+            gateway.target(&uri("http://127.0.0.1:8080/secretx/0/api".to_string())),
+            None
+        );
+        assert_eq!(
+            gateway.target(&uri("http://127.0.0.1:8080/secre/0/api".to_string())),
+            None
+        );
+    }
+
+    #[test]
+    fn test_retain_removes_routes_without_reusing_ids() {
+        let gateway = gateway();
+        let removed = gateway.url("https://removed.example");
+        let kept = gateway.url("https://example.social");
+
+        gateway.retain(|base_url| base_url == "https://example.social");
+
+        assert_eq!(gateway.url("https://example.social"), kept);
+        assert!(gateway
+            .target(&format!("{}/api", removed).parse::<Uri>().unwrap())
+            .is_none());
+        let added = gateway.url("https://removed.example");
+        assert_ne!(added, removed);
+        assert_eq!(
+            gateway.upstream_url(&format!("{}/oauth/authorize", added)),
+            "https://removed.example/oauth/authorize"
+        );
+    }
+
+    #[test]
+    fn test_relayed_headers_drop_hop_by_hop_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::HOST, HeaderValue::from_static("127.0.0.1"));
+        headers.insert(header::CONNECTION, HeaderValue::from_static("keep-alive"));
+        headers.insert(
+            header::AUTHORIZATION,
+            HeaderValue::from_static("Bearer token"),
+        );
+        let relayed = relayed_headers(&headers);
+        assert_eq!(relayed.len(), 1);
+        assert!(relayed.contains_key(header::AUTHORIZATION));
+    }
+}
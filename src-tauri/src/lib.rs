@@ -5,23 +5,36 @@ use rust_i18n::t;
 use serde::Serialize;
 use std::{env, fs::OpenOptions, path::PathBuf, str::FromStr, thread};
 use tauri::{AppHandle, Manager, State, async_runtime::Mutex};
+mod backup;
+mod capabilities;
+mod clients;
+mod config;
 mod database;
 mod entities;
+mod error;
+mod events;
 mod favicon;
+mod gateway;
 mod menu;
+mod metadata;
+mod nodeinfo;
+mod proxy;
+mod scheduler;
+mod server_url;
 mod settings;
+mod shortcuts;
 mod streaming;
-use tauri::Emitter;
+mod tls;
+mod webfinger;
+use error::Error;
 
 rust_i18n::i18n!("locales");
 
 #[tauri::command]
 async fn list_servers(
     sqlite_pool: State<'_, sqlx::SqlitePool>,
-) -> Result<Vec<(entities::Server, Option<entities::Account>)>, String> {
-    let servers = database::list_servers(&sqlite_pool)
-        .await
-        .map_err(|e| e.to_string())?;
+) -> Result<Vec<(entities::Server, Option<entities::Account>)>, Error> {
+    let servers = database::list_servers(&sqlite_pool).await?;
 
     Ok(servers)
 }
@@ -30,78 +43,177 @@ async fn list_servers(
 async fn get_server(
     sqlite_pool: State<'_, sqlx::SqlitePool>,
     id: i64,
-) -> Result<entities::Server, String> {
-    let server = database::get_server(&sqlite_pool, id)
-        .await
-        .map_err(|e| e.to_string())?;
+) -> Result<entities::Server, Error> {
+    let server = database::get_server(&sqlite_pool, id).await?;
 
     Ok(server)
 }
 
+/// `domain` is a domain, a full URL with a port and a path, a profile URL or a handle such as `@alice@example.social`.
+/// `ca_certificate` is PEM of extra root certificates for servers with an internal CA.
 #[tauri::command]
 async fn add_server(
     app_handle: AppHandle,
     sqlite_pool: State<'_, sqlx::SqlitePool>,
+    clients: State<'_, clients::Clients>,
     domain: &str,
-) -> Result<entities::Server, String> {
-    let url = format!("https://{}", domain);
-
-    let icon = favicon::get_favicon_url(&url).await;
-    tracing::info!("The favicon for {} is {:#?}", &url, icon);
-    let sns = megalodon::detector(url.as_str())
-        .await
-        .map_err(|e| e.to_string())?;
+    ca_certificate: Option<String>,
+) -> Result<entities::Server, Error> {
+    let target_url = target_base_url(domain)?;
+    // The certificate is trusted only for the typed host until the server is resolved.
+    let client = match &ca_certificate {
+        Some(pem) => clients.http_with_roots(&target_url, pem)?,
+        None => clients.http_for(&target_url),
+    };
+    let detected = async {
+        let server = webfinger::resolve(&client, domain).await?;
+        if database::find_anonymous_server(&sqlite_pool, &server.base_url)
+            .await?
+            .is_some()
+        {
+            return Err(Error::already_exists(format!(
+                "{} is already added",
+                server.base_url
+            )));
+        }
+        // The certificate must be trusted before connecting to the resolved server.
+        if let Some(pem) = &ca_certificate {
+            tls::set_roots(&server.base_url, Some(pem))?;
+            clients.reset()?;
+        }
+        let sns = nodeinfo::detect_sns(&clients.http_for(&server.base_url), &server.base_url)
+            .await
+            .map_err(|err| {
+                let detail = match err.code {
+                    error::ErrorCode::UnsupportedSns => {
+                        format!(
+                            "{} is not a fediverse server: {}",
+                            server.domain, err.detail
+                        )
+                    }
+                    _ => format!("Could not reach {}: {}", server.domain, err.detail),
+                };
+                Error::new(err.code, detail)
+            })?;
+        Ok::<_, Error>((server, sns))
+    };
+    let (server_url::ServerUrl { domain, base_url }, sns) = match detected.await {
+        Ok(detected) => detected,
+        Err(err) => {
+            load_certificates(&sqlite_pool, &clients).await;
+            return Err(err);
+        }
+    };
+    let url = base_url;
     tracing::info!("The SNS for {} is {}", &url, sns);
 
-    let server = entities::Server::new(0, domain.to_string(), url, sns.to_string(), icon);
-    let created = database::add_server(&sqlite_pool, server)
-        .await
-        .map_err(|e| e.to_string())?;
+    let mut server = entities::Server::new(0, domain, url, sns.to_string(), None);
+    server.ca_certificate = ca_certificate;
+    // Capabilities fall back to the SNS when the server does not provide nodeinfo.
+    if let Err(err) =
+        nodeinfo::detect_software(&clients.http_for(&server.base_url), &mut server).await
+    {
+        tracing::warn!(
+            "Failed to detect the software of {}: {}",
+            server.base_url,
+            err
+        );
+    }
+    let mut created = database::add_server(&sqlite_pool, server).await?;
+    if let Err(err) =
+        favicon::refresh(&sqlite_pool, &clients.http_for(&created.base_url), &created).await
+    {
+        tracing::warn!(
+            "Failed to download the favicon for {}: {}",
+            created.base_url,
+            err
+        );
+    }
+    created = database::get_server(&sqlite_pool, created.id).await?;
 
-    app_handle
-        .emit("updated-servers", ())
-        .expect("Failed to send updated-servers event");
+    events::emit(&app_handle, "updated-servers", ());
 
     Ok(created)
 }
 
+/// Returns the base URL of the host which `domain` is resolved on.
+fn target_base_url(domain: &str) -> Result<String, Error> {
+    let server_url::ServerUrl { base_url, .. } = match server_url::parse_target(domain)? {
+        server_url::Target::Server(server) => server,
+        server_url::Target::Handle { host, .. } => host,
+    };
+    Ok(base_url)
+}
+
 #[tauri::command]
 async fn remove_server(
     app_handle: AppHandle,
     sqlite_pool: State<'_, sqlx::SqlitePool>,
+    clients: State<'_, clients::Clients>,
+    scheduler: State<'_, scheduler::Scheduler>,
     id: i64,
-) -> Result<(), String> {
-    database::remove_server(&sqlite_pool, id)
-        .await
-        .map_err(|e| e.to_string())?;
+) -> Result<(), Error> {
+    let server = database::get_server(&sqlite_pool, id).await?;
+    database::remove_server(&sqlite_pool, id).await?;
+    if let Some(account_id) = server.account_id {
+        app_handle
+            .state::<streaming::UserStreamings>()
+            .stop(account_id);
+    }
+    clients.remove_server(id);
+    scheduler.remove_server(id);
+    load_certificates(&sqlite_pool, &clients).await;
 
-    app_handle
-        .emit("updated-servers", ())
-        .expect("Failed to send updated-servers event");
-    app_handle
-        .emit("updated-timelines", ())
-        .expect("Failed to send updated-timeline event");
+    events::emit(&app_handle, "updated-servers", ());
+    events::emit(&app_handle, "updated-timelines", ());
+    Ok(())
+}
+
+#[tauri::command]
+async fn update_server_certificate(
+    app_handle: AppHandle,
+    sqlite_pool: State<'_, sqlx::SqlitePool>,
+    clients: State<'_, clients::Clients>,
+    id: i64,
+    ca_certificate: Option<String>,
+) -> Result<(), Error> {
+    if let Some(pem) = &ca_certificate {
+        tls::parse_certificates(pem)?;
+    }
+    let mut server = database::get_server(&sqlite_pool, id).await?;
+    server.ca_certificate = ca_certificate;
+    database::update_server(&sqlite_pool, server).await?;
+    clients.remove_server(id);
+    load_certificates(&sqlite_pool, &clients).await;
+
+    events::emit(&app_handle, "updated-servers", ());
     Ok(())
 }
 
 #[tauri::command]
 async fn add_application(
     _sqlite_pool: State<'_, sqlx::SqlitePool>,
+    clients: State<'_, clients::Clients>,
     url: &str,
-) -> Result<oauth::AppData, String> {
-    let sns = megalodon::detector(url).await.map_err(|e| e.to_string())?;
-    let client = megalodon::generator(sns, url.to_string(), None, Some(String::from("fedistar")))
-        .map_err(|err| err.to_string())?;
+) -> Result<oauth::AppData, Error> {
+    let sns = nodeinfo::detect_sns(&clients.http_for(url), url).await?;
+    // The server is not registered yet, so the client is not cached.
+    let client = clients.generate(sns.clone(), url, None)?;
 
+    // Firefish redirects to the base URL by default, which may be the gateway.
     let options = megalodon::megalodon::AppInputOptions {
+        redirect_uris: (sns == megalodon::SNS::Firefish).then(|| url.to_string()),
         ..Default::default()
     };
-    let app_data = client
+    let mut app_data = client
         .register_app(String::from("Fedistar"), &options)
-        .await
-        .map_err(|e| e.to_string())?;
+        .await?;
+    app_data.url = app_data.url.map(|u| clients.upstream_url(&u));
 
-    let url = app_data.url.clone().expect("URL is not found");
+    let url = app_data
+        .url
+        .clone()
+        .ok_or_else(|| Error::validation("The authorization URL is not found"))?;
     tracing::info!("Opening the URL: {}", url);
 
     thread::spawn(move || match open::that(url) {
@@ -117,20 +229,13 @@ async fn add_application(
 async fn authorize_code(
     app_handle: AppHandle,
     sqlite_pool: State<'_, sqlx::SqlitePool>,
+    clients: State<'_, clients::Clients>,
     server: entities::Server,
     app: oauth::AppData,
     code: &str,
-) -> Result<(), String> {
-    let sns = megalodon::detector(&server.base_url)
-        .await
-        .map_err(|e| e.to_string())?;
-    let client = megalodon::generator(
-        sns.clone(),
-        server.base_url.clone().to_string(),
-        None,
-        Some(String::from("fedistar")),
-    )
-    .map_err(|err| err.to_string())?;
+) -> Result<(), Error> {
+    let sns = megalodon::SNS::from_str(&server.sns).map_err(Error::unsupported_sns)?;
+    let client = clients.megalodon(&server, None)?;
 
     let client_id = app.client_id;
     let client_secret = app.client_secret;
@@ -148,21 +253,12 @@ async fn authorize_code(
             authorization_code,
             megalodon::default::NO_REDIRECT.to_string(),
         )
-        .await
-        .map_err(|e| e.to_string())?;
-
-    let authorized_client = megalodon::generator(
-        sns,
-        server.base_url.clone().to_string(),
-        Some(token_data.access_token.clone()),
-        Some(String::from("fedistar")),
-    )
-    .map_err(|err| err.to_string())?;
+        .await?;
 
-    let account_data = authorized_client
-        .verify_account_credentials()
-        .await
-        .map_err(|e| e.to_string())?;
+    let authorized_client =
+        clients.generate(sns, &server.base_url, Some(token_data.access_token.clone()))?;
+
+    let account_data = authorized_client.verify_account_credentials().await?;
 
     let account = entities::Account::new(
         0,
@@ -176,13 +272,10 @@ async fn authorize_code(
         false,
     );
 
-    database::add_account(&sqlite_pool, &server, &account)
-        .await
-        .map_err(|e| e.to_string())?;
+    database::add_account(&sqlite_pool, &server, &account).await?;
+    clients.remove_server(server.id);
 
-    app_handle
-        .emit("updated-servers", ())
-        .expect("Failed to send updated-servers event");
+    events::emit(&app_handle, "updated-servers", ());
 
     start_user_streaming(&app_handle, server, account).await?;
 
@@ -201,6 +294,52 @@ struct UpdatedSettingsPayload {
     settings: settings::Settings,
 }
 
+#[derive(Clone, Serialize)]
+struct UpdatedLanguagePayload {
+    language: settings::LocaleType,
+}
+
+/// Opens the local timeline of a server without signing in.
+/// The server is added in read-only mode unless it is already added, in which case the existing one is used.
+#[tauri::command]
+async fn browse_instance(
+    app_handle: AppHandle,
+    sqlite_pool: State<'_, sqlx::SqlitePool>,
+    clients: State<'_, clients::Clients>,
+    domain: &str,
+) -> Result<entities::Timeline, Error> {
+    let server = match add_server(
+        app_handle.clone(),
+        sqlite_pool.clone(),
+        clients.clone(),
+        domain,
+        None,
+    )
+    .await
+    {
+        Ok(server) => server,
+        Err(err) if err.code == error::ErrorCode::AlreadyExists => {
+            let client = clients.http_for(&target_base_url(domain)?);
+            let resolved = webfinger::resolve(&client, domain).await?;
+            database::find_anonymous_server(&sqlite_pool, &resolved.base_url)
+                .await?
+                .ok_or_else(|| Error::not_found(resolved.base_url))?
+        }
+        Err(err) => return Err(err),
+    };
+
+    add_timeline(
+        app_handle,
+        sqlite_pool,
+        server,
+        "local",
+        "Local",
+        "sm",
+        None,
+    )
+    .await
+}
+
 #[tauri::command]
 async fn add_timeline(
     app_handle: AppHandle,
@@ -210,24 +349,194 @@ async fn add_timeline(
     name: &str,
     column_width: &str,
     list_id: Option<&str>,
-) -> Result<(), String> {
-    let k = entities::timeline::Kind::from_str(kind)?;
-    let width = entities::timeline::ColumnWidth::from_str(column_width)?;
-    let timeline = database::add_timeline(&sqlite_pool, &server, &k, name, list_id, &width)
-        .await
-        .map_err(|e| e.to_string())?;
+) -> Result<entities::Timeline, Error> {
+    let k = entities::timeline::Kind::from_str(kind).map_err(Error::validation)?;
+    let width =
+        entities::timeline::ColumnWidth::from_str(column_width).map_err(Error::validation)?;
+    let mut timeline = entities::Timeline::new(
+        0,
+        server.id,
+        k,
+        name.to_string(),
+        0,
+        list_id.map(|i| i.to_string()),
+        width,
+    );
+    // The name of account timelines is the acct of the account, such as `@alice@example.social`.
+    if timeline.kind == entities::timeline::Kind::Account {
+        let (id, acct) = find_remote_account(&app_handle, &sqlite_pool, &server, name).await?;
+        timeline.remote_account_id = Some(id);
+        timeline.remote_acct = Some(acct);
+    }
+    let created = database::add_timeline(&sqlite_pool, &timeline).await?;
 
-    let timelines = database::list_timelines(&sqlite_pool)
-        .await
-        .map_err(|e| e.to_string())?;
+    let timelines = database::list_timelines(&sqlite_pool).await?;
 
-    app_handle
-        .emit("updated-timelines", UpdatedTimelinePayload { timelines })
-        .expect("Failed to send updated-timelines event");
+    events::emit(
+        &app_handle,
+        "updated-timelines",
+        UpdatedTimelinePayload { timelines },
+    );
 
-    start_timeline_streaming(&app_handle, &sqlite_pool, server, timeline).await?;
+    start_timeline_streaming(&app_handle, &sqlite_pool, server, created.clone()).await?;
 
-    Ok(())
+    Ok(created)
+}
+
+#[tauri::command]
+async fn clone_timeline(
+    app_handle: AppHandle,
+    sqlite_pool: State<'_, sqlx::SqlitePool>,
+    id: i64,
+    server_id: i64,
+) -> Result<entities::Timeline, Error> {
+    let source = database::find_timeline(&sqlite_pool, id).await?;
+    let server = database::get_server(&sqlite_pool, server_id).await?;
+    if server.account_id.is_none() && source.kind.requires_account() {
+        return Err(Error::validation(format!(
+            "{} does not have an account, so the {} timeline can not be cloned to it",
+            server.domain, source.kind
+        )));
+    }
+    // Timelines are found by server, kind and name in the workspace, so the same column can not be there twice.
+    let duplicated = database::list_all_timelines(&sqlite_pool)
+        .await?
+        .iter()
+        .any(|t| {
+            t.workspace_id == source.workspace_id
+                && t.server_id == server.id
+                && t.kind == source.kind
+                && t.name == source.name
+        });
+    if duplicated {
+        return Err(Error::already_exists(format!(
+            "{} already has the timeline {} in this workspace",
+            server.domain, source.name
+        )));
+    }
+
+    // List ids are only meaningful on the account that owns the list, so find the list with the same title.
+    let list_id = if source.kind == entities::timeline::Kind::List {
+        Some(find_list_id(&app_handle, &sqlite_pool, &server, &source.name).await?)
+    } else {
+        source.list_id.clone()
+    };
+
+    let mut timeline = entities::Timeline::new(
+        0,
+        server.id,
+        source.kind.clone(),
+        source.name.clone(),
+        0,
+        list_id,
+        source.column_width.clone(),
+    );
+    timeline.show_boosts = source.show_boosts;
+    timeline.show_replies = source.show_replies;
+    timeline.show_pinned = source.show_pinned;
+    timeline.notification_filter = source.notification_filter;
+    timeline.workspace_id = source.workspace_id;
+    // Account ids are different on each server, so find the same account by its acct.
+    if source.kind == entities::timeline::Kind::Account {
+        let Some(acct) = &source.remote_acct else {
+            return Err(Error::validation(format!(
+                "could not find acct for {}",
+                source.name
+            )));
+        };
+        let (id, acct) = find_remote_account(&app_handle, &sqlite_pool, &server, acct).await?;
+        timeline.remote_account_id = Some(id);
+        timeline.remote_acct = Some(acct);
+    }
+
+    let created = database::add_timeline(&sqlite_pool, &timeline).await?;
+
+    let timelines = database::list_timelines(&sqlite_pool).await?;
+
+    events::emit(
+        &app_handle,
+        "updated-timelines",
+        UpdatedTimelinePayload { timelines },
+    );
+
+    start_timeline_streaming(&app_handle, &sqlite_pool, server, created.clone()).await?;
+
+    Ok(created)
+}
+
+/// Looks up the account with its acct on the server, and returns its id and the acct including the domain.
+async fn find_remote_account(
+    app_handle: &AppHandle,
+    sqlite_pool: &sqlx::SqlitePool,
+    server: &entities::Server,
+    acct: &str,
+) -> Result<(String, String), Error> {
+    let mut account: Option<entities::Account> = None;
+    if let Some(account_id) = server.account_id {
+        let (a, _) = database::get_account(sqlite_pool, account_id).await?;
+        account = Some(a);
+    }
+
+    let client = app_handle
+        .state::<clients::Clients>()
+        .megalodon(server, account.as_ref())?;
+    let res = app_handle
+        .state::<scheduler::Scheduler>()
+        .run(
+            app_handle,
+            (server.id, account.as_ref().map(|a| a.id)),
+            scheduler::Priority::Interactive,
+            client.lookup_account(acct.trim_start_matches('@').to_string()),
+        )
+        .await?;
+
+    let found = res.json;
+    // Local accounts do not have the domain in their acct.
+    let acct = if found.acct.contains('@') {
+        found.acct
+    } else {
+        format!("{}@{}", found.acct, server.domain)
+    };
+    Ok((found.id, acct))
+}
+
+async fn find_list_id(
+    app_handle: &AppHandle,
+    sqlite_pool: &sqlx::SqlitePool,
+    server: &entities::Server,
+    title: &str,
+) -> Result<String, Error> {
+    let Some(account_id) = server.account_id else {
+        return Err(Error::validation(format!(
+            "{} does not have an account, so the list {} can not be found",
+            server.domain, title
+        )));
+    };
+    let (account, _) = database::get_account(sqlite_pool, account_id).await?;
+
+    let client = app_handle
+        .state::<clients::Clients>()
+        .megalodon(server, Some(&account))?;
+    let res = app_handle
+        .state::<scheduler::Scheduler>()
+        .run(
+            app_handle,
+            (server.id, Some(account.id)),
+            scheduler::Priority::Interactive,
+            client.get_lists(),
+        )
+        .await?;
+
+    res.json()
+        .into_iter()
+        .find(|list| list.title == title)
+        .map(|list| list.id)
+        .ok_or_else(|| {
+            Error::not_found(format!(
+                "The list {} does not exist on {}",
+                title, server.domain
+            ))
+        })
 }
 
 #[tauri::command]
@@ -236,11 +545,9 @@ async fn get_timeline(
     server: entities::Server,
     kind: &str,
     name: &str,
-) -> Result<entities::Timeline, String> {
-    let k = entities::timeline::Kind::from_str(kind)?;
-    let timeline = database::get_timeline(&sqlite_pool, &server, &k, name)
-        .await
-        .map_err(|e| e.to_string())?;
+) -> Result<entities::Timeline, Error> {
+    let k = entities::timeline::Kind::from_str(kind).map_err(Error::validation)?;
+    let timeline = database::get_timeline(&sqlite_pool, &server, &k, name).await?;
 
     Ok(timeline)
 }
@@ -248,10 +555,8 @@ async fn get_timeline(
 #[tauri::command]
 async fn list_accounts(
     sqlite_pool: State<'_, sqlx::SqlitePool>,
-) -> Result<Vec<(entities::Account, entities::Server)>, String> {
-    let accounts = database::list_account(&sqlite_pool)
-        .await
-        .map_err(|e| e.to_string())?;
+) -> Result<Vec<(entities::Account, entities::Server)>, Error> {
+    let accounts = database::list_account(&sqlite_pool).await?;
 
     Ok(accounts)
 }
@@ -259,50 +564,137 @@ async fn list_accounts(
 #[tauri::command]
 async fn list_timelines(
     sqlite_pool: State<'_, sqlx::SqlitePool>,
-) -> Result<Vec<(entities::Timeline, entities::Server)>, String> {
-    let timelines = database::list_timelines(&sqlite_pool)
-        .await
-        .map_err(|e| e.to_string())?;
+) -> Result<Vec<(entities::Timeline, entities::Server)>, Error> {
+    let timelines = database::list_timelines(&sqlite_pool).await?;
 
     Ok(timelines)
 }
 
+/// Returns the status which was emitted as `timeline-status` for the timeline, if any.
+#[tauri::command]
+fn get_timeline_status(
+    statuses: State<'_, streaming::TimelineStatuses>,
+    id: i64,
+) -> Option<streaming::TimelineStatus> {
+    statuses.get(id)
+}
+
 #[tauri::command]
 async fn remove_timeline(
     app_handle: AppHandle,
     sqlite_pool: State<'_, sqlx::SqlitePool>,
     id: i64,
-) -> Result<(), String> {
-    database::remove_timeline(&sqlite_pool, id)
-        .await
-        .map_err(|e| e.to_string())?;
-
+) -> Result<(), Error> {
+    database::remove_timeline(&sqlite_pool, id).await?;
     app_handle
-        .emit("updated-timelines", ())
-        .expect("Failed to updated-timelines event");
+        .state::<streaming::TimelineStreamings>()
+        .stop(id);
+    app_handle.state::<streaming::TimelineStatuses>().remove(id);
+
+    events::emit(&app_handle, "updated-timelines", ());
     Ok(())
 }
 
 #[tauri::command]
-async fn get_account(
+async fn list_workspaces(
+    sqlite_pool: State<'_, sqlx::SqlitePool>,
+) -> Result<Vec<entities::Workspace>, Error> {
+    let workspaces = database::list_workspaces(&sqlite_pool).await?;
+
+    Ok(workspaces)
+}
+
+#[tauri::command]
+async fn create_workspace(
+    app_handle: AppHandle,
+    sqlite_pool: State<'_, sqlx::SqlitePool>,
+    name: &str,
+    icon: Option<&str>,
+) -> Result<entities::Workspace, Error> {
+    let workspace = database::add_workspace(&sqlite_pool, name, icon).await?;
+
+    events::emit(&app_handle, "updated-workspaces", ());
+
+    Ok(workspace)
+}
+
+#[tauri::command]
+async fn rename_workspace(
+    app_handle: AppHandle,
     sqlite_pool: State<'_, sqlx::SqlitePool>,
     id: i64,
-) -> Result<(entities::Account, entities::Server), String> {
-    let account = database::get_account(&sqlite_pool, id)
-        .await
-        .map_err(|e| e.to_string())?;
+    name: &str,
+    icon: Option<&str>,
+) -> Result<(), Error> {
+    database::update_workspace(&sqlite_pool, id, name, icon).await?;
 
-    Ok(account)
+    events::emit(&app_handle, "updated-workspaces", ());
+
+    Ok(())
+}
+
+#[tauri::command]
+async fn remove_workspace(
+    app_handle: AppHandle,
+    sqlite_pool: State<'_, sqlx::SqlitePool>,
+    id: i64,
+) -> Result<(), Error> {
+    let workspaces = database::list_workspaces(&sqlite_pool).await?;
+    let was_active = workspaces.iter().any(|w| w.id == id && w.active);
+
+    database::remove_workspace(&sqlite_pool, id).await?;
+
+    events::emit(&app_handle, "updated-workspaces", ());
+
+    if was_active {
+        restart_timeline_streamings(&app_handle, &sqlite_pool).await?;
+
+        let timelines = database::list_timelines(&sqlite_pool).await?;
+        events::emit(
+            &app_handle,
+            "updated-timelines",
+            UpdatedTimelinePayload { timelines },
+        );
+    }
+
+    Ok(())
 }
 
 #[tauri::command]
-async fn set_usual_account(
+async fn switch_workspace(
+    app_handle: AppHandle,
     sqlite_pool: State<'_, sqlx::SqlitePool>,
     id: i64,
-) -> Result<(), String> {
-    let _ = database::set_usual_account(&sqlite_pool, id)
-        .await
-        .map_err(|e| e.to_string())?;
+) -> Result<entities::Workspace, Error> {
+    let workspace = database::set_active_workspace(&sqlite_pool, id).await?;
+
+    restart_timeline_streamings(&app_handle, &sqlite_pool).await?;
+
+    let timelines = database::list_timelines(&sqlite_pool).await?;
+
+    events::emit(&app_handle, "updated-workspaces", ());
+    events::emit(
+        &app_handle,
+        "updated-timelines",
+        UpdatedTimelinePayload { timelines },
+    );
+
+    Ok(workspace)
+}
+
+#[tauri::command]
+async fn get_account(
+    sqlite_pool: State<'_, sqlx::SqlitePool>,
+    id: i64,
+) -> Result<(entities::Account, entities::Server), Error> {
+    let account = database::get_account(&sqlite_pool, id).await?;
+
+    Ok(account)
+}
+
+#[tauri::command]
+async fn set_usual_account(sqlite_pool: State<'_, sqlx::SqlitePool>, id: i64) -> Result<(), Error> {
+    let _ = database::set_usual_account(&sqlite_pool, id).await?;
     Ok(())
 }
 
@@ -311,18 +703,16 @@ async fn switch_left_timeline(
     app_handle: AppHandle,
     sqlite_pool: State<'_, sqlx::SqlitePool>,
     id: i64,
-) -> Result<(), String> {
-    database::switch_left_timeline(&sqlite_pool, id)
-        .await
-        .map_err(|e| e.to_string())?;
+) -> Result<(), Error> {
+    database::switch_left_timeline(&sqlite_pool, id).await?;
 
-    let timelines = database::list_timelines(&sqlite_pool)
-        .await
-        .map_err(|e| e.to_string())?;
+    let timelines = database::list_timelines(&sqlite_pool).await?;
 
-    app_handle
-        .emit("updated-timelines", UpdatedTimelinePayload { timelines })
-        .expect("Failed to updated-timelines event");
+    events::emit(
+        &app_handle,
+        "updated-timelines",
+        UpdatedTimelinePayload { timelines },
+    );
 
     Ok(())
 }
@@ -332,18 +722,55 @@ async fn switch_right_timeline(
     app_handle: AppHandle,
     sqlite_pool: State<'_, sqlx::SqlitePool>,
     id: i64,
-) -> Result<(), String> {
-    database::switch_right_timeline(&sqlite_pool, id)
-        .await
-        .map_err(|e| e.to_string())?;
+) -> Result<(), Error> {
+    database::switch_right_timeline(&sqlite_pool, id).await?;
 
-    let timelines = database::list_timelines(&sqlite_pool)
-        .await
-        .map_err(|e| e.to_string())?;
+    let timelines = database::list_timelines(&sqlite_pool).await?;
 
-    app_handle
-        .emit("updated-timelines", UpdatedTimelinePayload { timelines })
-        .expect("Failed to updated-timelines event");
+    events::emit(
+        &app_handle,
+        "updated-timelines",
+        UpdatedTimelinePayload { timelines },
+    );
+
+    Ok(())
+}
+
+#[tauri::command]
+async fn move_timeline(
+    app_handle: AppHandle,
+    sqlite_pool: State<'_, sqlx::SqlitePool>,
+    id: i64,
+    new_index: usize,
+) -> Result<(), Error> {
+    database::move_timeline(&sqlite_pool, id, new_index).await?;
+
+    let timelines = database::list_timelines(&sqlite_pool).await?;
+
+    events::emit(
+        &app_handle,
+        "updated-timelines",
+        UpdatedTimelinePayload { timelines },
+    );
+
+    Ok(())
+}
+
+#[tauri::command]
+async fn reorder_timelines(
+    app_handle: AppHandle,
+    sqlite_pool: State<'_, sqlx::SqlitePool>,
+    ids: Vec<i64>,
+) -> Result<(), Error> {
+    database::reorder_timelines(&sqlite_pool, &ids).await?;
+
+    let timelines = database::list_timelines(&sqlite_pool).await?;
+
+    events::emit(
+        &app_handle,
+        "updated-timelines",
+        UpdatedTimelinePayload { timelines },
+    );
 
     Ok(())
 }
@@ -354,20 +781,19 @@ async fn update_column_width(
     sqlite_pool: State<'_, sqlx::SqlitePool>,
     id: i64,
     column_width: &str,
-) -> Result<(), String> {
-    let width = entities::timeline::ColumnWidth::from_str(column_width)?;
+) -> Result<(), Error> {
+    let width =
+        entities::timeline::ColumnWidth::from_str(column_width).map_err(Error::validation)?;
 
-    database::update_column_width(&sqlite_pool, id, &width)
-        .await
-        .map_err(|e| e.to_string())?;
+    database::update_column_width(&sqlite_pool, id, &width).await?;
 
-    let timelines = database::list_timelines(&sqlite_pool)
-        .await
-        .map_err(|e| e.to_string())?;
+    let timelines = database::list_timelines(&sqlite_pool).await?;
 
-    app_handle
-        .emit("updated-timelines", UpdatedTimelinePayload { timelines })
-        .expect("Failed to updated-timelines event");
+    events::emit(
+        &app_handle,
+        "updated-timelines",
+        UpdatedTimelinePayload { timelines },
+    );
 
     Ok(())
 }
@@ -378,20 +804,18 @@ async fn update_show_boosts(
     sqlite_pool: State<'_, sqlx::SqlitePool>,
     id: i64,
     show_boosts: bool,
-) -> Result<(), String> {
-    database::update_show_boosts(&sqlite_pool, id, show_boosts)
-        .await
-        .map_err(|e| e.to_string())?;
+) -> Result<(), Error> {
+    database::update_show_boosts(&sqlite_pool, id, show_boosts).await?;
 
-    let timelines = database::list_timelines(&sqlite_pool)
-        .await
-        .map_err(|e| e.to_string())?;
+    let timelines = database::list_timelines(&sqlite_pool).await?;
 
-    app_handle
-        .emit("updated-timelines", UpdatedTimelinePayload { timelines })
-        .expect("Failed to updated-timelines event");
+    events::emit(
+        &app_handle,
+        "updated-timelines",
+        UpdatedTimelinePayload { timelines },
+    );
 
-    Ok(())
+    restart_account_timeline(&app_handle, &sqlite_pool, id).await
 }
 
 #[tauri::command]
@@ -400,24 +824,97 @@ async fn update_show_replies(
     sqlite_pool: State<'_, sqlx::SqlitePool>,
     id: i64,
     show_replies: bool,
-) -> Result<(), String> {
-    database::update_show_replies(&sqlite_pool, id, show_replies)
-        .await
-        .map_err(|e| e.to_string())?;
+) -> Result<(), Error> {
+    database::update_show_replies(&sqlite_pool, id, show_replies).await?;
 
-    let timelines = database::list_timelines(&sqlite_pool)
-        .await
-        .map_err(|e| e.to_string())?;
+    let timelines = database::list_timelines(&sqlite_pool).await?;
 
-    app_handle
-        .emit("updated-timelines", UpdatedTimelinePayload { timelines })
-        .expect("Failed to updated-timelines event");
+    events::emit(
+        &app_handle,
+        "updated-timelines",
+        UpdatedTimelinePayload { timelines },
+    );
+
+    restart_account_timeline(&app_handle, &sqlite_pool, id).await
+}
+
+/// Returns the notification types in megalodon for JavaScript which the filter shows.
+#[tauri::command]
+fn get_notification_types(
+    notification_filter: entities::timeline::NotificationFilter,
+) -> Vec<String> {
+    notification_filter.type_names()
+}
+
+/// `notification_filter` is one of the types in `NotificationFilter`, or `None` to show all notifications.
+#[tauri::command]
+async fn update_notification_filter(
+    app_handle: AppHandle,
+    sqlite_pool: State<'_, sqlx::SqlitePool>,
+    id: i64,
+    notification_filter: Option<&str>,
+) -> Result<(), Error> {
+    let filter = notification_filter
+        .map(entities::timeline::NotificationFilter::from_str)
+        .transpose()
+        .map_err(Error::validation)?;
+    database::update_notification_filter(&sqlite_pool, id, filter).await?;
+
+    let timelines = database::list_timelines(&sqlite_pool).await?;
+
+    events::emit(
+        &app_handle,
+        "updated-timelines",
+        UpdatedTimelinePayload { timelines },
+    );
 
     Ok(())
 }
 
 #[tauri::command]
-fn read_settings(settings_path: State<'_, PathBuf>) -> Result<settings::Settings, String> {
+async fn update_show_pinned(
+    app_handle: AppHandle,
+    sqlite_pool: State<'_, sqlx::SqlitePool>,
+    id: i64,
+    show_pinned: bool,
+) -> Result<(), Error> {
+    database::update_show_pinned(&sqlite_pool, id, show_pinned).await?;
+
+    let timelines = database::list_timelines(&sqlite_pool).await?;
+
+    events::emit(
+        &app_handle,
+        "updated-timelines",
+        UpdatedTimelinePayload { timelines },
+    );
+
+    restart_account_timeline(&app_handle, &sqlite_pool, id).await
+}
+
+// Account timelines exclude replies and boosts on the server, so polling is restarted with the new options.
+async fn restart_account_timeline(
+    app_handle: &AppHandle,
+    sqlite_pool: &sqlx::SqlitePool,
+    id: i64,
+) -> Result<(), Error> {
+    let timeline = database::find_timeline(sqlite_pool, id).await?;
+    if timeline.kind != entities::timeline::Kind::Account {
+        return Ok(());
+    }
+    // Timelines of inactive workspaces are started when the workspace is switched to.
+    let workspaces = database::list_workspaces(sqlite_pool).await?;
+    if !workspaces
+        .iter()
+        .any(|w| w.active && Some(w.id) == timeline.workspace_id)
+    {
+        return Ok(());
+    }
+    let server = database::get_server(sqlite_pool, timeline.server_id).await?;
+    start_timeline_streaming(app_handle, sqlite_pool, server, timeline).await
+}
+
+#[tauri::command]
+fn read_settings(settings_path: State<'_, PathBuf>) -> Result<settings::Settings, Error> {
     settings::read_settings(&settings_path)
 }
 
@@ -426,46 +923,232 @@ fn save_settings(
     app_handle: AppHandle,
     settings_path: State<'_, PathBuf>,
     obj: settings::Settings,
-) -> Result<(), String> {
+) -> Result<(), Error> {
+    shortcuts::validate(&obj.keybindings)?;
+    if let Some(proxy) = &obj.proxy {
+        proxy::validate(proxy)?;
+    }
     let _ = settings::save_settings(&settings_path, &obj)?;
     let res = settings::read_settings(&settings_path)?;
-    app_handle
-        .emit("updated-settings", UpdatedSettingsPayload { settings: res })
-        .expect("Failed to update-settings event");
+    apply_settings(&app_handle, &res);
+    events::emit(
+        &app_handle,
+        "updated-settings",
+        UpdatedSettingsPayload { settings: res },
+    );
+    Ok(())
+}
+
+#[tauri::command]
+async fn export_config(
+    sqlite_pool: State<'_, sqlx::SqlitePool>,
+    settings_path: State<'_, PathBuf>,
+    path: PathBuf,
+    password: Option<String>,
+) -> Result<(), Error> {
+    let exported = config::export(&sqlite_pool, &settings_path, password.as_deref()).await?;
+    config::write(&path, &exported)?;
+    tracing::info!("The configuration is exported to {:?}", path);
+
+    Ok(())
+}
+
+#[tauri::command]
+async fn import_config(
+    app_handle: AppHandle,
+    sqlite_pool: State<'_, sqlx::SqlitePool>,
+    clients: State<'_, clients::Clients>,
+    settings_path: State<'_, PathBuf>,
+    path: PathBuf,
+    password: Option<String>,
+    dry_run: bool,
+) -> Result<config::ImportReport, Error> {
+    let imported = config::read(&path)?;
+    let (report, created) = config::import(
+        &sqlite_pool,
+        &clients,
+        &settings_path,
+        imported,
+        password.as_deref(),
+        dry_run,
+    )
+    .await?;
+    if dry_run {
+        return Ok(report);
+    }
+    tracing::info!("The configuration is imported from {:?}", path);
+
+    // Accounts which already existed are streaming, so only the imported ones are started.
+    for (account, server) in created.into_iter() {
+        start_user_streaming(&app_handle, server, account).await?;
+    }
+    restart_timeline_streamings(&app_handle, &sqlite_pool).await?;
+
+    let timelines = database::list_timelines(&sqlite_pool).await?;
+    let settings = settings::read_settings(&settings_path)?;
+    // The settings watcher ignores writes by the app, so the imported settings are applied here.
+    apply_settings(&app_handle, &settings);
+
+    events::emit(&app_handle, "updated-servers", ());
+    events::emit(&app_handle, "updated-workspaces", ());
+    events::emit(
+        &app_handle,
+        "updated-timelines",
+        UpdatedTimelinePayload { timelines },
+    );
+    events::emit(
+        &app_handle,
+        "updated-settings",
+        UpdatedSettingsPayload { settings },
+    );
+
+    Ok(report)
+}
+
+#[tauri::command]
+async fn get_account_settings(
+    sqlite_pool: State<'_, sqlx::SqlitePool>,
+    account_id: i64,
+) -> Result<entities::AccountSettings, Error> {
+    let settings = database::get_account_settings(&sqlite_pool, account_id)
+        .await?
+        .unwrap_or(entities::AccountSettings {
+            account_id,
+            ..Default::default()
+        });
+
+    Ok(settings)
+}
+
+#[tauri::command]
+async fn update_account_settings(
+    app_handle: AppHandle,
+    sqlite_pool: State<'_, sqlx::SqlitePool>,
+    obj: entities::AccountSettings,
+) -> Result<(), Error> {
+    database::update_account_settings(&sqlite_pool, &obj).await?;
+    events::emit(&app_handle, "updated-settings-overrides", ());
+
+    Ok(())
+}
+
+#[tauri::command]
+async fn get_timeline_settings(
+    sqlite_pool: State<'_, sqlx::SqlitePool>,
+    timeline_id: i64,
+) -> Result<entities::TimelineSettings, Error> {
+    let settings = database::get_timeline_settings(&sqlite_pool, timeline_id)
+        .await?
+        .unwrap_or(entities::TimelineSettings {
+            timeline_id,
+            ..Default::default()
+        });
+
+    Ok(settings)
+}
+
+#[tauri::command]
+async fn update_timeline_settings(
+    app_handle: AppHandle,
+    sqlite_pool: State<'_, sqlx::SqlitePool>,
+    obj: entities::TimelineSettings,
+) -> Result<(), Error> {
+    database::update_timeline_settings(&sqlite_pool, &obj).await?;
+    events::emit(&app_handle, "updated-settings-overrides", ());
+
     Ok(())
 }
 
+/// Returns the settings applied to the given account and timeline.
+/// When only the timeline is given, the account of its server is used.
 #[tauri::command]
-fn toggle_menu(app_handle: AppHandle, settings_path: State<'_, PathBuf>) -> Result<(), String> {
+async fn get_effective_settings(
+    sqlite_pool: State<'_, sqlx::SqlitePool>,
+    settings_path: State<'_, PathBuf>,
+    account_id: Option<i64>,
+    timeline_id: Option<i64>,
+) -> Result<settings::EffectiveSettings, Error> {
+    let global = settings::read_settings(&settings_path)?;
+
+    let mut account_id = account_id;
+    let mut timeline_settings = None;
+    if let Some(timeline_id) = timeline_id {
+        if account_id.is_none() {
+            let timeline = database::find_timeline(&sqlite_pool, timeline_id).await?;
+            let server = database::get_server(&sqlite_pool, timeline.server_id).await?;
+            account_id = server.account_id;
+        }
+        timeline_settings = database::get_timeline_settings(&sqlite_pool, timeline_id).await?;
+    }
+    let account_settings = match account_id {
+        Some(account_id) => database::get_account_settings(&sqlite_pool, account_id).await?,
+        None => None,
+    };
+
+    Ok(settings::resolve(
+        &global,
+        account_settings.as_ref(),
+        timeline_settings.as_ref(),
+    ))
+}
+
+#[tauri::command]
+fn toggle_menu(app_handle: AppHandle, settings_path: State<'_, PathBuf>) -> Result<(), Error> {
     let window_handle = app_handle
         .get_webview_window("main")
-        .expect("Failed to get main window");
-    let mut s = settings::read_settings(&settings_path).map_err(|e| e.to_string())?;
+        .ok_or_else(|| Error::not_found("The main window is not found"))?;
+    let mut s = settings::read_settings(&settings_path)?;
 
     match window_handle.is_menu_visible() {
         Ok(true) => {
-            window_handle.hide_menu().expect("Failed to hide menu");
+            window_handle.hide_menu()?;
             s.app_menu = Some(settings::AppMenu { hidden: true });
             let _ = settings::save_settings(&settings_path, &s);
             Ok(())
         }
         Ok(false) => {
-            window_handle.show_menu().expect("Failed to show menu");
+            window_handle.show_menu()?;
             s.app_menu = Some(settings::AppMenu { hidden: false });
             let _ = settings::save_settings(&settings_path, &s);
             Ok(())
         }
-        Err(e) => Err(e.to_string()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[tauri::command]
+fn get_database_status(status: State<'_, backup::DatabaseStatus>) -> backup::DatabaseStatus {
+    status.inner().clone()
+}
+
+#[tauri::command]
+fn list_database_backups(backups: State<'_, backup::Backups>) -> Vec<String> {
+    backups.list()
+}
+
+#[tauri::command]
+async fn restore_database_backup(
+    app_handle: AppHandle,
+    sqlite_pool: State<'_, sqlx::SqlitePool>,
+    backups: State<'_, backup::Backups>,
+    name: Option<String>,
+) -> Result<(), Error> {
+    match backups.restore(&sqlite_pool, name.as_deref()).await {
+        Ok(_) => app_handle.restart(),
+        // The pool is closed when the database file had to be replaced, so the app can not continue with it.
+        Err(e) if sqlite_pool.is_closed() => {
+            tracing::error!("Failed to restore the database: {}", e);
+            app_handle.restart()
+        }
+        Err(e) => Err(e),
     }
 }
 
 #[tauri::command]
 async fn get_instruction(
     sqlite_pool: State<'_, sqlx::SqlitePool>,
-) -> Result<entities::Instruction, String> {
-    let instruction = database::get_instruction(&sqlite_pool)
-        .await
-        .map_err(|e| e.to_string())?;
+) -> Result<entities::Instruction, Error> {
+    let instruction = database::get_instruction(&sqlite_pool).await?;
 
     Ok(instruction)
 }
@@ -474,13 +1157,9 @@ async fn get_instruction(
 async fn init_instruction(
     app_handle: AppHandle,
     sqlite_pool: State<'_, sqlx::SqlitePool>,
-) -> Result<(), String> {
-    let instruction = database::init_instruction(&sqlite_pool)
-        .await
-        .map_err(|e| e.to_string())?;
-    app_handle
-        .emit("updated-instruction", instruction)
-        .expect("Failed to send updated-instruction event");
+) -> Result<(), Error> {
+    let instruction = database::init_instruction(&sqlite_pool).await?;
+    events::emit(&app_handle, "updated-instruction", instruction);
 
     Ok(())
 }
@@ -490,11 +1169,10 @@ async fn init_instruction(
 async fn switch_devtools(app_handle: AppHandle) -> () {
     #[cfg(any(feature = "devtools", debug_assertions))]
     {
-        let window = app_handle
-            .get_webview_window("main")
-            .expect("Failed to get main window");
-        window.open_devtools();
-        window.close_devtools();
+        if let Some(window) = app_handle.get_webview_window("main") {
+            window.open_devtools();
+            window.close_devtools();
+        }
     }
     ()
 }
@@ -504,13 +1182,9 @@ async fn update_instruction(
     app_handle: AppHandle,
     sqlite_pool: State<'_, sqlx::SqlitePool>,
     step: u32,
-) -> Result<(), String> {
-    let instruction = database::update_instruction(&sqlite_pool, step)
-        .await
-        .map_err(|e| e.to_string())?;
-    app_handle
-        .emit("updated-instruction", instruction)
-        .expect("Failed to send updated-instruction event");
+) -> Result<(), Error> {
+    let instruction = database::update_instruction(&sqlite_pool, step).await?;
+    events::emit(&app_handle, "updated-instruction", instruction);
 
     Ok(())
 }
@@ -527,33 +1201,40 @@ async fn frontend_log(message: String, level: String) -> () {
 }
 
 #[tauri::command]
-async fn open_media(app_handle: AppHandle, media_url: String) -> () {
+async fn open_media(app_handle: AppHandle, media_url: String) -> Result<(), Error> {
     let encoded = general_purpose::STANDARD_NO_PAD.encode(media_url.clone());
+    let url = media_url
+        .parse()
+        .map_err(|e| Error::validation(format!("Invalid media URL {}: {}", media_url, e)))?;
+
+    tauri::WebviewWindowBuilder::new(&app_handle, encoded, tauri::WebviewUrl::External(url))
+        .menu(menu::media_menu(&app_handle)?)
+        .title(t!("media.title"))
+        .on_menu_event(|app, event| match event.id().0.as_str() {
+            "close" => {
+                if let Err(err) = app.close() {
+                    tracing::error!("Failed to close media window: {}", err);
+                }
+            }
+            _ => {}
+        })
+        .build()?;
 
-    let _ = tauri::WebviewWindowBuilder::new(
-        &app_handle,
-        encoded,
-        tauri::WebviewUrl::External(media_url.parse().unwrap()),
-    )
-    .menu(menu::media_menu(&app_handle).expect("failed to build media menu"))
-    .title(t!("media.title"))
-    .on_menu_event(|app, event| match event.id().0.as_str() {
-        "close" => {
-            app.close().expect("failed to close window");
-        }
-        _ => {}
-    })
-    .build()
-    .expect("failed to build media window");
+    Ok(())
 }
 
 #[tauri::command]
-async fn list_fonts() -> Result<Vec<String>, String> {
+fn get_emit_failures() -> u64 {
+    events::emit_failures()
+}
+
+#[tauri::command]
+async fn list_fonts() -> Result<Vec<String>, Error> {
     let font_source = SystemSource::new();
     let fonts = font_source.all_families().map_err(|e| {
         let err = e.to_string();
         tracing::error!("Failed to get font families {}", err);
-        err
+        Error::internal(err)
     })?;
 
     Ok(fonts)
@@ -561,33 +1242,190 @@ async fn list_fonts() -> Result<Vec<String>, String> {
 
 #[tauri::command]
 async fn get_instance(
+    app_handle: AppHandle,
     sqlite_pool: State<'_, sqlx::SqlitePool>,
     server_id: i64,
-) -> Result<megalodon::entities::Instance, String> {
-    let server = database::get_server(&sqlite_pool, server_id)
-        .await
-        .map_err(|e| e.to_string())?;
-    let sns = megalodon::SNS::from_str(server.sns.as_ref()).map_err(|e| e.to_string())?;
-    let client = megalodon::generator(sns, server.base_url, None, Some(String::from("fedistar")))
-        .map_err(|e| e.to_string())?;
-    let res = client.get_instance().await.map_err(|e| e.to_string())?;
-    Ok(res.json())
-}
-
-async fn update_favicon(sqlite_pool: &sqlx::SqlitePool) -> Result<(), String> {
-    let servers = database::list_servers(sqlite_pool)
-        .await
-        .map_err(|e| e.to_string())?;
-    for (mut server, _) in servers {
-        let url = server.base_url.clone();
-        let icon = favicon::get_favicon_url(&url).await;
-        tracing::info!("The favicon for {} is {:#?}", &url, icon);
-        server.favicon = icon;
-        let _ = database::update_server(sqlite_pool, server)
-            .await
-            .map_err(|e| e.to_string())?;
+) -> Result<megalodon::entities::Instance, Error> {
+    let metadata = metadata::get(&app_handle, &sqlite_pool, server_id).await?;
+    Ok(metadata.instance)
+}
+
+/// Returns the cached instance information, limits, rules and custom emojis of the server.
+#[tauri::command]
+async fn get_server_metadata(
+    app_handle: AppHandle,
+    sqlite_pool: State<'_, sqlx::SqlitePool>,
+    server_id: i64,
+) -> Result<entities::ServerMetadata, Error> {
+    metadata::get(&app_handle, &sqlite_pool, server_id).await
+}
+
+/// Returns the features which the server supports, which are detected from nodeinfo.
+#[tauri::command]
+async fn get_capabilities(
+    sqlite_pool: State<'_, sqlx::SqlitePool>,
+    server_id: i64,
+) -> Result<capabilities::Capabilities, Error> {
+    let server = database::get_server(&sqlite_pool, server_id).await?;
+    Ok(capabilities::resolve(&server))
+}
+
+#[tauri::command]
+async fn refresh_server_metadata(
+    app_handle: AppHandle,
+    sqlite_pool: State<'_, sqlx::SqlitePool>,
+    server_id: i64,
+) -> Result<entities::ServerMetadata, Error> {
+    metadata::refresh(
+        &app_handle,
+        &sqlite_pool,
+        server_id,
+        scheduler::Priority::Interactive,
+    )
+    .await
+}
+
+// settings.json may be edited by hand or managed with dotfiles, so watch its modification time
+// and apply the new content while running.
+async fn watch_settings(app_handle: AppHandle, settings_path: PathBuf) {
+    const POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+
+    let modified = |path: &PathBuf| {
+        std::fs::metadata(path)
+            .ok()
+            .map(|m| (m.modified().ok(), m.len()))
+    };
+    let mut last = modified(&settings_path);
+
+    loop {
+        tokio::time::sleep(POLL_INTERVAL).await;
+        let current = modified(&settings_path);
+        if current == last {
+            continue;
+        }
+        last = current;
+        // Saves from the app are applied when they are made, so only external edits are reloaded.
+        if last.is_none() || settings::is_written_by_app(&settings_path) {
+            continue;
+        }
+
+        tracing::info!("{:?} is changed", settings_path);
+        match settings::read_settings(&settings_path) {
+            Ok(s) => {
+                apply_settings(&app_handle, &s);
+                events::emit(
+                    &app_handle,
+                    "updated-settings",
+                    UpdatedSettingsPayload { settings: s },
+                );
+            }
+            Err(err) => {
+                tracing::error!("Failed to reload settings: {}", err);
+                events::emit(&app_handle, "settings-error", err);
+            }
+        }
+    }
+}
+
+#[allow(unused_variables)]
+fn apply_settings(app_handle: &AppHandle, s: &settings::Settings) {
+    match proxy::set(s.proxy.as_ref()) {
+        Ok(true) => {
+            if let Some(clients) = app_handle.try_state::<clients::Clients>() {
+                match clients.reset() {
+                    Ok(()) => restart_streamings(app_handle),
+                    Err(err) => tracing::error!("Failed to apply the proxy: {}", err),
+                }
+            }
+        }
+        Ok(false) => {}
+        Err(err) => tracing::error!("Failed to apply the proxy: {}", err),
+    }
+    if let Err(err) = shortcuts::register(app_handle, &s.keybindings) {
+        tracing::error!("Failed to register global shortcuts: {}", err);
+    }
+
+    let language = s.appearance.language.to_string();
+    if &*rust_i18n::locale() != language.as_str() {
+        rust_i18n::set_locale(language.as_str());
+        if let Err(err) = rebuild_menus(app_handle) {
+            tracing::error!("Failed to rebuild menus: {}", err);
+        }
+        events::emit(
+            app_handle,
+            "updated-language",
+            UpdatedLanguagePayload {
+                language: s.appearance.language.clone(),
+            },
+        );
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    if let Some(window) = app_handle.get_webview_window("main") {
+        let hidden = s.app_menu.as_ref().is_some_and(|m| m.hidden);
+        let res = if hidden {
+            window.hide_menu()
+        } else {
+            window.show_menu()
+        };
+        if let Err(err) = res {
+            tracing::error!("Failed to apply menu visibility: {}", err);
+        }
+    }
+}
+
+// Native menus and window titles are built with the locale at that time, so build them again.
+fn rebuild_menus(app_handle: &AppHandle) -> Result<(), Error> {
+    app_handle.set_menu(menu::set_menu(app_handle)?)?;
+    for (label, window) in app_handle.webview_windows() {
+        if label == "main" {
+            continue;
+        }
+        window.set_menu(menu::media_menu(app_handle)?)?;
+        window.set_title(&t!("media.title"))?;
+    }
+    Ok(())
+}
+
+async fn load_certificates(sqlite_pool: &sqlx::SqlitePool, clients: &clients::Clients) {
+    let res = match database::list_servers(sqlite_pool).await {
+        Ok(servers) => {
+            let servers: Vec<entities::Server> = servers.into_iter().map(|(s, _)| s).collect();
+            tls::load_roots(&servers).and_then(|_| clients.reset())
+        }
+        Err(err) => Err(err),
+    };
+    if let Err(err) = res {
+        tracing::error!("Failed to load certificates of servers: {}", err);
+    }
+}
+
+async fn update_favicon(
+    app_handle: &AppHandle,
+    sqlite_pool: &sqlx::SqlitePool,
+) -> Result<(), Error> {
+    let clients = app_handle.state::<clients::Clients>();
+    let scheduler = app_handle.state::<scheduler::Scheduler>();
+    let servers = database::list_servers(sqlite_pool).await?;
+    for (server, _) in servers {
+        let _guard = scheduler
+            .acquire(
+                app_handle,
+                (server.id, server.account_id),
+                scheduler::Priority::Background,
+            )
+            .await;
+        let http = clients.http_for(&server.base_url);
+        if let Err(err) = favicon::refresh(sqlite_pool, &http, &server).await {
+            tracing::warn!(
+                "Failed to refresh the favicon for {}: {}",
+                server.base_url,
+                err
+            );
+        }
     }
 
+    events::emit(app_handle, "updated-servers", ());
     Ok(())
 }
 
@@ -596,7 +1434,7 @@ async fn start_timeline_streaming(
     sqlite_pool: &sqlx::SqlitePool,
     server: entities::Server,
     timeline: entities::Timeline,
-) -> Result<(), String> {
+) -> Result<(), Error> {
     if timeline.kind == entities::timeline::Kind::Home
         || timeline.kind == entities::timeline::Kind::Notifications
         || timeline.kind == entities::timeline::Kind::Favourites
@@ -606,23 +1444,44 @@ async fn start_timeline_streaming(
     }
     let mut account: Option<entities::Account> = None;
     if let Some(account_id) = server.account_id {
-        let (a, _) = database::get_account(&sqlite_pool, account_id)
-            .await
-            .map_err(|e| e.to_string())?;
+        let (a, _) = database::get_account(&sqlite_pool, account_id).await?;
         account = Some(a);
     }
 
-    let app_handle = app_handle.clone();
-    tauri::async_runtime::spawn(async move {
-        match streaming::start(app_handle, &server, &timeline, account).await {
-            Ok(()) => tracing::info!(
-                "{} streaming is finished for @{}",
-                timeline.name,
-                server.domain
-            ),
-            Err(err) => tracing::error!("{}", err),
-        }
-    });
+    let timeline_id = timeline.id;
+    let handle = {
+        let app_handle = app_handle.clone();
+        tauri::async_runtime::spawn(async move {
+            match streaming::start(app_handle, &server, &timeline, account).await {
+                Ok(()) => tracing::info!(
+                    "{} streaming is finished for @{}",
+                    timeline.name,
+                    server.domain
+                ),
+                Err(err) => tracing::error!("{}", err),
+            }
+        })
+    };
+    app_handle
+        .state::<streaming::TimelineStreamings>()
+        .insert(timeline_id, handle);
+
+    Ok(())
+}
+
+async fn restart_timeline_streamings(
+    app_handle: &AppHandle,
+    sqlite_pool: &sqlx::SqlitePool,
+) -> Result<(), Error> {
+    app_handle
+        .state::<streaming::TimelineStreamings>()
+        .stop_all();
+
+    let timelines = database::list_timelines(sqlite_pool).await?;
+
+    for (timeline, server) in timelines.into_iter() {
+        start_timeline_streaming(app_handle, sqlite_pool, server, timeline).await?;
+    }
 
     Ok(())
 }
@@ -631,18 +1490,24 @@ async fn start_user_streaming(
     app_handle: &AppHandle,
     server: entities::Server,
     account: entities::Account,
-) -> Result<(), String> {
-    let app_handle = app_handle.clone();
-    tauri::async_runtime::spawn(async move {
-        match streaming::start_user(app_handle, &server, &account).await {
-            Ok(()) => tracing::info!(
-                "user streaming is finished for {}@{}",
-                account.username,
-                server.domain
-            ),
-            Err(err) => tracing::error!("{}", err),
-        }
-    });
+) -> Result<(), Error> {
+    let account_id = account.id;
+    let handle = {
+        let app_handle = app_handle.clone();
+        tauri::async_runtime::spawn(async move {
+            match streaming::start_user(app_handle, &server, &account).await {
+                Ok(()) => tracing::info!(
+                    "user streaming is finished for {}@{}",
+                    account.username,
+                    server.domain
+                ),
+                Err(err) => tracing::error!("{}", err),
+            }
+        })
+    };
+    app_handle
+        .state::<streaming::UserStreamings>()
+        .insert(account_id, handle);
 
     Ok(())
 }
@@ -650,18 +1515,14 @@ async fn start_user_streaming(
 async fn start_streamings(
     app_handle: &AppHandle,
     sqlite_pool: &sqlx::SqlitePool,
-) -> Result<(), String> {
-    let accounts = database::list_account(&sqlite_pool)
-        .await
-        .map_err(|e| e.to_string())?;
+) -> Result<(), Error> {
+    let accounts = database::list_account(&sqlite_pool).await?;
 
     for (account, server) in accounts.into_iter() {
         start_user_streaming(app_handle, server, account).await?;
     }
 
-    let timelines = database::list_timelines(&sqlite_pool)
-        .await
-        .map_err(|e| e.to_string())?;
+    let timelines = database::list_timelines(&sqlite_pool).await?;
 
     for (timeline, server) in timelines.into_iter() {
         start_timeline_streaming(app_handle, &sqlite_pool, server, timeline).await?;
@@ -670,6 +1531,26 @@ async fn start_streamings(
     Ok(())
 }
 
+/// Streamings keep the connections of the clients which they started with,
+/// so they are started again after the clients are built with another proxy.
+fn restart_streamings(app_handle: &AppHandle) {
+    let Some(sqlite_pool) = app_handle.try_state::<sqlx::SqlitePool>() else {
+        return;
+    };
+    let sqlite_pool = sqlite_pool.inner().clone();
+    let app_handle = app_handle.clone();
+    tauri::async_runtime::spawn(async move {
+        app_handle.state::<streaming::UserStreamings>().stop_all();
+        app_handle
+            .state::<streaming::TimelineStreamings>()
+            .stop_all();
+        match start_streamings(&app_handle, &sqlite_pool).await {
+            Ok(()) => tracing::info!("streamings are restarted with the new proxy"),
+            Err(err) => tracing::error!("Failed to restart streamings: {}", err),
+        }
+    });
+}
+
 fn init_logger(logfile_path: std::path::PathBuf) {
     let log_level = match env::var("FEDISTAR_LOG_LEVEL") {
         Ok(level) => match level.to_lowercase().as_str() {
@@ -720,6 +1601,7 @@ pub fn run() -> Result<(), Box<dyn std::error::Error>> {
     const DATABASE_FILE: &str = "fedistar.db";
     const LOGFILE_PATH: &str = "fedistar.log";
     const SETTINGS_PATH: &str = "settings.json";
+    const BACKUP_DIR: &str = "backups";
 
     #[cfg(debug_assertions)]
     {
@@ -737,25 +1619,48 @@ pub fn run() -> Result<(), Box<dyn std::error::Error>> {
         .plugin(tauri_plugin_fs::init())
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_window_state::Builder::default().build())
+        .register_asynchronous_uri_scheme_protocol(favicon::PROTOCOL, favicon::handle_protocol)
         .invoke_handler(tauri::generate_handler![
             list_servers,
             get_server,
             add_server,
             remove_server,
+            update_server_certificate,
             add_application,
             authorize_code,
             get_account,
             set_usual_account,
             list_accounts,
             add_timeline,
+            browse_instance,
+            clone_timeline,
             list_timelines,
+            get_timeline_status,
+            get_notification_types,
             remove_timeline,
             switch_left_timeline,
             switch_right_timeline,
+            move_timeline,
+            reorder_timelines,
+            list_workspaces,
+            create_workspace,
+            rename_workspace,
+            remove_workspace,
+            switch_workspace,
             toggle_menu,
             read_settings,
             save_settings,
+            get_account_settings,
+            update_account_settings,
+            get_timeline_settings,
+            update_timeline_settings,
+            get_effective_settings,
+            export_config,
+            import_config,
             get_instruction,
+            get_database_status,
+            list_database_backups,
+            restore_database_backup,
             init_instruction,
             update_instruction,
             switch_devtools,
@@ -763,10 +1668,16 @@ pub fn run() -> Result<(), Box<dyn std::error::Error>> {
             update_column_width,
             update_show_boosts,
             update_show_replies,
+            update_show_pinned,
+            update_notification_filter,
             open_media,
+            get_emit_failures,
             list_fonts,
             get_timeline,
             get_instance,
+            get_server_metadata,
+            get_capabilities,
+            refresh_server_metadata,
         ])
         .setup(move |app| {
             let app_handle = app.handle().clone();
@@ -786,6 +1697,12 @@ pub fn run() -> Result<(), Box<dyn std::error::Error>> {
             let settings_path = config_dir.join(SETTINGS_PATH);
             let res = settings::read_settings(&settings_path)?;
             rust_i18n::set_locale(res.appearance.language.to_string().as_str());
+            if let Err(err) = proxy::set(res.proxy.as_ref()) {
+                tracing::error!("Failed to apply the proxy: {}", err);
+            }
+            if let Err(err) = shortcuts::register(&app_handle, &res.keybindings) {
+                tracing::error!("Failed to register global shortcuts: {}", err);
+            }
 
             let database_dir_str = std::fs::canonicalize(&config_dir)
                 .expect("Failed to canonicalize")
@@ -795,36 +1712,70 @@ pub fn run() -> Result<(), Box<dyn std::error::Error>> {
                 .replace("//", "");
             let database_url = format!("sqlite://{}/{}", database_dir_str, DATABASE_FILE);
 
-            let sqlite_pool = block_on(database::create_sqlite_pool(&database_url))?;
-            block_on(database::migrate_database(&sqlite_pool))?;
+            app.manage(block_on(clients::Clients::new())?);
+            app.manage(scheduler::Scheduler::default());
+
+            let backups =
+                backup::Backups::new(config_dir.join(DATABASE_FILE), config_dir.join(BACKUP_DIR));
+            let (sqlite_pool, database_status) =
+                block_on(backup::open_database(&database_url, &backups))?;
+
+            app.manage(streaming::TimelineStreamings::default());
+            app.manage(streaming::UserStreamings::default());
+            app.manage(streaming::TimelineStatuses::default());
+
+            if database_status != backup::DatabaseStatus::Ok {
+                tracing::error!(
+                    "The database is not available, waiting for recovery: {:?}",
+                    database_status
+                );
+            } else {
+                let clients = app.state::<clients::Clients>();
+                block_on(load_certificates(&sqlite_pool, &clients));
+
+                {
+                    let sqlite_pool = sqlite_pool.clone();
+                    let app_handle = app_handle.clone();
+                    tauri::async_runtime::spawn(async move {
+                        match start_streamings(&app_handle, &sqlite_pool).await {
+                            Ok(()) => tracing::info!("user streamings are kicked for all accounts"),
+                            Err(e) => tracing::error!("{}", e.to_string()),
+                        }
+                    });
+                }
 
-            {
-                let sqlite_pool = sqlite_pool.clone();
-                let app_handle = app_handle.clone();
-                tauri::async_runtime::spawn(async move {
-                    match start_streamings(&app_handle, &sqlite_pool).await {
-                        Ok(()) => tracing::info!("user streamings are kicked for all accounts"),
-                        Err(e) => tracing::error!("{}", e.to_string()),
-                    }
-                });
+                {
+                    let sqlite_pool = sqlite_pool.clone();
+                    let app_handle = app_handle.clone();
+                    tauri::async_runtime::spawn(async move {
+                        let _ = update_favicon(&app_handle, &sqlite_pool).await;
+                    });
+                }
+
+                tauri::async_runtime::spawn(metadata::watch(
+                    app_handle.clone(),
+                    sqlite_pool.clone(),
+                ));
             }
 
+            app.manage(sqlite_pool);
+            app.manage(backups);
+            app.manage(database_status);
             {
-                let sqlite_pool = sqlite_pool.clone();
-                tauri::async_runtime::spawn(async move {
-                    let _ = update_favicon(&sqlite_pool).await;
-                });
+                let app_handle = app_handle.clone();
+                let settings_path = settings_path.clone();
+                tauri::async_runtime::spawn(watch_settings(app_handle, settings_path));
             }
 
-            app.manage(sqlite_pool);
             app.manage(Mutex::new(app_handle));
 
             app.manage(settings_path);
 
             app.on_menu_event(|app, event| match event.id().0.as_str() {
                 "crash_reporting" => {
-                    open::that("https://fedistar.net/help#crash_reporting")
-                        .expect("Failed to open the URL");
+                    if let Err(err) = open::that("https://fedistar.net/help#crash_reporting") {
+                        tracing::error!("Failed to open the URL: {}", err);
+                    }
                 }
                 "quit" => {
                     let app_handle = app.app_handle();
@@ -835,18 +1786,14 @@ pub fn run() -> Result<(), Box<dyn std::error::Error>> {
 
             #[cfg(debug_assertions)]
             {
-                let window = app
-                    .get_webview_window("main")
-                    .expect("Failed to get main window");
-                window.open_devtools();
-                window.close_devtools();
+                if let Some(window) = app.get_webview_window("main") {
+                    window.open_devtools();
+                    window.close_devtools();
+                }
             }
             Ok(())
         })
-        .menu(|app_handle| {
-            let m = menu::set_menu(app_handle).expect("Failed to generate menu");
-            Ok(m)
-        })
+        .menu(menu::set_menu)
         .on_page_load(|window, _payload| {
             #[cfg(not(target_os = "macos"))]
             {
@@ -855,10 +1802,9 @@ pub fn run() -> Result<(), Box<dyn std::error::Error>> {
                 if let Ok(s) = settings::read_settings(&settings_path) {
                     if let Some(app_menu) = s.app_menu {
                         if app_menu.hidden {
-                            window
-                                .app_handle()
-                                .hide_menu()
-                                .expect("Failed to hide menu");
+                            if let Err(err) = window.app_handle().hide_menu() {
+                                tracing::error!("Failed to hide menu: {}", err);
+                            }
                         }
                     }
                 }
@@ -0,0 +1,208 @@
+use chrono::Utc;
+use serde::Serialize;
+use std::time::Duration;
+use tauri::{AppHandle, Manager};
+
+use crate::{
+    clients::Clients,
+    database,
+    entities::ServerMetadata,
+    error::{Error, ErrorCode},
+    events, nodeinfo,
+    scheduler::{Priority, Scheduler},
+};
+
+/// Cached metadata is refreshed in the background after a day.
+const TTL: i64 = 24 * 60 * 60;
+/// How often the background task looks for stale metadata.
+const CHECK_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Clone, Serialize)]
+struct UpdatedServerMetadataPayload {
+    server_id: i64,
+}
+
+fn is_stale(metadata: &ServerMetadata, now: i64) -> bool {
+    now - metadata.fetched_at >= TTL
+}
+
+async fn cached(
+    sqlite_pool: &sqlx::SqlitePool,
+    server_id: i64,
+) -> Result<Option<ServerMetadata>, Error> {
+    match database::get_server_metadata(sqlite_pool, server_id).await {
+        // The cache is only a copy, so it is fetched again when it can not be read.
+        Err(err) if err.code == ErrorCode::Validation => {
+            tracing::warn!("Ignore the broken metadata of {}: {}", server_id, err);
+            Ok(None)
+        }
+        res => res,
+    }
+}
+
+/// Returns the cached metadata, which is fetched when nothing is cached yet.
+/// Stale metadata is returned as it is and refreshed in the background, so it is also available offline.
+pub(crate) async fn get(
+    app_handle: &AppHandle,
+    sqlite_pool: &sqlx::SqlitePool,
+    server_id: i64,
+) -> Result<ServerMetadata, Error> {
+    let Some(metadata) = cached(sqlite_pool, server_id).await? else {
+        return refresh(app_handle, sqlite_pool, server_id, Priority::Interactive).await;
+    };
+    if is_stale(&metadata, Utc::now().timestamp()) {
+        let app_handle = app_handle.clone();
+        let sqlite_pool = sqlite_pool.clone();
+        tauri::async_runtime::spawn(async move {
+            if let Err(err) =
+                refresh(&app_handle, &sqlite_pool, server_id, Priority::Background).await
+            {
+                tracing::warn!("Failed to refresh the metadata of {}: {}", server_id, err);
+            }
+        });
+    }
+    Ok(metadata)
+}
+
+/// Fetches the metadata from the server and caches it.
+pub(crate) async fn refresh(
+    app_handle: &AppHandle,
+    sqlite_pool: &sqlx::SqlitePool,
+    server_id: i64,
+    priority: Priority,
+) -> Result<ServerMetadata, Error> {
+    let server = database::get_server(sqlite_pool, server_id).await?;
+    let client = app_handle.state::<Clients>().megalodon(&server, None)?;
+    let scheduler = app_handle.state::<Scheduler>();
+    let key = (server.id, None);
+
+    let instance = scheduler
+        .run(app_handle, key, priority, client.get_instance())
+        .await?
+        .json;
+    // Some servers do not provide custom emojis, which should not prevent caching the others.
+    let emojis = match scheduler
+        .run(
+            app_handle,
+            key,
+            priority,
+            client.get_instance_custom_emojis(),
+        )
+        .await
+    {
+        Ok(res) => res.json,
+        Err(err) => {
+            tracing::warn!("Failed to get custom emojis of {}: {}", server.domain, err);
+            Vec::new()
+        }
+    };
+
+    // The software may be upgraded or changed to a fork, which changes its capabilities.
+    let http = app_handle.state::<Clients>().http();
+    if let Err(err) = nodeinfo::update_software(sqlite_pool, &http, &server).await {
+        tracing::warn!(
+            "Failed to detect the software of {}: {}",
+            server.domain,
+            err
+        );
+    }
+
+    let metadata = ServerMetadata::new(server.id, instance, emojis, Utc::now().timestamp());
+    database::save_server_metadata(sqlite_pool, &metadata).await?;
+    events::emit(
+        app_handle,
+        "updated-server-metadata",
+        UpdatedServerMetadataPayload { server_id },
+    );
+    Ok(metadata)
+}
+
+/// Refreshes stale metadata of all servers while the app is running.
+pub(crate) async fn watch(app_handle: AppHandle, sqlite_pool: sqlx::SqlitePool) {
+    loop {
+        match database::list_servers(&sqlite_pool).await {
+            Ok(servers) => {
+                for (server, _) in servers {
+                    let stale = match cached(&sqlite_pool, server.id).await {
+                        Ok(Some(metadata)) => is_stale(&metadata, Utc::now().timestamp()),
+                        Ok(None) => true,
+                        Err(err) => {
+                            tracing::error!("{}", err);
+                            false
+                        }
+                    };
+                    if !stale {
+                        continue;
+                    }
+                    let res =
+                        refresh(&app_handle, &sqlite_pool, server.id, Priority::Background).await;
+                    if let Err(err) = res {
+                        tracing::warn!(
+                            "Failed to refresh the metadata of {}: {}",
+                            server.domain,
+                            err
+                        );
+                    }
+                }
+            }
+            Err(err) => tracing::error!("Failed to list servers: {}", err),
+        }
+        tokio::time::sleep(CHECK_INTERVAL).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metadata(fetched_at: i64) -> ServerMetadata {
+        let instance = serde_json::from_value(serde_json::json!({
+            "uri": "example.com",
+            "title": "Example",
+            "description": "",
+            "email": "admin@example.com",
+            "version": "4.3.0",
+            "thumbnail": null,
+            "urls": null,
+            "stats": { "user_count": 1, "status_count": 1, "domain_count": 1 },
+            "languages": ["en"],
+            "registrations": false,
+            "approval_required": false,
+            "invites_enabled": null,
+            "configuration": {
+                "statuses": {
+                    "max_characters": 500,
+                    "max_media_attachments": 4,
+                    "characters_reserved_per_url": 23
+                },
+                "polls": {
+                    "max_options": 4,
+                    "max_characters_per_option": 50,
+                    "min_expiration": 300,
+                    "max_expiration": 2629746
+                }
+            },
+            "contact_account": null,
+            "rules": [{ "id": "1", "text": "Be nice" }]
+        }))
+        .unwrap();
+        ServerMetadata::new(1, instance, Vec::new(), fetched_at)
+    }
+
+    #[test]
+    fn test_limits_from_instance() {
+        let metadata = metadata(0);
+        assert_eq!(metadata.limits.max_characters, Some(500));
+        assert_eq!(metadata.limits.max_media_attachments, Some(4));
+        assert_eq!(metadata.limits.poll_max_options, Some(4));
+        assert_eq!(metadata.limits.poll_max_expiration, Some(2629746));
+        assert_eq!(metadata.rules.len(), 1);
+        assert_eq!(metadata.rules[0].text, "Be nice");
+    }
+
+    #[test]
+    fn test_is_stale() {
+        assert!(!is_stale(&metadata(1_000), 1_000 + TTL - 1));
+        assert!(is_stale(&metadata(1_000), 1_000 + TTL));
+    }
+}
@@ -0,0 +1,175 @@
+use serde::Deserialize;
+use url::Url;
+
+use crate::{database, entities, error::Error};
+
+const SCHEMA_PREFIX: &str = "http://nodeinfo.diaspora.software/ns/schema/";
+
+#[derive(Deserialize)]
+struct Links {
+    #[serde(default)]
+    links: Vec<Link>,
+}
+
+#[derive(Deserialize)]
+struct Link {
+    rel: String,
+    href: String,
+}
+
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub struct Nodeinfo {
+    pub software: Software,
+    /// Free-form metadata whose keys depend on the software.
+    #[serde(default)]
+    pub metadata: serde_json::Value,
+}
+
+#[derive(Debug, Clone, Deserialize, PartialEq, Eq)]
+pub struct Software {
+    pub name: String,
+    #[serde(default)]
+    pub version: String,
+}
+
+/// Reads the newest nodeinfo which the server links from `/.well-known/nodeinfo`.
+/// The URL of the document is also returned, since URLs in the metadata may be relative to it.
+pub(crate) async fn fetch(client: &reqwest::Client, base: &Url) -> Result<(Url, Nodeinfo), Error> {
+    let well_known = base
+        .join("/.well-known/nodeinfo")
+        .map_err(|e| Error::validation(e.to_string()))?;
+    let links: Links = client
+        .get(well_known)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    let Some(link) = links
+        .links
+        .iter()
+        .filter(|l| l.rel.starts_with(SCHEMA_PREFIX))
+        .max_by(|a, b| a.rel.cmp(&b.rel))
+    else {
+        return Err(Error::unsupported_sns(format!(
+            "{} does not provide nodeinfo",
+            base
+        )));
+    };
+    let url = base
+        .join(&link.href)
+        .map_err(|e| Error::validation(e.to_string()))?;
+    let nodeinfo = client
+        .get(url.clone())
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    Ok((url, nodeinfo))
+}
+
+/// Detects the SNS of the server from nodeinfo, with the same names as `megalodon::detector`
+/// which can not be given our HTTP client.
+pub(crate) async fn detect_sns(
+    client: &reqwest::Client,
+    base_url: &str,
+) -> Result<megalodon::SNS, Error> {
+    let base = Url::parse(base_url).map_err(|e| Error::validation(e.to_string()))?;
+    let (_, nodeinfo) = fetch(client, &base).await?;
+    sns_of(&nodeinfo)
+        .ok_or_else(|| Error::unsupported_sns(format!("Unknown SNS: {}", nodeinfo.software.name)))
+}
+
+fn sns_of(nodeinfo: &Nodeinfo) -> Option<megalodon::SNS> {
+    match nodeinfo.software.name.to_lowercase().as_str() {
+        "mastodon" | "hometown" => Some(megalodon::SNS::Mastodon),
+        "pleroma" | "akkoma" => Some(megalodon::SNS::Pleroma),
+        "firefish" | "iceshrimp" => Some(megalodon::SNS::Firefish),
+        "friendica" => Some(megalodon::SNS::Friendica),
+        "gotosocial" => Some(megalodon::SNS::Gotosocial),
+        "pixelfed" => Some(megalodon::SNS::Pixelfed),
+        // Forks often keep the name of their upstream in the metadata.
+        _ => match nodeinfo
+            .metadata
+            .pointer("/upstream/name")
+            .and_then(|n| n.as_str())
+        {
+            Some(upstream) if upstream.eq_ignore_ascii_case("mastodon") => {
+                Some(megalodon::SNS::Mastodon)
+            }
+            _ => None,
+        },
+    }
+}
+
+/// Records the software name and version of the server, and returns whether they are changed.
+pub(crate) async fn detect_software(
+    client: &reqwest::Client,
+    server: &mut entities::Server,
+) -> Result<bool, Error> {
+    let base = Url::parse(&server.base_url).map_err(|e| Error::validation(e.to_string()))?;
+    let (_, nodeinfo) = fetch(client, &base).await?;
+    let name = Some(nodeinfo.software.name.to_lowercase());
+    let version = Some(nodeinfo.software.version).filter(|v| !v.is_empty());
+    if server.software == name && server.software_version == version {
+        return Ok(false);
+    }
+    tracing::info!(
+        "The software of {} is {:?} {:?}",
+        server.base_url,
+        name,
+        version
+    );
+    server.software = name;
+    server.software_version = version;
+    Ok(true)
+}
+
+/// Detects the software of a registered server and saves it.
+pub(crate) async fn update_software(
+    sqlite_pool: &sqlx::SqlitePool,
+    client: &reqwest::Client,
+    server: &entities::Server,
+) -> Result<(), Error> {
+    let mut server = server.clone();
+    if detect_software(client, &mut server).await? {
+        database::update_server(sqlite_pool, server).await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn nodeinfo(name: &str, metadata: serde_json::Value) -> Nodeinfo {
+        Nodeinfo {
+            software: Software {
+                name: name.to_string(),
+                version: String::new(),
+            },
+            metadata,
+        }
+    }
+
+    #[test]
+    fn test_sns_of() {
+        assert_eq!(
+            sns_of(&nodeinfo("Akkoma", serde_json::Value::Null)),
+            Some(megalodon::SNS::Pleroma)
+        );
+        assert_eq!(
+            sns_of(&nodeinfo("iceshrimp", serde_json::Value::Null)),
+            Some(megalodon::SNS::Firefish)
+        );
+        assert_eq!(
+            sns_of(&nodeinfo(
+                "glitch",
+                serde_json::json!({ "upstream": { "name": "mastodon" } })
+            )),
+            Some(megalodon::SNS::Mastodon)
+        );
+        assert_eq!(sns_of(&nodeinfo("misskey", serde_json::Value::Null)), None);
+    }
+}
@@ -0,0 +1,312 @@
+use chrono::{DateTime, Utc};
+use megalodon::response::Response;
+use reqwest::header::HeaderMap;
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tauri::AppHandle;
+use tokio::sync::OwnedMutexGuard;
+
+use crate::{
+    clients::ClientKey,
+    error::{Error, ErrorCode},
+    events,
+};
+
+/// Background requests leave this fraction of the limit for the requests which the user is waiting for.
+const RESERVED_RATIO: u64 = 10;
+/// How long to wait after 429 when the server does not tell when the limit is reset.
+const DEFAULT_RESET: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Priority {
+    /// Requests which the user is waiting for, which only wait when nothing is left.
+    Interactive,
+    /// Polling, favicon refresh and backfill, which are sent one by one and keep the reserved budget.
+    Background,
+}
+
+/// Rate limit which the server reported in the last response.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimit {
+    pub limit: u64,
+    pub remaining: u64,
+    pub reset_at: DateTime<Utc>,
+}
+
+impl RateLimit {
+    fn wait_time(&self, priority: Priority, now: DateTime<Utc>) -> Option<Duration> {
+        let reserved = match priority {
+            Priority::Interactive => 0,
+            Priority::Background => self.limit / RESERVED_RATIO,
+        };
+        if self.remaining > reserved {
+            return None;
+        }
+        (self.reset_at - now).to_std().ok()
+    }
+}
+
+struct Budget {
+    rate_limit: RateLimit,
+    /// Whether `rate-limited` is already sent for this reset time.
+    notified: bool,
+}
+
+#[derive(Clone, Serialize)]
+pub struct RateLimitedPayload {
+    server_id: i64,
+    account_id: Option<i64>,
+    limit: u64,
+    remaining: u64,
+    reset_at: String,
+}
+
+/// Tracks the rate limit budget of each account and delays requests until the limit is reset.
+#[derive(Default)]
+pub struct Scheduler {
+    budgets: Mutex<HashMap<ClientKey, Budget>>,
+    queues: Mutex<HashMap<ClientKey, Arc<tokio::sync::Mutex<()>>>>,
+}
+
+impl Scheduler {
+    /// Sends the megalodon request when the budget allows it, and records the rate limit of the response.
+    pub async fn run<T, E>(
+        &self,
+        app_handle: &AppHandle,
+        key: ClientKey,
+        priority: Priority,
+        request: impl Future<Output = Result<Response<T>, E>>,
+    ) -> Result<Response<T>, Error>
+    where
+        E: Into<Error> + RateLimitHeader,
+    {
+        let _guard = self.acquire(app_handle, key, priority).await;
+        match request.await {
+            Ok(res) => {
+                self.observe(key, &res.header);
+                Ok(res)
+            }
+            Err(err) => {
+                let header = err.rate_limit_header();
+                let err: Error = err.into();
+                if err.code == ErrorCode::RateLimited {
+                    self.exhausted(app_handle, key, header.as_ref());
+                }
+                Err(err)
+            }
+        }
+    }
+
+    /// Waits until the request can be sent.
+    /// Background requests of an account are queued, so the guard must be kept until the response is received.
+    pub async fn acquire(
+        &self,
+        app_handle: &AppHandle,
+        key: ClientKey,
+        priority: Priority,
+    ) -> Option<OwnedMutexGuard<()>> {
+        let guard = match priority {
+            Priority::Interactive => None,
+            Priority::Background => Some(self.queue(key).lock_owned().await),
+        };
+        while let Some(wait) = self.wait_time(app_handle, key, priority) {
+            tokio::time::sleep(wait).await;
+        }
+        guard
+    }
+
+    /// Records the rate limit headers of a response.
+    pub fn observe(&self, key: ClientKey, header: &HeaderMap) {
+        if let Some(rate_limit) = parse_rate_limit(header) {
+            self.record(key, rate_limit);
+        }
+    }
+
+    /// Forgets the budgets of the server, which is called when the server or its account is changed.
+    pub fn remove_server(&self, server_id: i64) {
+        self.budgets
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .retain(|(id, _), _| *id != server_id);
+        self.queues
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .retain(|(id, _), _| *id != server_id);
+    }
+
+    fn queue(&self, key: ClientKey) -> Arc<tokio::sync::Mutex<()>> {
+        self.queues
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .entry(key)
+            .or_default()
+            .clone()
+    }
+
+    fn record(&self, key: ClientKey, rate_limit: RateLimit) {
+        let mut budgets = self.budgets.lock().unwrap_or_else(|e| e.into_inner());
+        let notified = budgets
+            .get(&key)
+            .is_some_and(|b| b.notified && b.rate_limit.reset_at == rate_limit.reset_at);
+        budgets.insert(
+            key,
+            Budget {
+                rate_limit,
+                notified,
+            },
+        );
+    }
+
+    // The server refused the request with 429, so nothing is left until the reset time.
+    fn exhausted(&self, app_handle: &AppHandle, key: ClientKey, header: Option<&HeaderMap>) {
+        let now = Utc::now();
+        let previous = self
+            .budgets
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(&key)
+            .map(|b| b.rate_limit);
+        let rate_limit = header
+            .and_then(parse_rate_limit)
+            .or(previous.filter(|r| r.reset_at > now))
+            .unwrap_or(RateLimit {
+                limit: 0,
+                remaining: 0,
+                reset_at: now + DEFAULT_RESET,
+            });
+        self.record(
+            key,
+            RateLimit {
+                remaining: 0,
+                ..rate_limit
+            },
+        );
+        // Sends rate-limited even if no more requests are sent until the reset time.
+        self.wait_time(app_handle, key, Priority::Interactive);
+    }
+
+    fn wait_time(
+        &self,
+        app_handle: &AppHandle,
+        key: ClientKey,
+        priority: Priority,
+    ) -> Option<Duration> {
+        let (wait, payload) = {
+            let mut budgets = self.budgets.lock().unwrap_or_else(|e| e.into_inner());
+            let budget = budgets.get_mut(&key)?;
+            let wait = budget.rate_limit.wait_time(priority, Utc::now())?;
+            let payload = (!budget.notified).then(|| RateLimitedPayload {
+                server_id: key.0,
+                account_id: key.1,
+                limit: budget.rate_limit.limit,
+                remaining: budget.rate_limit.remaining,
+                reset_at: budget.rate_limit.reset_at.to_rfc3339(),
+            });
+            budget.notified = true;
+            (wait, payload)
+        };
+        if let Some(payload) = payload {
+            tracing::warn!(
+                "Requests to the server {} are delayed until {}",
+                payload.server_id,
+                payload.reset_at
+            );
+            events::emit(app_handle, "rate-limited", payload);
+        }
+        Some(wait)
+    }
+}
+
+/// Errors which may keep the headers of a 429 response.
+pub trait RateLimitHeader {
+    fn rate_limit_header(&self) -> Option<HeaderMap>;
+}
+
+impl RateLimitHeader for megalodon::error::Error {
+    fn rate_limit_header(&self) -> Option<HeaderMap> {
+        match self {
+            megalodon::error::Error::OwnError(own) => own.header.clone(),
+            _ => None,
+        }
+    }
+}
+
+impl RateLimitHeader for Error {
+    fn rate_limit_header(&self) -> Option<HeaderMap> {
+        None
+    }
+}
+
+fn parse_rate_limit(header: &HeaderMap) -> Option<RateLimit> {
+    let value = |name: &str| header.get(name).and_then(|v| v.to_str().ok());
+    Some(RateLimit {
+        limit: value("x-ratelimit-limit")?.parse().ok()?,
+        remaining: value("x-ratelimit-remaining")?.parse().ok()?,
+        reset_at: DateTime::parse_from_rfc3339(value("x-ratelimit-reset")?)
+            .ok()?
+            .with_timezone(&Utc),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    fn rate_limit(remaining: u64) -> RateLimit {
+        RateLimit {
+            limit: 300,
+            remaining,
+            reset_at: DateTime::parse_from_rfc3339("2026-10-19T12:05:00Z")
+                .unwrap()
+                .with_timezone(&Utc),
+        }
+    }
+
+    #[test]
+    fn test_parse_rate_limit() {
+        let mut header = HeaderMap::new();
+        header.insert("X-RateLimit-Limit", HeaderValue::from_static("300"));
+        header.insert("X-RateLimit-Remaining", HeaderValue::from_static("0"));
+        header.insert(
+            "X-RateLimit-Reset",
+            HeaderValue::from_static("2026-10-19T12:05:00.000Z"),
+        );
+        assert_eq!(parse_rate_limit(&header), Some(rate_limit(0)));
+    }
+
+    #[test]
+    fn test_parse_rate_limit_without_headers() {
+        assert!(parse_rate_limit(&HeaderMap::new()).is_none());
+    }
+
+    #[test]
+    fn test_background_keeps_reserved_budget() {
+        let now = DateTime::parse_from_rfc3339("2026-10-19T12:04:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        assert_eq!(
+            rate_limit(30).wait_time(Priority::Background, now),
+            Some(Duration::from_secs(60))
+        );
+        assert_eq!(rate_limit(30).wait_time(Priority::Interactive, now), None);
+        assert_eq!(
+            rate_limit(0).wait_time(Priority::Interactive, now),
+            Some(Duration::from_secs(60))
+        );
+        assert_eq!(rate_limit(31).wait_time(Priority::Background, now), None);
+    }
+
+    #[test]
+    fn test_no_wait_after_reset() {
+        let now = DateTime::parse_from_rfc3339("2026-10-19T12:06:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        assert_eq!(rate_limit(0).wait_time(Priority::Background, now), None);
+    }
+}
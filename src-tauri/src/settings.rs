@@ -1,36 +1,152 @@
 use serde::{Deserialize, Serialize};
-use serde_json::{from_str, to_string, Value};
-use std::{fmt, fs, path::PathBuf};
+use serde_json::{from_str, to_string, Map, Value};
+use std::{collections::BTreeMap, ffi::OsString, fmt, fs, path::PathBuf, sync::Mutex};
+
+use crate::{entities, error::Error};
+
+/// Migrations of settings.json, in the order they were introduced.
+/// Each one upgrades the file from the version of its index to the next version.
+const MIGRATIONS: &[fn(&mut Value)] = &[add_color_theme, add_behavior];
+
+pub const SETTINGS_VERSION: u64 = MIGRATIONS.len() as u64;
+
+/// The contents this app wrote last to each settings file, to tell its own writes from external edits.
+static LAST_WRITTEN: Mutex<BTreeMap<PathBuf, String>> = Mutex::new(BTreeMap::new());
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(default)]
 pub struct Settings {
+    pub version: u64,
     pub appearance: Appearance,
     pub behavior: Option<Behavior>,
     pub app_menu: Option<AppMenu>,
+    pub keybindings: Keybindings,
+    pub proxy: Option<Proxy>,
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(default)]
 pub struct Appearance {
     pub font_size: u32,
     pub font_family: Option<String>,
     pub language: LocaleType,
     pub color_theme: ThemeType,
+    pub media_display: MediaDisplay,
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+#[serde(default)]
 pub struct Behavior {
     pub confirm_reblog: bool,
+    pub default_visibility: Visibility,
+    pub default_language: Option<String>,
 }
 
-#[derive(Deserialize, Serialize, Debug, Clone)]
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+#[serde(default)]
 pub struct AppMenu {
     pub hidden: bool,
 }
 
-#[derive(Debug, Serialize, Deserialize, sqlx::Type, Clone, PartialEq, Eq)]
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct Keybindings {
+    pub global: GlobalKeybindings,
+    pub app: AppKeybindings,
+}
+
+/// Shortcuts registered to the OS, which work even when the window is not focused.
+/// They are not set by default to avoid taking keys from other applications.
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct GlobalKeybindings {
+    pub toggle_window: Option<String>,
+    pub open_compose: Option<String>,
+}
+
+/// Shortcuts handled in the window.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(default)]
+pub struct AppKeybindings {
+    pub compose: String,
+    pub search: String,
+    pub devtools: String,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Proxy {
+    pub protocol: ProxyProtocol,
+    pub host: String,
+    pub port: u16,
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+    /// Hosts connected directly. Each entry also matches its subdomains, and `*` matches all hosts.
+    #[serde(default)]
+    pub no_proxy: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ProxyProtocol {
+    Http,
+    Https,
+    Socks5,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            version: SETTINGS_VERSION,
+            appearance: Appearance::default(),
+            behavior: Some(Behavior::default()),
+            app_menu: Some(AppMenu::default()),
+            keybindings: Keybindings::default(),
+            proxy: None,
+        }
+    }
+}
+
+impl Default for AppKeybindings {
+    fn default() -> Self {
+        Self {
+            compose: "CmdOrCtrl+N".to_string(),
+            search: "CmdOrCtrl+K".to_string(),
+            devtools: "F12".to_string(),
+        }
+    }
+}
+
+impl Default for Appearance {
+    fn default() -> Self {
+        Self {
+            font_size: 14,
+            font_family: None,
+            language: LocaleType::default(),
+            color_theme: ThemeType::default(),
+            media_display: MediaDisplay::default(),
+        }
+    }
+}
+
+/// Settings after applying the account and timeline overrides to the global settings.
+#[derive(Serialize, Debug, Clone)]
+pub struct EffectiveSettings {
+    pub font_size: u32,
+    pub font_family: Option<String>,
+    pub color_theme: ThemeType,
+    pub media_display: MediaDisplay,
+    pub confirm_reblog: bool,
+    pub default_visibility: Visibility,
+    pub default_language: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::Type, Clone, PartialEq, Eq, Default)]
 #[sqlx(rename_all = "lowercase")]
 #[serde(rename_all = "lowercase")]
 pub enum LocaleType {
+    #[default]
     En,
     Ja,
     It,
@@ -51,66 +167,160 @@ pub enum LocaleType {
     Ia,
 }
 
-#[derive(Debug, Serialize, Deserialize, sqlx::Type, Clone, PartialEq, Eq)]
+#[derive(Debug, Serialize, Deserialize, sqlx::Type, Clone, PartialEq, Eq, Default)]
 #[sqlx(rename_all = "kebab-case")]
 #[serde(rename_all = "kebab-case")]
 pub enum ThemeType {
+    #[default]
     Dark,
     Light,
     HighContrast,
 }
 
-pub(crate) fn read_settings(filepath: &PathBuf) -> Result<Settings, String> {
+#[derive(Debug, Serialize, Deserialize, sqlx::Type, Clone, PartialEq, Eq, Default)]
+#[sqlx(rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum Visibility {
+    #[default]
+    Public,
+    Unlisted,
+    Private,
+    Direct,
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::Type, Clone, PartialEq, Eq, Default)]
+#[sqlx(rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum MediaDisplay {
+    /// Hide only media marked as sensitive.
+    #[default]
+    Default,
+    ShowAll,
+    HideAll,
+}
+
+/// Resolves the settings in the order of timeline overrides, account overrides and global settings.
+pub(crate) fn resolve(
+    global: &Settings,
+    account: Option<&entities::AccountSettings>,
+    timeline: Option<&entities::TimelineSettings>,
+) -> EffectiveSettings {
+    let behavior = global.behavior.clone().unwrap_or_default();
+
+    EffectiveSettings {
+        font_size: timeline
+            .and_then(|t| t.font_size)
+            .unwrap_or(global.appearance.font_size),
+        font_family: global.appearance.font_family.clone(),
+        color_theme: global.appearance.color_theme.clone(),
+        media_display: timeline
+            .and_then(|t| t.media_display.clone())
+            .unwrap_or(global.appearance.media_display.clone()),
+        confirm_reblog: account
+            .and_then(|a| a.confirm_reblog)
+            .unwrap_or(behavior.confirm_reblog),
+        default_visibility: account
+            .and_then(|a| a.default_visibility.clone())
+            .unwrap_or(behavior.default_visibility),
+        default_language: account
+            .and_then(|a| a.default_language.clone())
+            .or(behavior.default_language),
+    }
+}
+
+pub(crate) fn read_settings(filepath: &PathBuf) -> Result<Settings, Error> {
     let Ok(text) = fs::read_to_string(filepath) else {
-        // Default settings
-        return Ok(Settings {
-            appearance: Appearance {
-                font_size: 14,
-                font_family: None,
-                language: LocaleType::En,
-                color_theme: ThemeType::Dark,
-            },
-            behavior: Some(Behavior {
-                confirm_reblog: false,
-            }),
-            app_menu: Some(AppMenu { hidden: false }),
-        });
+        return Ok(Settings::default());
     };
-    let updated = update_settings_with_default(filepath, text)?;
-    from_str::<Settings>(&updated).map_err(|err| err.to_string())
+    let mut value = from_str::<Value>(&text)?;
+    if migrate(&mut value)? {
+        if let Err(err) = write_atomically(filepath, &value.to_string()) {
+            tracing::error!("Failed to save migrated settings: {}", err);
+        }
+    }
+    Ok(serde_json::from_value::<Settings>(value)?)
 }
 
-pub(crate) fn save_settings(filepath: &PathBuf, settings: &Settings) -> Result<(), String> {
-    let str = to_string(settings).map_err(|err| err.to_string())?;
-    fs::write(filepath, str).map_err(|err| err.to_string())
+pub(crate) fn save_settings(filepath: &PathBuf, settings: &Settings) -> Result<(), Error> {
+    let mut settings = settings.clone();
+    settings.version = SETTINGS_VERSION;
+    let str = to_string(&settings)?;
+    write_atomically(filepath, &str)
 }
 
-pub(crate) fn update_settings_with_default(
-    filepath: &PathBuf,
-    original: String,
-) -> Result<String, String> {
-    let Ok(text) = fs::read_to_string(filepath) else {
-        return Err("Settings file does not exist".to_string());
+/// Applies every migration newer than the version recorded in the file.
+/// Returns whether the value has been changed.
+fn migrate(value: &mut Value) -> Result<bool, Error> {
+    let Some(object) = value.as_object_mut() else {
+        return Err(Error::validation("Settings must be a JSON object"));
     };
-    let Ok(value) = serde_json::from_str::<Value>(text.as_str()) else {
-        return Err("Failed to load json".to_string());
+    let version = object.get("version").and_then(Value::as_u64).unwrap_or(0);
+    if version >= SETTINGS_VERSION {
+        return Ok(false);
+    }
+
+    for migration in MIGRATIONS.iter().skip(version as usize) {
+        migration(value);
+    }
+    if let Some(object) = value.as_object_mut() {
+        object.insert("version".to_string(), Value::from(SETTINGS_VERSION));
+    }
+    Ok(true)
+}
+
+// Writes to a temporary file first, so that a crash while writing never leaves a truncated settings.json.
+fn write_atomically(filepath: &PathBuf, contents: &str) -> Result<(), Error> {
+    let mut tmp = OsString::from(filepath.as_os_str());
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+    fs::write(&tmp, contents)?;
+    fs::rename(&tmp, filepath)?;
+    LAST_WRITTEN
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .insert(filepath.clone(), contents.to_string());
+    Ok(())
+}
+
+/// Returns whether the file still has the contents which this app wrote last.
+pub(crate) fn is_written_by_app(filepath: &PathBuf) -> bool {
+    let Ok(text) = fs::read_to_string(filepath) else {
+        return false;
     };
-    if value["appearance"]["color_theme"] == Value::Null {
-        let mut update = value.clone();
-        update["appearance"]["color_theme"] = Value::String("dark".to_string());
-        let str = update.to_string();
-        let _ = fs::write(filepath, str).map_err(|err| err.to_string());
-        return Ok(update.to_string());
+    LAST_WRITTEN
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .get(filepath)
+        .is_some_and(|contents| *contents == text)
+}
+
+fn object_entry<'a>(value: &'a mut Value, key: &str) -> Option<&'a mut Map<String, Value>> {
+    let entry = value
+        .as_object_mut()?
+        .entry(key)
+        .or_insert_with(|| Value::Object(Map::new()));
+    if entry.is_null() {
+        *entry = Value::Object(Map::new());
     }
-    if value["behavior"] == Value::Null || value["behavior"]["confirm_reblog"] == Value::Null {
-        let mut update = value.clone();
-        update["behavior"] = Value::Object(serde_json::Map::new());
-        update["behavior"]["confirm_reblog"] = Value::Bool(false);
-        let str = update.to_string();
-        let _ = fs::write(filepath, str).map_err(|err| err.to_string());
-        return Ok(update.to_string());
+    entry.as_object_mut()
+}
+
+// color_theme was added after the first release.
+fn add_color_theme(value: &mut Value) {
+    if let Some(appearance) = object_entry(value, "appearance") {
+        appearance
+            .entry("color_theme")
+            .or_insert_with(|| Value::String("dark".to_string()));
+    }
+}
+
+// behavior.confirm_reblog was added after color_theme.
+fn add_behavior(value: &mut Value) {
+    if let Some(behavior) = object_entry(value, "behavior") {
+        behavior
+            .entry("confirm_reblog")
+            .or_insert(Value::Bool(false));
     }
-    Ok(original)
 }
 
 impl fmt::Display for LocaleType {
@@ -131,3 +341,143 @@ impl fmt::Display for LocaleType {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_settings(name: &str, contents: Option<&str>) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "fedistar-settings-{}-{}.json",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_file(&path);
+        if let Some(contents) = contents {
+            fs::write(&path, contents).unwrap();
+        }
+        path
+    }
+
+    #[test]
+    fn test_read_settings_without_file() {
+        let path = temp_settings("missing", None);
+        let settings = read_settings(&path).unwrap();
+        assert_eq!(settings.version, SETTINGS_VERSION);
+        assert_eq!(settings.appearance.font_size, 14);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_read_settings_for_first_format() {
+        let path = temp_settings(
+            "first",
+            Some(r#"{"appearance":{"font_size":16,"font_family":null,"language":"ja"}}"#),
+        );
+        let settings = read_settings(&path).unwrap();
+        assert_eq!(settings.appearance.font_size, 16);
+        assert_eq!(settings.appearance.language, LocaleType::Ja);
+        assert_eq!(settings.appearance.color_theme, ThemeType::Dark);
+        assert!(!settings.behavior.unwrap().confirm_reblog);
+
+        let saved = from_str::<Value>(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(saved["version"], Value::from(SETTINGS_VERSION));
+        assert_eq!(saved["appearance"]["color_theme"], "dark");
+        assert_eq!(saved["behavior"]["confirm_reblog"], false);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_read_settings_with_color_theme() {
+        let path = temp_settings(
+            "color-theme",
+            Some(
+                r#"{"appearance":{"font_size":12,"font_family":"Noto Sans","language":"en","color_theme":"light"},"app_menu":{"hidden":true}}"#,
+            ),
+        );
+        let settings = read_settings(&path).unwrap();
+        assert_eq!(settings.appearance.color_theme, ThemeType::Light);
+        assert!(settings.app_menu.unwrap().hidden);
+        assert!(!settings.behavior.unwrap().confirm_reblog);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_read_settings_for_current_format() {
+        let contents = format!(
+            r#"{{"version":{},"appearance":{{"font_size":14,"font_family":null,"language":"en","color_theme":"high-contrast"}},"behavior":{{"confirm_reblog":true}},"app_menu":{{"hidden":false}}}}"#,
+            SETTINGS_VERSION
+        );
+        let path = temp_settings("current", Some(&contents));
+        let settings = read_settings(&path).unwrap();
+        assert_eq!(settings.appearance.color_theme, ThemeType::HighContrast);
+        assert!(settings.behavior.unwrap().confirm_reblog);
+        assert_eq!(fs::read_to_string(&path).unwrap(), contents);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_read_settings_with_invalid_json() {
+        let path = temp_settings("invalid", Some("[]"));
+        assert!(read_settings(&path).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_resolve_without_overrides() {
+        let global = Settings::default();
+        let effective = resolve(&global, None, None);
+        assert_eq!(effective.font_size, 14);
+        assert!(!effective.confirm_reblog);
+        assert_eq!(effective.default_visibility, Visibility::Public);
+        assert_eq!(effective.media_display, MediaDisplay::Default);
+    }
+
+    #[test]
+    fn test_resolve_with_overrides() {
+        let mut global = Settings::default();
+        global.behavior = Some(Behavior {
+            confirm_reblog: false,
+            default_visibility: Visibility::Public,
+            default_language: Some("en".to_string()),
+        });
+        let account = entities::AccountSettings {
+            account_id: 1,
+            confirm_reblog: Some(true),
+            default_visibility: Some(Visibility::Unlisted),
+            default_language: None,
+        };
+        let timeline = entities::TimelineSettings {
+            timeline_id: 1,
+            font_size: Some(18),
+            media_display: None,
+        };
+        let effective = resolve(&global, Some(&account), Some(&timeline));
+        assert_eq!(effective.font_size, 18);
+        assert_eq!(effective.media_display, MediaDisplay::Default);
+        assert!(effective.confirm_reblog);
+        assert_eq!(effective.default_visibility, Visibility::Unlisted);
+        assert_eq!(effective.default_language, Some("en".to_string()));
+    }
+
+    #[test]
+    fn test_save_settings_records_version() {
+        let path = temp_settings("save", None);
+        let mut settings = Settings::default();
+        settings.version = 0;
+        save_settings(&path, &settings).unwrap();
+        assert_eq!(read_settings(&path).unwrap().version, SETTINGS_VERSION);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_is_written_by_app() {
+        let path = temp_settings("written", None);
+        save_settings(&path, &Settings::default()).unwrap();
+        assert!(is_written_by_app(&path));
+
+        fs::write(&path, r#"{"version": 2, "appearance": {"font_size": 16}}"#).unwrap();
+        assert!(!is_written_by_app(&path));
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,146 @@
+use serde::Serialize;
+use std::{collections::HashMap, str::FromStr};
+use tauri::{AppHandle, Manager};
+use tauri_plugin_global_shortcut::{GlobalShortcutExt, Shortcut, ShortcutState};
+
+use crate::{error::Error, events, settings::Keybindings};
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum GlobalAction {
+    ToggleWindow,
+    OpenCompose,
+}
+
+#[derive(Clone, Serialize)]
+struct GlobalShortcutPayload {
+    action: GlobalAction,
+}
+
+// Global and in-app shortcuts share the keyboard, so every binding is checked against all the others.
+fn entries(keybindings: &Keybindings) -> Vec<(&'static str, &str)> {
+    let mut entries = vec![
+        ("app.compose", keybindings.app.compose.as_str()),
+        ("app.search", keybindings.app.search.as_str()),
+        ("app.devtools", keybindings.app.devtools.as_str()),
+    ];
+    if let Some(accelerator) = &keybindings.global.toggle_window {
+        entries.push(("global.toggle_window", accelerator));
+    }
+    if let Some(accelerator) = &keybindings.global.open_compose {
+        entries.push(("global.open_compose", accelerator));
+    }
+    entries
+}
+
+fn parse(name: &str, accelerator: &str) -> Result<Shortcut, Error> {
+    Shortcut::from_str(accelerator).map_err(|e| {
+        Error::validation(format!(
+            "Invalid shortcut for {}: {}: {}",
+            name, accelerator, e
+        ))
+    })
+}
+
+/// Checks that every accelerator can be parsed and no two actions share the same keys.
+pub(crate) fn validate(keybindings: &Keybindings) -> Result<(), Error> {
+    let mut used: HashMap<Shortcut, &str> = HashMap::new();
+    for (name, accelerator) in entries(keybindings) {
+        let shortcut = parse(name, accelerator)?;
+        if let Some(other) = used.insert(shortcut, name) {
+            return Err(Error::validation(format!(
+                "{} conflicts with {}: {}",
+                name, other, accelerator
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Replaces the registered global shortcuts with the given ones.
+pub(crate) fn register(app_handle: &AppHandle, keybindings: &Keybindings) -> Result<(), Error> {
+    validate(keybindings)?;
+
+    let global_shortcut = app_handle.global_shortcut();
+    global_shortcut
+        .unregister_all()
+        .map_err(|e| Error::internal(e.to_string()))?;
+
+    let actions = [
+        (
+            GlobalAction::ToggleWindow,
+            keybindings.global.toggle_window.as_deref(),
+        ),
+        (
+            GlobalAction::OpenCompose,
+            keybindings.global.open_compose.as_deref(),
+        ),
+    ];
+    for (action, accelerator) in actions {
+        let Some(accelerator) = accelerator else {
+            continue;
+        };
+        let res = global_shortcut.on_shortcut(accelerator, move |app_handle, _, event| {
+            if event.state == ShortcutState::Pressed {
+                handle(app_handle, action);
+            }
+        });
+        // Another application may own the keys, which should not prevent the other shortcuts.
+        if let Err(e) = res {
+            tracing::error!("Failed to register the shortcut {}: {}", accelerator, e);
+        }
+    }
+    Ok(())
+}
+
+fn handle(app_handle: &AppHandle, action: GlobalAction) {
+    let Some(window) = app_handle.get_webview_window("main") else {
+        return;
+    };
+    let res = match action {
+        GlobalAction::ToggleWindow => {
+            let visible = window.is_visible().unwrap_or(false);
+            let focused = window.is_focused().unwrap_or(false);
+            if visible && focused {
+                window.hide()
+            } else {
+                window.show().and_then(|_| window.set_focus())
+            }
+        }
+        GlobalAction::OpenCompose => window.show().and_then(|_| window.set_focus()),
+    };
+    if let Err(e) = res {
+        tracing::error!("Failed to handle the shortcut {:?}: {}", action, e);
+    }
+    events::emit(
+        app_handle,
+        "global-shortcut",
+        GlobalShortcutPayload { action },
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_default_keybindings() {
+        assert!(validate(&Keybindings::default()).is_ok());
+    }
+
+    #[test]
+    fn test_validate_invalid_accelerator() {
+        let mut keybindings = Keybindings::default();
+        keybindings.global.toggle_window = Some("Ctrl+Shift".to_string());
+        let err = validate(&keybindings).unwrap_err();
+        assert!(err.detail.contains("global.toggle_window"));
+    }
+
+    #[test]
+    fn test_validate_conflict() {
+        let mut keybindings = Keybindings::default();
+        keybindings.global.open_compose = Some("cmdorctrl+n".to_string());
+        let err = validate(&keybindings).unwrap_err();
+        assert!(err.detail.contains("app.compose"));
+    }
+}
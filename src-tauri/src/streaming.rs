@@ -1,8 +1,27 @@
-use megalodon::{self, streaming::Message};
+use megalodon::{
+    self,
+    megalodon::{
+        GetAccountStatusesInputOptions, GetListTimelineInputOptions, GetLocalTimelineInputOptions,
+        GetPublicTimelineInputOptions, GetTagTimelineInputOptions,
+    },
+    response::Response,
+    streaming::Message,
+    Megalodon,
+};
 use serde::Serialize;
-use tauri::{AppHandle, Emitter};
+use std::{collections::HashMap, ops::Deref, sync::Mutex, time::Duration};
+use tauri::{async_runtime::JoinHandle, AppHandle, Manager};
 
-use crate::entities;
+use crate::{
+    clients::Clients,
+    database, entities,
+    error::{Error, ErrorCode},
+    events,
+    scheduler::{Priority, Scheduler},
+};
+
+/// How often timelines are fetched when streaming is not available.
+const POLLING_INTERVAL: Duration = Duration::from_secs(30);
 
 #[derive(Clone, Serialize)]
 pub struct ReceiveHomeStatusPayload {
@@ -25,6 +44,8 @@ pub struct DeleteHomeStatusPayload {
 #[derive(Clone, Serialize)]
 pub struct ReceiveNotificationPayload {
     server_id: i64,
+    /// Notifications timelines whose filter matches the notification.
+    timeline_ids: Vec<i64>,
     notification: megalodon::entities::Notification,
 }
 
@@ -59,24 +80,109 @@ pub struct ReceiveTimelineConversationPayload {
     conversation: megalodon::entities::Conversation,
 }
 
+/// State of a timeline which can not be shown by the streaming events alone.
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum TimelineStatus {
+    /// The server requires signing in to read the timeline, so it is not streamed anymore.
+    AuthRequired,
+}
+
+#[derive(Clone, Serialize)]
+pub struct TimelineStatusPayload {
+    server_id: i64,
+    timeline_id: i64,
+    status: TimelineStatus,
+}
+
+/// The latest status of each timeline keyed by timeline id,
+/// which columns read when they are mounted after the event is emitted.
+#[derive(Default)]
+pub struct TimelineStatuses {
+    statuses: Mutex<HashMap<i64, TimelineStatus>>,
+}
+
+impl TimelineStatuses {
+    pub fn get(&self, timeline_id: i64) -> Option<TimelineStatus> {
+        let statuses = self.statuses.lock().unwrap_or_else(|e| e.into_inner());
+        statuses.get(&timeline_id).copied()
+    }
+
+    fn insert(&self, timeline_id: i64, status: TimelineStatus) {
+        let mut statuses = self.statuses.lock().unwrap_or_else(|e| e.into_inner());
+        statuses.insert(timeline_id, status);
+    }
+
+    pub fn remove(&self, timeline_id: i64) {
+        let mut statuses = self.statuses.lock().unwrap_or_else(|e| e.into_inner());
+        statuses.remove(&timeline_id);
+    }
+}
+
+/// Running streamings keyed by id, so that they can be stopped
+/// when their owner is removed or a new one is started for it.
+#[derive(Default)]
+pub struct Streamings {
+    handles: Mutex<HashMap<i64, JoinHandle<()>>>,
+}
+
+impl Streamings {
+    pub fn insert(&self, id: i64, handle: JoinHandle<()>) {
+        let mut handles = self.handles.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(old) = handles.insert(id, handle) {
+            old.abort();
+        }
+    }
+
+    pub fn stop(&self, id: i64) {
+        let mut handles = self.handles.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(handle) = handles.remove(&id) {
+            handle.abort();
+        }
+    }
+
+    pub fn stop_all(&self) {
+        let mut handles = self.handles.lock().unwrap_or_else(|e| e.into_inner());
+        for (_, handle) in handles.drain() {
+            handle.abort();
+        }
+    }
+}
+
+/// Timeline streamings keyed by timeline id, which are also stopped when the workspace becomes inactive.
+#[derive(Default)]
+pub struct TimelineStreamings(Streamings);
+
+impl Deref for TimelineStreamings {
+    type Target = Streamings;
+
+    fn deref(&self) -> &Streamings {
+        &self.0
+    }
+}
+
+/// User streamings keyed by account id.
+#[derive(Default)]
+pub struct UserStreamings(Streamings);
+
+impl Deref for UserStreamings {
+    type Target = Streamings;
+
+    fn deref(&self) -> &Streamings {
+        &self.0
+    }
+}
+
 pub async fn start_user(
     app_handle: AppHandle,
     server: &entities::Server,
     account: &entities::Account,
-) -> Result<(), String> {
+) -> Result<(), Error> {
     let mut retry_count = 0;
 
-    let url = format!("https://{}", server.domain);
-    let sns = megalodon::detector(url.as_str())
-        .await
-        .map_err(|e| e.to_string())?;
-    let client = megalodon::generator(
-        sns,
-        url,
-        Some(account.access_token.clone()),
-        Some(String::from("fedistar")),
-    )
-    .map_err(|err| err.to_string())?;
+    let client = app_handle
+        .state::<Clients>()
+        .megalodon(server, Some(account))?;
 
     let streaming = client.user_streaming().await;
     if !streaming.is_supported() {
@@ -104,53 +210,42 @@ pub async fn start_user(
                 match message {
                     Message::Update(mes) => {
                         tracing::debug!("receive update");
-                        app_handle
-                            .emit(
-                                "receive-home-status",
-                                ReceiveHomeStatusPayload {
-                                    server_id,
-                                    status: mes,
-                                },
-                            )
-                            .expect("Failed to send receive-home-status event");
+                        events::emit(
+                            &app_handle,
+                            "receive-home-status",
+                            ReceiveHomeStatusPayload {
+                                server_id,
+                                status: mes,
+                            },
+                        );
                     }
                     Message::Notification(mes) => {
                         tracing::debug!("receive notification");
                         if mes.account.is_some() {
-                            app_handle
-                                .emit(
-                                    "receive-notification",
-                                    ReceiveNotificationPayload {
-                                        server_id,
-                                        notification: mes,
-                                    },
-                                )
-                                .expect("Failed to send receive-notification event");
+                            emit_notification(&app_handle, server_id, mes).await;
                         }
                     }
                     Message::StatusUpdate(mes) => {
                         tracing::debug!("receive status updated");
-                        app_handle
-                            .emit(
-                                "receive-home-status-update",
-                                ReceiveHomeStatusUpdatePayload {
-                                    server_id,
-                                    status: mes,
-                                },
-                            )
-                            .expect("Failed to send receive-home-status-update event");
+                        events::emit(
+                            &app_handle,
+                            "receive-home-status-update",
+                            ReceiveHomeStatusUpdatePayload {
+                                server_id,
+                                status: mes,
+                            },
+                        );
                     }
                     Message::Delete(status_id) => {
                         tracing::debug!("receive delete");
-                        app_handle
-                            .emit(
-                                "delete-home-status",
-                                DeleteHomeStatusPayload {
-                                    server_id,
-                                    status_id,
-                                },
-                            )
-                            .expect("Failed to send delete-home-status event");
+                        events::emit(
+                            &app_handle,
+                            "delete-home-status",
+                            DeleteHomeStatusPayload {
+                                server_id,
+                                status_id,
+                            },
+                        );
                     }
                     _ => {}
                 }
@@ -175,7 +270,9 @@ pub async fn start(
     server: &entities::Server,
     timeline: &entities::Timeline,
     account: Option<entities::Account>,
-) -> Result<(), String> {
+) -> Result<(), Error> {
+    // The status is checked again when the streaming is restarted, such as after signing in.
+    app_handle.state::<TimelineStatuses>().remove(timeline.id);
     let mut retry_count = 0;
     let streaming_message;
     if let Some(ref account) = account {
@@ -193,18 +290,21 @@ pub async fn start(
         );
     }
 
-    let url = format!("https://{}", server.domain);
-    let sns = megalodon::detector(url.as_str())
-        .await
-        .map_err(|e| e.to_string())?;
+    let client = app_handle
+        .state::<Clients>()
+        .megalodon(server, account.as_ref())?;
 
-    let client = megalodon::generator(
-        sns,
-        url,
-        account.clone().and_then(|a| Some(a.access_token)),
-        Some(String::from("fedistar")),
-    )
-    .map_err(|err| err.to_string())?;
+    // Account timelines do not have a streaming.
+    if timeline.kind == entities::timeline::Kind::Account {
+        return poll(
+            app_handle,
+            server,
+            timeline,
+            account.as_ref(),
+            client.as_ref(),
+        )
+        .await;
+    }
 
     let streaming: Box<dyn megalodon::Streaming + Send + Sync>;
     match timeline.kind {
@@ -218,7 +318,12 @@ pub async fn start(
             streaming = client.direct_streaming().await;
         }
         entities::timeline::Kind::List => match &timeline.list_id {
-            None => return Err(format!("could not find list_id for {} ", timeline.name)),
+            None => {
+                return Err(Error::validation(format!(
+                    "could not find list_id for {} ",
+                    timeline.name
+                )))
+            }
             Some(list_id) => {
                 streaming = client.list_streaming(list_id.to_string()).await;
             }
@@ -226,7 +331,12 @@ pub async fn start(
         entities::timeline::Kind::Tag => {
             streaming = client.tag_streaming(timeline.name.clone()).await;
         }
-        _ => return Err(format!("{} is not supported", timeline.name)),
+        _ => {
+            return Err(Error::validation(format!(
+                "{} is not supported",
+                timeline.name
+            )))
+        }
     }
 
     if !streaming.is_supported() {
@@ -235,6 +345,12 @@ pub async fn start(
     }
 
     loop {
+        // Some servers require signing in to read public timelines, which fails the streaming forever.
+        if account.is_none() && requires_auth(&app_handle, server, timeline, client.as_ref()).await
+        {
+            tracing::info!("{} requires signing in", streaming_message);
+            return Ok(());
+        }
         tracing::info!("{} is started", streaming_message);
 
         let timeline_id = timeline.id;
@@ -249,58 +365,54 @@ pub async fn start(
                 match message {
                     Message::Update(mes) => {
                         tracing::debug!("receive update");
-                        app_handle
-                            .emit(
-                                "receive-timeline-status",
-                                ReceiveTimelineStatusPayload {
-                                    server_id,
-                                    timeline_id,
-                                    name: name.clone(),
-                                    status: mes,
-                                },
-                            )
-                            .expect("Failed to receive-timeline-status event");
+                        events::emit(
+                            &app_handle,
+                            "receive-timeline-status",
+                            ReceiveTimelineStatusPayload {
+                                server_id,
+                                timeline_id,
+                                name: name.clone(),
+                                status: mes,
+                            },
+                        );
                     }
                     Message::StatusUpdate(mes) => {
                         tracing::debug!("receive status update");
-                        app_handle
-                            .emit(
-                                "receive-timeline-status-update",
-                                ReceiveTimelineStatusUpdatePayload {
-                                    server_id,
-                                    timeline_id,
-                                    name: name.clone(),
-                                    status: mes,
-                                },
-                            )
-                            .expect("Failed to receive-timeline-status-update event");
+                        events::emit(
+                            &app_handle,
+                            "receive-timeline-status-update",
+                            ReceiveTimelineStatusUpdatePayload {
+                                server_id,
+                                timeline_id,
+                                name: name.clone(),
+                                status: mes,
+                            },
+                        );
                     }
                     Message::Delete(status_id) => {
                         tracing::debug!("receive delete");
-                        app_handle
-                            .emit(
-                                "delete-timeline-status",
-                                DeleteTimelineStatusPayload {
-                                    server_id,
-                                    timeline_id,
-                                    name: name.clone(),
-                                    status_id,
-                                },
-                            )
-                            .expect("Failed to delete-timeline-status event");
+                        events::emit(
+                            &app_handle,
+                            "delete-timeline-status",
+                            DeleteTimelineStatusPayload {
+                                server_id,
+                                timeline_id,
+                                name: name.clone(),
+                                status_id,
+                            },
+                        );
                     }
                     Message::Conversation(conversation) => {
                         tracing::debug!("receive conversation");
-                        app_handle
-                            .emit(
-                                "receive-timeline-conversation",
-                                ReceiveTimelineConversationPayload {
-                                    server_id,
-                                    timeline_id,
-                                    conversation,
-                                },
-                            )
-                            .expect("Failed to receive-timeline-conversation event");
+                        events::emit(
+                            &app_handle,
+                            "receive-timeline-conversation",
+                            ReceiveTimelineConversationPayload {
+                                server_id,
+                                timeline_id,
+                                conversation,
+                            },
+                        );
                     }
                     _ => {}
                 }
@@ -315,3 +427,223 @@ pub async fn start(
         tokio::time::sleep(std::time::Duration::from_secs(delay)).await;
     }
 }
+
+async fn poll(
+    app_handle: AppHandle,
+    server: &entities::Server,
+    timeline: &entities::Timeline,
+    account: Option<&entities::Account>,
+    client: &(dyn Megalodon + Send + Sync),
+) -> Result<(), Error> {
+    tracing::info!(
+        "{} polling is started for @{}",
+        timeline.name,
+        server.domain
+    );
+
+    let server_id = server.id;
+    let timeline_id = timeline.id;
+    let key = (server_id, account.map(|a| a.id));
+    let scheduler = app_handle.state::<Scheduler>();
+    let mut since_id: Option<String> = None;
+    // The first page is already shown by the frontend, so statuses are emitted from the next poll.
+    // It may be empty, so since_id can not tell whether the first page has been read.
+    let mut initialized = false;
+    loop {
+        let res = scheduler
+            .run(
+                &app_handle,
+                key,
+                Priority::Background,
+                fetch_statuses(client, timeline, since_id.clone()),
+            )
+            .await;
+        match res {
+            Ok(res) => {
+                let statuses = res.json;
+                let known = initialized;
+                initialized = true;
+                if let Some(latest) = statuses.first() {
+                    since_id = Some(latest.id.clone());
+                }
+                for status in statuses.into_iter().rev().filter(|_| known) {
+                    events::emit(
+                        &app_handle,
+                        "receive-timeline-status",
+                        ReceiveTimelineStatusPayload {
+                            server_id,
+                            timeline_id,
+                            name: timeline.name.clone(),
+                            status,
+                        },
+                    );
+                }
+            }
+            Err(err) if err.code == ErrorCode::Validation => return Err(err),
+            Err(err) if err.code == ErrorCode::Auth && account.is_none() => {
+                tracing::info!(
+                    "{} of @{} requires signing in",
+                    timeline.name,
+                    server.domain
+                );
+                emit_auth_required(&app_handle, server_id, timeline_id);
+                return Ok(());
+            }
+            Err(err) => tracing::warn!("Failed to poll {}: {}", timeline.name, err),
+        }
+
+        tokio::time::sleep(POLLING_INTERVAL).await;
+    }
+}
+
+/// Sends the notification with the notifications timelines of the server which show its type.
+async fn emit_notification(
+    app_handle: &AppHandle,
+    server_id: i64,
+    notification: megalodon::entities::Notification,
+) {
+    let sqlite_pool = app_handle.state::<sqlx::SqlitePool>();
+    let timeline_ids = match database::list_notification_filters(&sqlite_pool, server_id).await {
+        Ok(filters) => filters
+            .into_iter()
+            .filter(|(_, filter)| filter.is_none_or(|f| f.matches(&notification.r#type)))
+            .map(|(id, _)| id)
+            .collect(),
+        Err(err) => {
+            tracing::warn!("Failed to list notifications timelines: {}", err);
+            Vec::new()
+        }
+    };
+    events::emit(
+        app_handle,
+        "receive-notification",
+        ReceiveNotificationPayload {
+            server_id,
+            timeline_ids,
+            notification,
+        },
+    );
+}
+
+/// Reads the latest status of the timeline without signing in, and tells the frontend when it is refused.
+async fn requires_auth(
+    app_handle: &AppHandle,
+    server: &entities::Server,
+    timeline: &entities::Timeline,
+    client: &(dyn Megalodon + Send + Sync),
+) -> bool {
+    let res = app_handle
+        .state::<Scheduler>()
+        .run(
+            app_handle,
+            (server.id, None),
+            Priority::Background,
+            fetch_statuses(client, timeline, None),
+        )
+        .await;
+    match res {
+        Err(err) if err.code == ErrorCode::Auth => {
+            emit_auth_required(app_handle, server.id, timeline.id);
+            true
+        }
+        _ => false,
+    }
+}
+
+fn emit_auth_required(app_handle: &AppHandle, server_id: i64, timeline_id: i64) {
+    app_handle
+        .state::<TimelineStatuses>()
+        .insert(timeline_id, TimelineStatus::AuthRequired);
+    events::emit(
+        app_handle,
+        "timeline-status",
+        TimelineStatusPayload {
+            server_id,
+            timeline_id,
+            status: TimelineStatus::AuthRequired,
+        },
+    );
+}
+
+async fn fetch_statuses(
+    client: &(dyn Megalodon + Send + Sync),
+    timeline: &entities::Timeline,
+    since_id: Option<String>,
+) -> Result<Response<Vec<megalodon::entities::Status>>, Error> {
+    let res = match timeline.kind {
+        entities::timeline::Kind::Public => {
+            let options = GetPublicTimelineInputOptions {
+                since_id,
+                ..Default::default()
+            };
+            client.get_public_timeline(Some(&options)).await
+        }
+        entities::timeline::Kind::Local => {
+            let options = GetLocalTimelineInputOptions {
+                since_id,
+                ..Default::default()
+            };
+            client.get_local_timeline(Some(&options)).await
+        }
+        entities::timeline::Kind::Tag => {
+            let options = GetTagTimelineInputOptions {
+                since_id,
+                ..Default::default()
+            };
+            client
+                .get_tag_timeline(timeline.name.clone(), Some(&options))
+                .await
+        }
+        entities::timeline::Kind::Account => {
+            let Some(account_id) = &timeline.remote_account_id else {
+                return Err(Error::validation(format!(
+                    "could not find account_id for {} ",
+                    timeline.name
+                )));
+            };
+            // Pinned statuses are loaded by the frontend, so only new statuses are polled.
+            let options = GetAccountStatusesInputOptions {
+                since_id,
+                exclude_replies: Some(!timeline.show_replies),
+                exclude_reblogs: Some(!timeline.show_boosts),
+                ..Default::default()
+            };
+            client
+                .get_account_statuses(account_id.to_string(), Some(&options))
+                .await
+        }
+        entities::timeline::Kind::List => {
+            let Some(list_id) = &timeline.list_id else {
+                return Err(Error::validation(format!(
+                    "could not find list_id for {} ",
+                    timeline.name
+                )));
+            };
+            let options = GetListTimelineInputOptions {
+                since_id,
+                ..Default::default()
+            };
+            client
+                .get_list_timeline(list_id.to_string(), Some(&options))
+                .await
+        }
+        _ => {
+            return Err(Error::validation(format!(
+                "{} is not supported",
+                timeline.name
+            )))
+        }
+    };
+    res.map_err(read_error)
+}
+
+/// Mastodon refuses anonymous reads of timelines which require signing in with 422
+/// "This method requires an authenticated user", not with 401.
+fn read_error(err: megalodon::error::Error) -> Error {
+    match &err {
+        megalodon::error::Error::OwnError(own) if own.status == Some(422) => {
+            Error::auth(err.to_string())
+        }
+        _ => Error::from(err),
+    }
+}
@@ -0,0 +1,266 @@
+use rustls::{
+    client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+    pki_types::{CertificateDer, ServerName, UnixTime},
+    DigitallySignedStruct, SignatureScheme,
+};
+use rustls_platform_verifier::Verifier;
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, RwLock},
+};
+use url::Url;
+
+use crate::{entities, error::Error};
+
+/// Extra root certificates in PEM keyed by the origin of the server.
+static EXTRA_ROOTS: RwLock<BTreeMap<String, String>> = RwLock::new(BTreeMap::new());
+
+/// Checks that the text contains one or more PEM certificates.
+pub(crate) fn parse_certificates(pem: &str) -> Result<Vec<reqwest::Certificate>, Error> {
+    let certs = reqwest::Certificate::from_pem_bundle(pem.as_bytes())
+        .map_err(|e| Error::validation(format!("Invalid certificate: {}", e)))?;
+    if certs.is_empty() {
+        return Err(Error::validation("No certificate is found"));
+    }
+    Ok(certs)
+}
+
+/// Trusts the certificate for the server, or stops trusting it with None.
+pub(crate) fn set_roots(base_url: &str, pem: Option<&str>) -> Result<(), Error> {
+    if let Some(pem) = pem {
+        parse_certificates(pem)?;
+    }
+    let key = origin(base_url)?;
+    let mut roots = EXTRA_ROOTS.write().unwrap_or_else(|e| e.into_inner());
+    match pem {
+        Some(pem) => roots.insert(key, pem.trim().to_string()),
+        None => roots.remove(&key),
+    };
+    Ok(())
+}
+
+pub(crate) fn load_roots(servers: &[entities::Server]) -> Result<(), Error> {
+    let mut roots = EXTRA_ROOTS.write().unwrap_or_else(|e| e.into_inner());
+    roots.clear();
+    for server in servers {
+        let Some(pem) = &server.ca_certificate else {
+            continue;
+        };
+        match origin(&server.base_url) {
+            Ok(key) => {
+                roots.insert(key, pem.trim().to_string());
+            }
+            Err(e) => tracing::error!("Ignore the certificate for {}: {}", server.base_url, e),
+        }
+    }
+    Ok(())
+}
+
+/// Returns the extra certificates in PEM keyed by the origin of the server.
+pub(crate) fn roots() -> Vec<(String, String)> {
+    let roots = EXTRA_ROOTS.read().unwrap_or_else(|e| e.into_inner());
+    roots.iter().map(|(k, v)| (k.clone(), v.clone())).collect()
+}
+
+/// Returns whether the server of the URL has extra certificates.
+pub(crate) fn has_roots(url: &str) -> bool {
+    let Ok(key) = origin(url) else {
+        return false;
+    };
+    let roots = EXTRA_ROOTS.read().unwrap_or_else(|e| e.into_inner());
+    roots.contains_key(&key)
+}
+
+/// Makes the reqwest client builder trust the certificates in PEM for the origin.
+/// The client is meant for requests to the origin, and the certificates are not trusted
+/// for other hosts which it is redirected to.
+pub(crate) fn scope_roots(
+    builder: reqwest::ClientBuilder,
+    origin: &str,
+    pem: &str,
+    http1_only: bool,
+) -> Result<reqwest::ClientBuilder, Error> {
+    let host = Url::parse(origin)
+        .ok()
+        .and_then(|u| {
+            u.host_str()
+                .map(|h| h.trim_matches(['[', ']']).to_lowercase())
+        })
+        .ok_or_else(|| Error::validation(format!("{} does not have a host", origin)))?;
+    let certs = rustls::pki_types::pem::PemObject::pem_slice_iter(pem.as_bytes())
+        .collect::<Result<Vec<CertificateDer<'static>>, _>>()
+        .map_err(|e| Error::validation(format!("Invalid certificate: {}", e)))?;
+
+    let provider = Arc::new(rustls::crypto::aws_lc_rs::default_provider());
+    let verifier = ScopedVerifier {
+        host,
+        extra: Verifier::new_with_extra_roots(certs, provider.clone())
+            .map_err(|e| Error::internal(e.to_string()))?,
+        platform: Verifier::new(provider.clone()).map_err(|e| Error::internal(e.to_string()))?,
+    };
+    let mut config = rustls::ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .map_err(|e| Error::internal(e.to_string()))?
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(verifier))
+        .with_no_client_auth();
+    // reqwest does not negotiate the protocol for preconfigured TLS.
+    config.alpn_protocols = if http1_only {
+        vec![b"http/1.1".to_vec()]
+    } else {
+        vec![b"h2".to_vec(), b"http/1.1".to_vec()]
+    };
+    Ok(builder.tls_backend_preconfigured(config))
+}
+
+/// Verifies certificates of the host with the extra roots, and the others only with the platform.
+/// TLS does not know the port, so the roots are trusted for every port of the host.
+#[derive(Debug)]
+struct ScopedVerifier {
+    host: String,
+    extra: Verifier,
+    platform: Verifier,
+}
+
+impl ServerCertVerifier for ScopedVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let verifier = if server_name.to_str().eq_ignore_ascii_case(&self.host) {
+            &self.extra
+        } else {
+            &self.platform
+        };
+        verifier.verify_server_cert(end_entity, intermediates, server_name, ocsp_response, now)
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.platform.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.platform.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.platform.supported_verify_schemes()
+    }
+}
+
+pub(crate) fn origin(url: &str) -> Result<String, Error> {
+    let url = Url::parse(url).map_err(|e| Error::validation(format!("{}: {}", url, e)))?;
+    Ok(url.origin().ascii_serialization())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustls::pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer};
+    use std::sync::Arc;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+    use tokio_rustls::TlsAcceptor;
+
+    const CA: &str = include_str!("../fixtures/tls/ca.pem");
+    const SERVER_CERT: &str = include_str!("../fixtures/tls/server.pem");
+    const SERVER_KEY: &str = include_str!("../fixtures/tls/server.key");
+
+    // A local HTTPS server whose certificate is issued by the test CA.
+    async fn start_tls_server() -> u16 {
+        let certs = CertificateDer::pem_slice_iter(SERVER_CERT.as_bytes())
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        let key = PrivateKeyDer::from_pem_slice(SERVER_KEY.as_bytes()).unwrap();
+        let config = rustls::ServerConfig::builder_with_provider(Arc::new(
+            rustls::crypto::ring::default_provider(),
+        ))
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .unwrap();
+        let acceptor = TlsAcceptor::from(Arc::new(config));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        tokio::spawn(async move {
+            loop {
+                let Ok((stream, _)) = listener.accept().await else {
+                    return;
+                };
+                let acceptor = acceptor.clone();
+                tokio::spawn(async move {
+                    let Ok(mut stream) = acceptor.accept(stream).await else {
+                        return;
+                    };
+                    let mut buf = [0; 1024];
+                    let _ = stream.read(&mut buf).await;
+                    let _ = stream
+                        .write_all(
+                            b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok",
+                        )
+                        .await;
+                    let _ = stream.shutdown().await;
+                });
+            }
+        });
+        port
+    }
+
+    #[test]
+    fn test_parse_certificates() {
+        assert_eq!(parse_certificates(CA).unwrap().len(), 1);
+        assert!(parse_certificates("not a certificate").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_client_trusts_extra_roots() {
+        let port = start_tls_server().await;
+        let url = format!("https://localhost:{}/social", port);
+        let origin = origin(&url).unwrap();
+
+        let client = reqwest::Client::new();
+        assert!(client.get(&url).send().await.is_err());
+
+        let client = scope_roots(reqwest::Client::builder(), &origin, CA, false)
+            .unwrap()
+            .build()
+            .unwrap();
+        let res = client.get(&url).send().await.unwrap();
+        assert_eq!(res.text().await.unwrap(), "ok");
+    }
+
+    #[tokio::test]
+    async fn test_extra_roots_are_not_trusted_for_other_hosts() {
+        let port = start_tls_server().await;
+        let url = format!("https://localhost:{}/", port);
+
+        let client = scope_roots(
+            reqwest::Client::builder(),
+            "https://internal.example",
+            CA,
+            false,
+        )
+        .unwrap()
+        .build()
+        .unwrap();
+        assert!(client.get(&url).send().await.is_err());
+    }
+}
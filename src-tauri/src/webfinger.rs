@@ -0,0 +1,351 @@
+use scraper::{Html, Selector};
+use serde::Deserialize;
+use url::Url;
+
+use crate::{
+    error::Error,
+    server_url::{self, ServerUrl, Target},
+};
+
+const ACTIVITY_TYPES: [&str; 2] = [
+    "application/activity+json",
+    "application/ld+json; profile=\"https://www.w3.org/ns/activitystreams\"",
+];
+const PROFILE_PAGE: &str = "http://webfinger.net/rel/profile-page";
+
+#[derive(Deserialize)]
+struct Jrd {
+    #[serde(default)]
+    links: Vec<JrdLink>,
+}
+
+#[derive(Deserialize)]
+struct JrdLink {
+    rel: String,
+    #[serde(rename = "type")]
+    kind: Option<String>,
+    href: Option<String>,
+}
+
+/// Finds the server which the user means by a domain, a server URL, a profile URL or a handle.
+/// Domains which delegate WebFinger to another host, such as `example.com` to `social.example.com`, are resolved to that host.
+pub(crate) async fn resolve(client: &reqwest::Client, input: &str) -> Result<ServerUrl, Error> {
+    match server_url::parse_target(input)? {
+        Target::Handle { user, host } => {
+            let resource = format!("acct:{}@{}", user, host.domain);
+            let jrd = lookup(client, &host, &resource).await?;
+            let Some(href) = actor(&jrd) else {
+                return Err(Error::validation(format!(
+                    "{} does not have an ActivityPub actor",
+                    resource
+                )));
+            };
+            match server_url::parse_target(&href)? {
+                Target::Server(server) => Ok(server),
+                Target::Handle { .. } => Err(Error::validation(format!(
+                    "Invalid actor {} of {}",
+                    href, resource
+                ))),
+            }
+        }
+        Target::Server(server) => match delegated_host(client, &server).await {
+            Some(delegated) => {
+                tracing::info!(
+                    "{} delegates WebFinger to {}",
+                    server.domain,
+                    delegated.domain
+                );
+                Ok(delegated)
+            }
+            None => Ok(server),
+        },
+    }
+}
+
+async fn lookup(client: &reqwest::Client, host: &ServerUrl, resource: &str) -> Result<Jrd, Error> {
+    let mut url = Url::parse(&format!("{}/.well-known/webfinger", host.base_url))
+        .map_err(|e| Error::validation(e.to_string()))?;
+    url.query_pairs_mut().append_pair("resource", resource);
+    let res = client
+        .get(url)
+        .header(reqwest::header::ACCEPT, "application/jrd+json")
+        .send()
+        .await?;
+    if res.status().is_success() {
+        return Ok(res.json().await?);
+    }
+
+    // Older servers only announce the WebFinger endpoint in host-meta.
+    let Some(template) = host_meta_template(client, host).await else {
+        return Err(Error::not_found(format!(
+            "{} is not found on {}",
+            resource, host.domain
+        )));
+    };
+    let url = template.replace("{uri}", &encode(resource));
+    Ok(client
+        .get(url)
+        .header(reqwest::header::ACCEPT, "application/jrd+json")
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?)
+}
+
+/// Returns the host which serves WebFinger for the server, when it is different from the server itself.
+async fn delegated_host(client: &reqwest::Client, server: &ServerUrl) -> Option<ServerUrl> {
+    // Servers under a path are not delegated, since host-meta is only on the root.
+    if server.base_url.matches('/').count() > 2 {
+        return None;
+    }
+    let template = host_meta_template(client, server).await?;
+    let delegated = server_url::parse(
+        Url::parse(&template)
+            .ok()?
+            .origin()
+            .ascii_serialization()
+            .as_str(),
+    )
+    .ok()?;
+    (delegated.domain != server.domain).then_some(delegated)
+}
+
+async fn host_meta_template(client: &reqwest::Client, host: &ServerUrl) -> Option<String> {
+    let res = client
+        .get(format!("{}/.well-known/host-meta", host.base_url))
+        .send()
+        .await
+        .ok()?
+        .error_for_status()
+        .ok()?;
+    parse_host_meta(&res.text().await.ok()?)
+}
+
+/// Reads the WebFinger template from the XRD of host-meta.
+fn parse_host_meta(content: &str) -> Option<String> {
+    let document = Html::parse_document(content);
+    let selector = Selector::parse("link[rel=\"lrdd\"][template]").expect("Failed to parse link");
+    document
+        .select(&selector)
+        .filter_map(|e| e.value().attr("template"))
+        .find(|t| t.contains("{uri}"))
+        .map(|t| t.to_string())
+}
+
+/// Returns the ActivityPub actor of the account, or its profile page when the actor is not linked.
+fn actor(jrd: &Jrd) -> Option<String> {
+    let href = |rel: &str, types: &[&str]| {
+        jrd.links
+            .iter()
+            .filter(|l| l.rel == rel)
+            .filter(|l| types.is_empty() || l.kind.as_deref().is_some_and(|k| types.contains(&k)))
+            .find_map(|l| l.href.clone())
+    };
+    href("self", &ACTIVITY_TYPES).or_else(|| href(PROFILE_PAGE, &[]))
+}
+
+fn encode(value: &str) -> String {
+    url::form_urlencoded::byte_serialize(value.as_bytes()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    // A local HTTP server which replies the body for each path, and 404 for the others.
+    async fn start_server(routes: impl Fn(u16) -> Vec<(&'static str, String)>) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let routes = routes(port);
+
+        tokio::spawn(async move {
+            loop {
+                let Ok((mut stream, _)) = listener.accept().await else {
+                    return;
+                };
+                let mut buf = [0; 1024];
+                let n = stream.read(&mut buf).await.unwrap_or(0);
+                let request = String::from_utf8_lossy(&buf[..n]);
+                let path = request
+                    .split_whitespace()
+                    .nth(1)
+                    .and_then(|target| target.split('?').next())
+                    .unwrap_or_default();
+                let response = match routes.iter().find(|(p, _)| *p == path) {
+                    Some((_, body)) => format!(
+                        "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        body.len(),
+                        body
+                    ),
+                    None => {
+                        "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                            .to_string()
+                    }
+                };
+                let _ = stream.write_all(response.as_bytes()).await;
+                let _ = stream.shutdown().await;
+            }
+        });
+        port
+    }
+
+    fn host_meta(template: &str) -> String {
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<XRD xmlns="http://docs.oasis-open.org/ns/xri/xrd-1.0">
+  <Link rel="lrdd" template="{}"/>
+</XRD>"#,
+            template
+        )
+    }
+
+    const JRD: &str = r#"{ "links": [{ "rel": "self", "type": "application/activity+json", "href": "https://social.example.com/users/alice" }] }"#;
+
+    #[tokio::test]
+    async fn test_resolve_delegated_host() {
+        let port = start_server(|_| {
+            vec![(
+                "/.well-known/host-meta",
+                host_meta("http://localhost:8080/.well-known/webfinger?resource={uri}"),
+            )]
+        })
+        .await;
+        let client = reqwest::Client::new();
+
+        let server = resolve(&client, &format!("http://127.0.0.1:{}", port))
+            .await
+            .unwrap();
+        assert_eq!(server.base_url, "http://localhost:8080");
+    }
+
+    #[tokio::test]
+    async fn test_resolve_without_delegation() {
+        let port = start_server(|_| Vec::new()).await;
+        let client = reqwest::Client::new();
+
+        let url = format!("http://127.0.0.1:{}", port);
+        assert_eq!(resolve(&client, &url).await.unwrap().base_url, url);
+    }
+
+    #[tokio::test]
+    async fn test_delegated_host_ignores_servers_under_a_path() {
+        let port = start_server(|_| {
+            vec![(
+                "/.well-known/host-meta",
+                host_meta("http://localhost:8080/.well-known/webfinger?resource={uri}"),
+            )]
+        })
+        .await;
+        let client = reqwest::Client::new();
+
+        let server = server_url::parse(&format!("http://127.0.0.1:{}/social", port)).unwrap();
+        assert!(delegated_host(&client, &server).await.is_none());
+        let server = server_url::parse(&format!("http://127.0.0.1:{}", port)).unwrap();
+        assert_eq!(
+            delegated_host(&client, &server).await.unwrap().base_url,
+            "http://localhost:8080"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_lookup() {
+        let port = start_server(|_| vec![("/.well-known/webfinger", JRD.to_string())]).await;
+        let client = reqwest::Client::new();
+
+        let host = server_url::parse(&format!("http://127.0.0.1:{}", port)).unwrap();
+        let jrd = lookup(&client, &host, "acct:alice@127.0.0.1")
+            .await
+            .unwrap();
+        assert_eq!(
+            actor(&jrd).as_deref(),
+            Some("https://social.example.com/users/alice")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_lookup_falls_back_to_host_meta() {
+        let port = start_server(|port| {
+            vec![
+                (
+                    "/.well-known/host-meta",
+                    host_meta(&format!(
+                        "http://127.0.0.1:{}/webfinger?resource={{uri}}",
+                        port
+                    )),
+                ),
+                ("/webfinger", JRD.to_string()),
+            ]
+        })
+        .await;
+        let client = reqwest::Client::new();
+
+        let host = server_url::parse(&format!("http://127.0.0.1:{}", port)).unwrap();
+        let jrd = lookup(&client, &host, "acct:alice@127.0.0.1")
+            .await
+            .unwrap();
+        assert_eq!(
+            actor(&jrd).as_deref(),
+            Some("https://social.example.com/users/alice")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_lookup_not_found() {
+        let port = start_server(|_| Vec::new()).await;
+        let client = reqwest::Client::new();
+
+        let host = server_url::parse(&format!("http://127.0.0.1:{}", port)).unwrap();
+        assert!(lookup(&client, &host, "acct:alice@127.0.0.1")
+            .await
+            .is_err());
+    }
+
+    #[test]
+    fn test_parse_host_meta() {
+        let content = r#"<?xml version="1.0" encoding="UTF-8"?>
+<XRD xmlns="http://docs.oasis-open.org/ns/xri/xrd-1.0">
+  <Link rel="lrdd" template="https://social.example.com/.well-known/webfinger?resource={uri}"/>
+</XRD>"#;
+        assert_eq!(
+            parse_host_meta(content).as_deref(),
+            Some("https://social.example.com/.well-known/webfinger?resource={uri}")
+        );
+        assert_eq!(parse_host_meta("<XRD></XRD>"), None);
+    }
+
+    #[test]
+    fn test_actor() {
+        let jrd: Jrd = serde_json::from_str(
+            r#"{
+  "subject": "acct:alice@example.com",
+  "links": [
+    { "rel": "http://webfinger.net/rel/profile-page", "type": "text/html", "href": "https://social.example.com/@alice" },
+    { "rel": "self", "type": "application/activity+json", "href": "https://social.example.com/users/alice" },
+    { "rel": "http://ostatus.org/schema/1.0/subscribe", "template": "https://social.example.com/authorize_interaction?uri={uri}" }
+  ]
+}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            actor(&jrd).as_deref(),
+            Some("https://social.example.com/users/alice")
+        );
+    }
+
+    #[test]
+    fn test_actor_falls_back_to_profile_page() {
+        let jrd: Jrd = serde_json::from_str(
+            r#"{ "links": [{ "rel": "http://webfinger.net/rel/profile-page", "href": "https://social.example.com/@alice" }] }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            actor(&jrd).as_deref(),
+            Some("https://social.example.com/@alice")
+        );
+    }
+}
@@ -1,4 +1,4 @@
-import { invoke } from '@tauri-apps/api/core'
+import { convertFileSrc, invoke } from '@tauri-apps/api/core'
 import { Dispatch, ReactElement, SetStateAction, useEffect, useState } from 'react'
 import { Icon } from '@rsuite/icons'
 import { Popover, Dropdown, Sidebar, Sidenav, Whisper, Button, Avatar, Badge, useToaster } from 'rsuite'
@@ -213,7 +213,7 @@ const Navigator: React.FC<NavigatorProps> = (props): ReactElement => {
                     <Badge invisible={props.unreads.find(u => u.server_id === server.server.id && u.count > 0) ? false : true}>
                       <Avatar
                         size="sm"
-                        src={FailoverImg(server.server.favicon)}
+                        src={FailoverImg(server.server.favicon ? convertFileSrc(String(server.server.id), 'favicon') : null)}
                         className="server-icon"
                         alt={server.server.domain}
                         key={server.server.id}
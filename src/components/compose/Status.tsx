@@ -41,6 +41,7 @@ import { Server } from 'src/entities/server'
 import { CustomEmojiCategory } from 'src/entities/emoji'
 import alert from 'src/components/utils/alert'
 import { Account } from 'src/entities/account'
+import { ServerMetadata } from 'src/entities/serverMetadata'
 import AutoCompleteTextarea, { ArgProps as AutoCompleteTextareaProps } from './AutoCompleteTextarea'
 import languages from 'src/utils/languages'
 import EditMedia from './EditMedia'
@@ -127,12 +128,11 @@ const Status: React.FC<Props> = props => {
     }
 
     const f = async () => {
-      const instance = await invoke<Entity.Instance>('get_instance', { serverId: props.server.id })
-      if (instance.configuration.statuses.max_characters) {
-        setMaxCharacters(instance.configuration.statuses.max_characters)
+      const metadata = await invoke<ServerMetadata>('get_server_metadata', { serverId: props.server.id })
+      if (metadata.limits.max_characters) {
+        setMaxCharacters(metadata.limits.max_characters)
       }
-      const emojis = await props.client.getInstanceCustomEmojis()
-      setCustomEmojis(mapCustomEmojiCategory(props.server.domain, emojis.data))
+      setCustomEmojis(mapCustomEmojiCategory(props.server.domain, metadata.emojis))
     }
     f()
   }, [props.server, props.client])
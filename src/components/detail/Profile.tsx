@@ -291,6 +291,7 @@ const Profile: React.FC<Props> = props => {
                             top,
                             onClose,
                             client,
+                            server,
                             myself,
                             user,
                             relationship,
@@ -366,6 +367,7 @@ type ProfileMenuProps = {
   top?: number
   onClose: (delay?: number) => NodeJS.Timeout | void
   client: MegalodonInterface
+  server: Server
   myself: Entity.Account
   user: Entity.Account
   relationship: Entity.Relationship | null
@@ -374,7 +376,7 @@ type ProfileMenuProps = {
 }
 
 const profileMenu = (
-  { className, left, top, onClose, client, myself, user, relationship, onChange, openAddListMember }: ProfileMenuProps,
+  { className, left, top, onClose, client, server, myself, user, relationship, onChange, openAddListMember }: ProfileMenuProps,
   ref: React.RefCallback<HTMLElement>
 ): ReactElement => {
   const domain = domainFromAcct(user.acct)
@@ -386,6 +388,10 @@ const profileMenu = (
         open(user.url)
         return
       }
+      case 'add_column': {
+        await invoke('add_timeline', { server: server, kind: 'account', name: `@${user.acct}`, listId: null, columnWidth: 'sm' })
+        return
+      }
       case 'mute': {
         if (relationship.muting) {
           await client.unmuteAccount(user.id)
@@ -425,6 +431,9 @@ const profileMenu = (
         <Dropdown.Item eventKey="browser">
           <FormattedMessage id="detail.profile.open_page" />
         </Dropdown.Item>
+        <Dropdown.Item eventKey="add_column">
+          <FormattedMessage id="detail.profile.add_column" />
+        </Dropdown.Item>
         {relationship && (
           <>
             <Dropdown.Separator style={{ backgroundColor: 'var(--rs-dropdown-header-text)' }} />
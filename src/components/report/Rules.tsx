@@ -4,6 +4,7 @@ import { useEffect, useState } from 'react'
 import { FormattedMessage } from 'react-intl'
 import { Button, Checkbox, CheckboxGroup, Modal } from 'rsuite'
 import { Server } from 'src/entities/server'
+import { ServerMetadata } from 'src/entities/serverMetadata'
 
 type Props = {
   server: Server
@@ -16,10 +17,8 @@ export default function Rules(props: Props) {
 
   useEffect(() => {
     const f = async () => {
-      const instance = await invoke<Entity.Instance>('get_instance', { serverId: props.server.id })
-      if (instance.rules) {
-        setRules(instance.rules)
-      }
+      const metadata = await invoke<ServerMetadata>('get_server_metadata', { serverId: props.server.id })
+      setRules(metadata.rules)
     }
     f()
   }, [props.server])
@@ -4,7 +4,6 @@ import { invoke } from '@tauri-apps/api/core'
 import { Server } from 'src/entities/server'
 import { OAuth } from 'megalodon'
 import alert from '../utils/alert'
-import { parseDomain } from 'src/utils/domainParser'
 import { FormattedMessage, useIntl } from 'react-intl'
 import { BsClipboard } from 'react-icons/bs'
 import { Icon } from '@rsuite/icons'
@@ -36,12 +35,12 @@ const New: React.FC<Props> = props => {
   async function addServer() {
     setLoading(true)
     try {
-      const d = parseDomain(domain)
-      const res = await invoke<Server>('add_server', { domain: d })
+      const res = await invoke<Server>('add_server', { domain: domain.trim() })
       setServer(res)
     } catch (err) {
       console.error(err)
-      toast.push(alert('error', formatMessage({ id: 'alert.failed_add_server' }, { domain: domain })), { placement: 'topCenter' })
+      const id = err?.code === 'already_exists' ? 'alert.server_already_exists' : 'alert.failed_add_server'
+      toast.push(alert('error', formatMessage({ id }, { domain: domain })), { placement: 'topCenter' })
     } finally {
       setLoading(false)
     }
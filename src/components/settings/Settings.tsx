@@ -121,17 +121,20 @@ export default function Settings(props: Props) {
   }, [props.open])
 
   const handleSubmit = async () => {
+    // Keep the settings which are not edited in this dialog, such as the proxy and the media display.
     const s: SettingsType = {
+      ...settings,
       appearance: {
+        ...settings.appearance,
         font_size: Number(formValue.font_size),
         font_family: formValue.font_family,
         language: formValue.language,
         color_theme: formValue.color_theme
       },
       behavior: {
+        ...settings.behavior,
         confirm_reblog: formValue.confirm_reblog
-      },
-      app_menu: settings.app_menu
+      }
     }
     await invoke('save_settings', { obj: s })
     props.reloadAppearance()
@@ -10,34 +10,45 @@ import {
   Container,
   Header,
   Content,
-  Button
+  Button,
+  Input,
+  useToaster
 } from 'rsuite'
 import { Icon } from '@rsuite/icons'
-import { BsPlus, BsHouseDoor, BsBell, BsPeople, BsGlobe2, BsStar, BsListUl, BsChevronLeft, BsBookmark, BsEnvelope } from 'react-icons/bs'
+import { BsPlus, BsHouseDoor, BsBell, BsAt, BsPeople, BsGlobe2, BsStar, BsListUl, BsChevronLeft, BsBookmark, BsEnvelope } from 'react-icons/bs'
 import { Server, ServerSet } from '../../entities/server'
 import { useEffect, useState } from 'react'
 import { invoke } from '@tauri-apps/api/core'
 import generator, { Entity } from 'megalodon'
 import { Account } from 'src/entities/account'
-import { TimelineKind } from 'src/entities/timeline'
+import { NotificationFilter, Timeline, TimelineKind } from 'src/entities/timeline'
 import { Instruction } from 'src/entities/instruction'
+import { Capabilities } from 'src/entities/capabilities'
 import { listen } from '@tauri-apps/api/event'
 import { FormattedMessage, useIntl } from 'react-intl'
+import alert from '../utils/alert'
 
 type AuthorizedProps = {
   server: Server
-  select: (kind: TimelineKind, name: string, list_id: string | null) => void
+  select: (kind: TimelineKind, name: string, list_id: string | null, notificationFilter?: NotificationFilter) => void
 }
 
 const AuthorizedTimelines: React.FC<AuthorizedProps> = props => {
   const [loading, setLoading] = useState<boolean>(false)
   const [lists, setLists] = useState<Array<Entity.List>>([])
+  const [capabilities, setCapabilities] = useState<Capabilities | null>(null)
   const { server, select } = props
 
   useEffect(() => {
     const f = async () => {
       setLoading(true)
       try {
+        const capabilities = await invoke<Capabilities>('get_capabilities', { serverId: server.id })
+        setCapabilities(capabilities)
+        if (!capabilities.lists) {
+          setLists([])
+          return
+        }
         const [account, _] = await invoke<[Account, Server]>('get_account', { id: server.account_id })
         const client = generator(server.sns, server.base_url, account.access_token, 'Fedistar')
         const res = await client.getLists()
@@ -75,31 +86,45 @@ const AuthorizedTimelines: React.FC<AuthorizedProps> = props => {
           </FlexboxGrid.Item>
         </FlexboxGrid>
       </List.Item>
-      <List.Item index={5} onClick={() => select('favourites', 'Favourites', null)} style={{ cursor: 'pointer' }}>
+      <List.Item index={5} onClick={() => select('notifications', 'Mentions', null, 'mentions')} style={{ cursor: 'pointer' }}>
         <FlexboxGrid align="middle">
           <FlexboxGrid.Item colspan={4} style={{ display: 'flex', justifyContent: 'center', alignItems: 'center' }}>
-            <Icon as={BsStar} />
+            <Icon as={BsAt} />
           </FlexboxGrid.Item>
           <FlexboxGrid.Item colspan={20}>
             <div>
-              <FormattedMessage id="timeline.favourites" />
+              <FormattedMessage id="timeline.notification_filter.mentions" />
             </div>
           </FlexboxGrid.Item>
         </FlexboxGrid>
       </List.Item>
-      <List.Item index={6} onClick={() => select('bookmarks', 'Bookmarks', null)} style={{ cursor: 'pointer' }}>
+      <List.Item index={6} onClick={() => select('favourites', 'Favourites', null)} style={{ cursor: 'pointer' }}>
         <FlexboxGrid align="middle">
           <FlexboxGrid.Item colspan={4} style={{ display: 'flex', justifyContent: 'center', alignItems: 'center' }}>
-            <Icon as={BsBookmark} />
+            <Icon as={BsStar} />
           </FlexboxGrid.Item>
           <FlexboxGrid.Item colspan={20}>
             <div>
-              <FormattedMessage id="timeline.bookmarks" />
+              <FormattedMessage id="timeline.favourites" />
             </div>
           </FlexboxGrid.Item>
         </FlexboxGrid>
       </List.Item>
-      <List.Item index={7} onClick={() => select('direct', 'Direct messages', null)} style={{ cursor: 'pointer' }}>
+      {capabilities?.bookmarks !== false && (
+        <List.Item index={7} onClick={() => select('bookmarks', 'Bookmarks', null)} style={{ cursor: 'pointer' }}>
+          <FlexboxGrid align="middle">
+            <FlexboxGrid.Item colspan={4} style={{ display: 'flex', justifyContent: 'center', alignItems: 'center' }}>
+              <Icon as={BsBookmark} />
+            </FlexboxGrid.Item>
+            <FlexboxGrid.Item colspan={20}>
+              <div>
+                <FormattedMessage id="timeline.bookmarks" />
+              </div>
+            </FlexboxGrid.Item>
+          </FlexboxGrid>
+        </List.Item>
+      )}
+      <List.Item index={8} onClick={() => select('direct', 'Direct messages', null)} style={{ cursor: 'pointer' }}>
         <FlexboxGrid align="middle">
           <FlexboxGrid.Item colspan={4} style={{ display: 'flex', justifyContent: 'center', alignItems: 'center' }}>
             <Icon as={BsEnvelope} />
@@ -112,12 +137,12 @@ const AuthorizedTimelines: React.FC<AuthorizedProps> = props => {
         </FlexboxGrid>
       </List.Item>
       {loading && (
-        <List.Item index={8}>
+        <List.Item index={9}>
           <Loader />
         </List.Item>
       )}
       {lists.map((list, index) => (
-        <List.Item key={index} index={8 + index} onClick={() => select('list', list.title, list.id)} style={{ cursor: 'pointer' }}>
+        <List.Item key={index} index={9 + index} onClick={() => select('list', list.title, list.id)} style={{ cursor: 'pointer' }}>
           <FlexboxGrid align="middle">
             <FlexboxGrid.Item colspan={4} style={{ display: 'flex', justifyContent: 'center', alignItems: 'center' }}>
               <Icon as={BsListUl} />
@@ -141,6 +166,11 @@ const New: React.FC<Props> = props => {
 
   const [server, setServer] = useState<Server | null>(null)
   const [walkthrough, setWalkthrough] = useState<boolean>(false)
+  const [browsing, setBrowsing] = useState<boolean>(false)
+  const [browseDomain, setBrowseDomain] = useState('')
+  const [loading, setLoading] = useState<boolean>(false)
+
+  const toast = useToaster()
 
   useEffect(() => {
     listen<Instruction>('updated-instruction', event => {
@@ -168,6 +198,10 @@ const New: React.FC<Props> = props => {
   const addTimelineMenu = ({ onClose, left, top, className }: { onClose?: any; left?: any; top?: any; className?: any }, ref: any) => {
     const handleSelect = (eventKey: string) => {
       onClose()
+      if (eventKey === 'browse') {
+        setBrowsing(true)
+        return
+      }
       const target = props.servers.find(s => s.server.id === parseInt(eventKey))
       setServer(target.server)
     }
@@ -179,6 +213,10 @@ const New: React.FC<Props> = props => {
               {server.account ? server.account.username + '@' + server.server.domain : server.server.domain}
             </Dropdown.Item>
           ))}
+          <Dropdown.Separator />
+          <Dropdown.Item eventKey="browse">
+            <FormattedMessage id="timeline.browse" />
+          </Dropdown.Item>
         </Dropdown.Menu>
       </Popover>
     )
@@ -227,15 +265,61 @@ const New: React.FC<Props> = props => {
     </div>
   )
 
-  const select = async (tl: TimelineKind, name: string, list_id: string | null) => {
-    await invoke('add_timeline', { server: server, kind: tl, name: name, listId: list_id, columnWidth: 'sm' })
+  const select = async (tl: TimelineKind, name: string, list_id: string | null, notificationFilter?: NotificationFilter) => {
+    const timeline = await invoke<Timeline>('add_timeline', { server: server, kind: tl, name: name, listId: list_id, columnWidth: 'sm' })
+    if (notificationFilter) {
+      await invoke('update_notification_filter', { id: timeline.id, notificationFilter: notificationFilter })
+    }
     setServer(null)
   }
 
   const back = async () => {
     setServer(null)
+    setBrowsing(false)
+    setBrowseDomain('')
+  }
+
+  const browse = async () => {
+    setLoading(true)
+    try {
+      await invoke('browse_instance', { domain: browseDomain.trim() })
+      back()
+    } catch (err) {
+      console.error(err)
+      toast.push(alert('error', formatMessage({ id: 'alert.failed_browse_instance' }, { domain: browseDomain })), {
+        placement: 'topCenter'
+      })
+    } finally {
+      setLoading(false)
+    }
   }
 
+  const browseInstance = () => (
+    <div className="add-timeline" style={{ width: '240px', minWidth: '240px', display: 'flex', flexDirection: 'column', height: '100%' }}>
+      <Container style={{ height: '100%' }}>
+        <Header style={{ backgroundColor: 'var(--rs-border-secondary)' }}>
+          <div style={{ display: 'flex', alignItems: 'center', justifyContent: 'space-between' }}>
+            <div style={{ paddingLeft: '8px', lineHeight: '52px' }}>
+              <FormattedMessage id="timeline.browse" />
+            </div>
+            <div>
+              <Button appearance="link" onClick={back} title={formatMessage({ id: 'timeline.back' })}>
+                <Icon as={BsChevronLeft} />
+                <FormattedMessage id="timeline.back" />
+              </Button>
+            </div>
+          </div>
+        </Header>
+        <Content style={{ padding: '8px' }}>
+          <Input value={browseDomain} onChange={value => setBrowseDomain(value)} placeholder="mastodon.social" />
+          <Button appearance="primary" block style={{ marginTop: '8px' }} disabled={loading} onClick={browse}>
+            {loading ? <Loader /> : <FormattedMessage id="timeline.browse_instance" />}
+          </Button>
+        </Content>
+      </Container>
+    </div>
+  )
+
   const selectTimeline = () => (
     <div className="add-timeline" style={{ width: '240px', minWidth: '240px', display: 'flex', flexDirection: 'column', height: '100%' }}>
       <Container style={{ height: '100%' }}>
@@ -283,7 +367,9 @@ const New: React.FC<Props> = props => {
     </div>
   )
 
-  if (server === null) {
+  if (browsing) {
+    return browseInstance()
+  } else if (server === null) {
     return addButton()
   } else {
     return selectTimeline()
@@ -4,12 +4,12 @@ import { Icon } from '@rsuite/icons'
 import { invoke } from '@tauri-apps/api/core'
 import { listen } from '@tauri-apps/api/event'
 import { useEffect, useState, forwardRef, useRef, useCallback, Dispatch, SetStateAction } from 'react'
-import generator, { MegalodonInterface, Entity } from 'megalodon'
+import generator, { MegalodonInterface, Entity, NotificationType } from 'megalodon'
 import { Virtuoso } from 'react-virtuoso'
 
 import { Account } from 'src/entities/account'
 import { Server } from 'src/entities/server'
-import { columnWidth, Timeline } from 'src/entities/timeline'
+import { columnWidth, NotificationFilter, Timeline } from 'src/entities/timeline'
 import Notification from './notification/Notification'
 import FailoverImg from 'src/utils/failoverImg'
 import { ReceiveNotificationPayload } from 'src/payload'
@@ -77,7 +77,7 @@ const Notifications: React.FC<Props> = props => {
       setCustomEmojis(mapCustomEmojiCategory(props.server.domain, emojis.data))
 
       listen<ReceiveNotificationPayload>('receive-notification', ev => {
-        if (ev.payload.server_id !== props.server.id) {
+        if (ev.payload.server_id !== props.server.id || !ev.payload.timeline_ids.includes(props.timeline.id)) {
           return
         }
         updateMarker(cli)
@@ -102,6 +102,12 @@ const Notifications: React.FC<Props> = props => {
     f()
   }, [])
 
+  useEffect(() => {
+    if (client) {
+      reload()
+    }
+  }, [props.timeline.notification_filter])
+
   useEffect(() => {
     if (!replyOpened.current) {
       prependUnreads()
@@ -128,12 +134,20 @@ const Notifications: React.FC<Props> = props => {
   }
 
   const loadNotifications = async (client: MegalodonInterface, maxId?: string): Promise<Array<Entity.Notification>> => {
-    let options = { limit: TIMELINE_STATUSES_COUNT }
+    let options: { limit: number; max_id?: string; exclude_type?: Array<Entity.NotificationType> } = { limit: TIMELINE_STATUSES_COUNT }
     if (maxId) {
       options = Object.assign({}, options, { max_id: maxId })
     }
+    const filter = props.timeline.notification_filter
+    let types: Array<string> | null = null
+    if (filter) {
+      const included = await invoke<Array<string>>('get_notification_types', { notificationFilter: filter })
+      options = Object.assign({}, options, { exclude_type: Object.values(NotificationType).filter(t => !included.includes(t)) })
+      types = included
+    }
     const res = await client.getNotifications(options)
-    return res.data.filter(n => !!n.account)
+    // Some servers ignore excluded types, so they are checked again.
+    return res.data.filter(n => !!n.account && (!types || types.includes(n.type)))
   }
 
   const closeOptionPopover = () => triggerRef?.current.close()
@@ -290,9 +304,9 @@ const Notifications: React.FC<Props> = props => {
                     whiteSpace: 'nowrap',
                     width: 'calc(100% - 42px)'
                   }}
-                  title={timelineName(props.timeline.kind, props.timeline.name, formatMessage) + '@' + props.server.domain}
+                  title={timelineName(props.timeline.kind, props.timeline.name, formatMessage, props.timeline.notification_filter) + '@' + props.server.domain}
                 >
-                  {timelineName(props.timeline.kind, props.timeline.name, formatMessage)}
+                  {timelineName(props.timeline.kind, props.timeline.name, formatMessage, props.timeline.notification_filter)}
                   <span style={{ fontSize: '14px', color: 'var(--rs-text-secondary)' }}>@{props.server.domain}</span>
                 </div>
               </div>
@@ -432,6 +446,11 @@ const OptionPopover = forwardRef<HTMLDivElement, { timeline: Timeline; close: ()
     props.close()
   }
 
+  const updateNotificationFilter = async (timeline: Timeline, notificationFilter: NotificationFilter | null) => {
+    await invoke('update_notification_filter', { id: timeline.id, notificationFilter: notificationFilter })
+    props.close()
+  }
+
   return (
     <Popover ref={ref} style={{ opacity: 1 }}>
       <div style={{ display: 'flex', flexDirection: 'column', width: '220px' }}>
@@ -444,6 +463,32 @@ const OptionPopover = forwardRef<HTMLDivElement, { timeline: Timeline; close: ()
           <Radio value="md">md</Radio>
           <Radio value="lg">lg</Radio>
         </RadioGroup>
+        <label style={{ padding: '8px 0' }}>
+          <FormattedMessage id="timeline.settings.notification_filter" />
+        </label>
+        <RadioGroup
+          value={props.timeline.notification_filter ?? 'all'}
+          onChange={value => updateNotificationFilter(props.timeline, value === 'all' ? null : (value as NotificationFilter))}
+        >
+          <Radio value="all">
+            <FormattedMessage id="timeline.notification_filter.all" />
+          </Radio>
+          <Radio value="mentions">
+            <FormattedMessage id="timeline.notification_filter.mentions" />
+          </Radio>
+          <Radio value="follows">
+            <FormattedMessage id="timeline.notification_filter.follows" />
+          </Radio>
+          <Radio value="reactions">
+            <FormattedMessage id="timeline.notification_filter.reactions" />
+          </Radio>
+          <Radio value="polls">
+            <FormattedMessage id="timeline.notification_filter.polls" />
+          </Radio>
+          <Radio value="admin">
+            <FormattedMessage id="timeline.notification_filter.admin" />
+          </Radio>
+        </RadioGroup>
         <Divider style={{ margin: '16px 0' }} />
         <div style={{ display: 'flex', justifyContent: 'space-between' }}>
           <div>
@@ -30,7 +30,8 @@ import {
   BsListUl,
   BsBookmark,
   BsArrowClockwise,
-  BsHash
+  BsHash,
+  BsPerson
 } from 'react-icons/bs'
 import { listen } from '@tauri-apps/api/event'
 import { Virtuoso } from 'react-virtuoso'
@@ -47,7 +48,9 @@ import {
   ReceiveHomeStatusPayload,
   ReceiveHomeStatusUpdatePayload,
   ReceiveTimelineStatusPayload,
-  ReceiveTimelineStatusUpdatePayload
+  ReceiveTimelineStatusUpdatePayload,
+  TimelineStatus,
+  TimelineStatusPayload
 } from 'src/payload'
 import { TIMELINE_STATUSES_COUNT, TIMELINE_MAX_STATUSES } from 'src/defaults'
 import alert from 'src/components/utils/alert'
@@ -83,6 +86,7 @@ export default function TimelineColumn(props: Props) {
   const [walkthrough, setWalkthrough] = useState<boolean>(false)
   const [customEmojis, setCustomEmojis] = useState<Array<CustomEmojiCategory>>([])
   const [filters, setFilters] = useState<Array<Entity.Filter>>([])
+  const [authRequired, setAuthRequired] = useState<boolean>(false)
 
   const scrollerRef = useRef<HTMLElement | null>(null)
   const triggerRef = useRef(null)
@@ -202,6 +206,20 @@ export default function TimelineColumn(props: Props) {
         setUnreadStatuses(last => deleteStatus(last, ev.payload.status_id))
         setStatuses(last => deleteStatus(last, ev.payload.status_id))
       })
+
+      listen<TimelineStatusPayload>('timeline-status', ev => {
+        if (ev.payload.timeline_id !== props.timeline.id || ev.payload.server_id !== props.server.id) {
+          return
+        }
+        setAuthRequired(ev.payload.status === 'auth-required')
+      })
+
+      // The status may be emitted before this column is mounted.
+      invoke<TimelineStatus | null>('get_timeline_status', { id: props.timeline.id }).then(status => {
+        if (status === 'auth-required') {
+          setAuthRequired(true)
+        }
+      })
     }
   }, [])
 
@@ -278,6 +296,20 @@ export default function TimelineColumn(props: Props) {
         const res = await client.getTagTimeline(tl.name, options)
         return res.data
       }
+      case 'account': {
+        if (!tl.remote_account_id) {
+          return []
+        }
+        const res = await client.getAccountStatuses(
+          tl.remote_account_id,
+          Object.assign({}, options, { exclude_replies: !tl.show_replies, exclude_reblogs: !tl.show_boosts })
+        )
+        if (maxId || !tl.show_pinned) {
+          return res.data
+        }
+        const pinned = await client.getAccountStatuses(tl.remote_account_id, { pinned: true })
+        return pinned.data.concat(res.data.filter(s => !pinned.data.some(p => p.id === s.id)))
+      }
       default:
     }
   }
@@ -313,6 +345,8 @@ export default function TimelineColumn(props: Props) {
         return <Icon as={BsBookmark} />
       case 'tag':
         return <Icon as={BsHash} />
+      case 'account':
+        return <Icon as={BsPerson} />
     }
   }
 
@@ -498,7 +532,11 @@ export default function TimelineColumn(props: Props) {
           </div>
         </Header>
 
-        {loading ? (
+        {authRequired ? (
+          <div style={{ padding: '2em 1em', textAlign: 'center' }}>
+            <FormattedMessage id="timeline.auth_required" values={{ domain: props.server.domain }} />
+          </div>
+        ) : loading ? (
           <Loader style={{ margin: '10em auto' }} />
         ) : (
           <Content style={{ height: 'calc(100% - 54px)' }}>
@@ -591,10 +629,14 @@ const OptionPopover = forwardRef<HTMLDivElement, { timeline: Timeline; close: ()
     await invoke('update_show_replies', { id: timeline.id, showReplies: showReplies })
   }
 
+  const updateShowPinned = async (timeline: Timeline, showPinned: boolean) => {
+    await invoke('update_show_pinned', { id: timeline.id, showPinned: showPinned })
+  }
+
   return (
     <Popover ref={ref} style={{ opacity: 1 }}>
       <div style={{ display: 'flex', flexDirection: 'column', width: '220px' }}>
-        {props.timeline.kind === 'home' && (
+        {(props.timeline.kind === 'home' || props.timeline.kind === 'account') && (
           <div style={{ display: 'flex', flexDirection: 'column', marginBottom: '16px', gap: '4px' }}>
             <Toggle size="sm" checked={props.timeline.show_boosts} onChange={value => updateShowBoosts(props.timeline, value)}>
               <FormattedMessage id="timeline.settings.show_boosts" />
@@ -602,6 +644,11 @@ const OptionPopover = forwardRef<HTMLDivElement, { timeline: Timeline; close: ()
             <Toggle size="sm" checked={props.timeline.show_replies} onChange={value => updateShowReplies(props.timeline, value)}>
               <FormattedMessage id="timeline.settings.show_replies" />
             </Toggle>
+            {props.timeline.kind === 'account' && (
+              <Toggle size="sm" checked={props.timeline.show_pinned} onChange={value => updateShowPinned(props.timeline, value)}>
+                <FormattedMessage id="timeline.settings.show_pinned" />
+              </Toggle>
+            )}
           </div>
         )}
 
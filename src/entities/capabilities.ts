@@ -0,0 +1,11 @@
+export type Capabilities = {
+  streaming: boolean
+  markers: boolean
+  quotes: boolean
+  reactions: boolean
+  edit: boolean
+  scheduled_posts: boolean
+  bookmarks: boolean
+  lists: boolean
+  translation: boolean
+}
@@ -7,6 +7,9 @@ export type Server = {
   sns: 'mastodon' | 'pleroma' | 'friendica' | 'firefish'
   favicon: string | null
   account_id: number | null
+  ca_certificate: string | null
+  software: string | null
+  software_version: string | null
 }
 
 export type ServerSet = {
@@ -0,0 +1,20 @@
+import { Entity } from 'megalodon'
+
+export type Limits = {
+  max_characters: number | null
+  max_media_attachments: number | null
+  characters_reserved_per_url: number | null
+  poll_max_options: number | null
+  poll_max_characters_per_option: number | null
+  poll_min_expiration: number | null
+  poll_max_expiration: number | null
+}
+
+export type ServerMetadata = {
+  server_id: number
+  instance: Entity.Instance
+  rules: Array<Entity.InstanceRule>
+  emojis: Array<Entity.Emoji>
+  limits: Limits
+  fetched_at: number
+}
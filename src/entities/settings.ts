@@ -1,18 +1,49 @@
 import { localeType } from 'src/i18n'
 
 export type Settings = {
+  version?: number
   appearance: {
     font_size: number
     font_family: string | null
     language: localeType
     color_theme: ThemeType
+    media_display?: MediaDisplay
   }
   behavior?: {
     confirm_reblog: boolean
+    default_visibility?: Visibility
+    default_language?: string | null
   }
   app_menu?: {
     hidden: boolean
   }
+  keybindings?: {
+    global: {
+      toggle_window: string | null
+      open_compose: string | null
+    }
+    app: AppKeybindings
+  }
+  proxy?: Proxy | null
+}
+
+export type AppKeybindings = {
+  compose: string
+  search: string
+  devtools: string
+}
+
+export type Proxy = {
+  protocol: 'http' | 'https' | 'socks5'
+  host: string
+  port: number
+  username: string | null
+  password: string | null
+  no_proxy: Array<string>
 }
 
 export type ThemeType = 'dark' | 'light' | 'high-contrast'
+
+export type MediaDisplay = 'default' | 'show_all' | 'hide_all'
+
+export type Visibility = 'public' | 'unlisted' | 'private' | 'direct'
@@ -8,10 +8,15 @@ export type Timeline = {
   column_width: ColumnWidth
   show_boosts: boolean
   show_replies: boolean
+  remote_account_id: string | null
+  remote_acct: string | null
+  show_pinned: boolean
+  notification_filter: NotificationFilter | null
 }
 
-export type TimelineKind = 'home' | 'notifications' | 'local' | 'public' | 'favourites' | 'list' | 'bookmarks' | 'direct' | 'tag'
+export type TimelineKind = 'home' | 'notifications' | 'local' | 'public' | 'favourites' | 'list' | 'bookmarks' | 'direct' | 'tag' | 'account'
 export type ColumnWidth = 'xs' | 'sm' | 'md' | 'lg'
+export type NotificationFilter = 'mentions' | 'follows' | 'reactions' | 'polls' | 'admin'
 
 export function columnWidth(width: ColumnWidth) {
   switch (width) {
@@ -16,10 +16,10 @@ import Navigator from 'src/components/Navigator'
 import Compose from 'src/components/compose/Compose'
 import Media from 'src/components/Media'
 import generateNotification from 'src/utils/notification'
-import { ReceiveNotificationPayload } from 'src/payload'
+import { GlobalShortcutPayload, RateLimitedPayload, ReceiveNotificationPayload } from 'src/payload'
 import { Entity, MegalodonInterface } from 'megalodon'
 import Thirdparty from 'src/components/settings/Thirdparty'
-import { Settings } from 'src/entities/settings'
+import { AppKeybindings, Settings } from 'src/entities/settings'
 import SettingsPage from 'src/components/settings/Settings'
 import Detail from 'src/components/detail/Detail'
 import { Account } from 'src/entities/account'
@@ -32,9 +32,17 @@ import { useIntl } from 'react-intl'
 import { Context } from 'src/i18n'
 import Search from 'src/components/search/Search'
 import { Behavior } from 'src/entities/behavior'
+import matchAccelerator from 'src/utils/accelerator'
 
 const { scrollLeft } = DOMHelper
 
+// The same as the defaults of the backend, used until the settings are loaded.
+const defaultAppKeybindings: AppKeybindings = {
+  compose: 'CmdOrCtrl+N',
+  search: 'CmdOrCtrl+K',
+  devtools: 'F12'
+}
+
 const defaultFontFaimly = [
   'Apple-System',
   'Hiragino Kaku Gothic ProN',
@@ -63,6 +71,8 @@ function App() {
 
   const [modalState, dispatch] = useReducer(modalReducer, initialModalState)
   const spaceRef = useRef<HTMLDivElement>(null)
+  // The key handler is registered once, so it reads the latest keybindings through this ref.
+  const keybindingsRef = useRef<AppKeybindings>(defaultAppKeybindings)
 
   const toaster = useToaster()
   const { switchLang } = useContext(Context)
@@ -75,6 +85,7 @@ function App() {
   useEffect(() => {
     loadAppearance()
     loadBehavior()
+    loadKeybindings()
     document.addEventListener('keydown', handleKeyPress)
 
     invoke<Array<[Server, Account | null]>>('list_servers').then(res => {
@@ -108,6 +119,28 @@ function App() {
       )
     })
 
+    listen('updated-settings', () => {
+      loadKeybindings()
+    })
+
+    listen<GlobalShortcutPayload>('global-shortcut', ev => {
+      if (ev.payload.action === 'open_compose') {
+        setSearchOpened(false)
+        setComposeOpened(true)
+      }
+    })
+
+    listen<RateLimitedPayload>('rate-limited', async ev => {
+      const server = await invoke<Server>('get_server', { id: ev.payload.server_id })
+      toaster.push(
+        alert(
+          'warning',
+          formatMessage({ id: 'alert.rate_limited' }, { domain: server.domain, time: dayjs(ev.payload.reset_at).format('HH:mm:ss') })
+        ),
+        { placement: 'topCenter' }
+      )
+    })
+
     listen<ReceiveNotificationPayload>('receive-notification', async ev => {
       const server_id = ev.payload.server_id
       setUnreads(current => {
@@ -160,8 +193,18 @@ function App() {
   }, [highlighted])
 
   const handleKeyPress = useCallback(async (event: KeyboardEvent) => {
-    if (event.key === 'F12') {
+    const keybindings = keybindingsRef.current
+    if (matchAccelerator(keybindings.devtools, event)) {
+      event.preventDefault()
       await invoke('switch_devtools')
+    } else if (matchAccelerator(keybindings.compose, event)) {
+      event.preventDefault()
+      setSearchOpened(false)
+      setComposeOpened(previous => !previous)
+    } else if (matchAccelerator(keybindings.search, event)) {
+      event.preventDefault()
+      setComposeOpened(false)
+      setSearchOpened(previous => !previous)
     }
   }, [])
 
@@ -187,6 +230,14 @@ function App() {
     })
   }
 
+  const loadKeybindings = () => {
+    invoke<Settings>('read_settings').then(res => {
+      if (res.keybindings) {
+        keybindingsRef.current = res.keybindings.app
+      }
+    })
+  }
+
   const toggleCompose = () => {
     if (servers.find(s => s.account !== null)) {
       setSearchOpened(false)
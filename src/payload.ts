@@ -4,6 +4,7 @@ import { localeType } from './i18n'
 
 export type ReceiveNotificationPayload = {
   server_id: number
+  timeline_ids: Array<number>
   notification: Entity.Notification
 }
 
@@ -56,3 +57,23 @@ export type UpdatedSettingsPayload = {
     color_theme: ThemeType
   }
 }
+
+export type RateLimitedPayload = {
+  server_id: number
+  account_id: number | null
+  limit: number
+  remaining: number
+  reset_at: string
+}
+
+export type TimelineStatus = 'auth-required'
+
+export type TimelineStatusPayload = {
+  server_id: number
+  timeline_id: number
+  status: TimelineStatus
+}
+
+export type GlobalShortcutPayload = {
+  action: 'toggle_window' | 'open_compose'
+}
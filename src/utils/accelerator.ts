@@ -0,0 +1,52 @@
+const isMac = navigator.userAgent.includes('Mac')
+
+// Returns whether the keyboard event is the accelerator, which is written in the same format as global shortcuts, such as CmdOrCtrl+N.
+const matchAccelerator = (accelerator: string, event: KeyboardEvent): boolean => {
+  const parts = accelerator.split('+').map(p => p.trim().toLowerCase())
+  const key = parts.pop()
+  let ctrl = false
+  let meta = false
+  let alt = false
+  let shift = false
+  for (const modifier of parts) {
+    switch (modifier) {
+      case 'cmdorctrl':
+      case 'cmdorcontrol':
+      case 'commandorctrl':
+      case 'commandorcontrol':
+        if (isMac) {
+          meta = true
+        } else {
+          ctrl = true
+        }
+        break
+      case 'ctrl':
+      case 'control':
+        ctrl = true
+        break
+      case 'cmd':
+      case 'command':
+      case 'super':
+      case 'meta':
+        meta = true
+        break
+      case 'alt':
+      case 'option':
+        alt = true
+        break
+      case 'shift':
+        shift = true
+        break
+      default:
+        return false
+    }
+  }
+  if (event.ctrlKey !== ctrl || event.metaKey !== meta || event.altKey !== alt || event.shiftKey !== shift) {
+    return false
+  }
+  // KeyboardEvent.code is like KeyN or Digit1, while accelerators may be written as N or 1.
+  const code = event.code.toLowerCase()
+  return code === key || code.replace(/^(key|digit)/, '') === key || event.key.toLowerCase() === key
+}
+
+export default matchAccelerator
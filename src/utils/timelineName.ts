@@ -1,15 +1,19 @@
 import { MessageDescriptor } from 'react-intl'
-import { TimelineKind } from 'src/entities/timeline'
+import { NotificationFilter, TimelineKind } from 'src/entities/timeline'
 
 const timelineName = (
   timelineKind: TimelineKind,
   name: string,
-  formatMessage: (descriptor: MessageDescriptor, values?: any, opt?: any) => string
+  formatMessage: (descriptor: MessageDescriptor, values?: any, opt?: any) => string,
+  notificationFilter?: NotificationFilter | null
 ) => {
   switch (timelineKind) {
     case 'home':
       return formatMessage({ id: 'timeline.home' })
     case 'notifications':
+      if (notificationFilter) {
+        return formatMessage({ id: `timeline.notification_filter.${notificationFilter}` })
+      }
       return formatMessage({ id: 'timeline.notifications' })
     case 'favourites':
       return formatMessage({ id: 'timeline.favourites' })